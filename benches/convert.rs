@@ -0,0 +1,43 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use qoi_rs::{Image, Pixel, PixelOrder, convert};
+
+fn convert_formats(c: &mut Criterion) {
+    let image = Image::from_fn(1920, 1080, |x, y| {
+        Pixel::new(x as u8, y as u8, (x ^ y) as u8, (x + y) as u8)
+    });
+    let mut packed = vec![0; image.pixels.len()];
+    let mut pixels = image.pixels.clone();
+
+    let mut group = c.benchmark_group("convert/1920x1080");
+    group.bench_function("to xrgb", |b| {
+        b.iter(|| convert::pixels_to_u32(black_box(&image.pixels), &mut packed, PixelOrder::Xrgb))
+    });
+    group.bench_function("to xrgb (per pixel)", |b| {
+        b.iter(|| {
+            for (out, pixel) in packed.iter_mut().zip(black_box(&image.pixels)) {
+                *out = pixel.to_u32(PixelOrder::Xrgb);
+            }
+        })
+    });
+    group.bench_function("from bgra", |b| {
+        b.iter(|| convert::u32_to_pixels(black_box(&packed), &mut pixels, PixelOrder::Bgra))
+    });
+    group.bench_function("premultiply", |b| {
+        b.iter(|| {
+            pixels.copy_from_slice(&image.pixels);
+            convert::premultiply(black_box(&mut pixels))
+        })
+    });
+    group.bench_function("unpremultiply", |b| {
+        b.iter(|| {
+            pixels.copy_from_slice(&image.pixels);
+            convert::unpremultiply(black_box(&mut pixels))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, convert_formats);
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use qoi_rs::{Decoder, Encoder, FrameDecoder, Image, Pixel};
+
+// A gradient with some noise, so every op kind shows up.
+fn frame(width: usize, height: usize) -> Vec<u8> {
+    let mut seed = 1u32;
+    let image = Image::from_fn(width, height, |x, y| {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        let noise = (seed >> 29) as u8;
+        Pixel::new(x as u8, y as u8, (x + y) as u8 ^ noise, 255)
+    });
+    Encoder::new(width as u32, height as u32)
+        .encode(&image.pixels)
+        .unwrap()
+}
+
+// A random walk per channel with steps too large for DIFF, so that like in
+// photographs most pixels are written as LUMA or RGB ops.
+fn photo(width: usize, height: usize) -> Vec<u8> {
+    let mut seed = 7u32;
+    let mut prev = [128u8; 3];
+    let image = Image::from_fn(width, height, |_, _| {
+        for channel in &mut prev {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            *channel = channel
+                .wrapping_add((seed >> 24) as u8 / 8)
+                .wrapping_sub(16);
+        }
+        Pixel::new(prev[0], prev[1], prev[2], 255)
+    });
+    Encoder::new(width as u32, height as u32)
+        .encode(&image.pixels)
+        .unwrap()
+}
+
+fn decode_photo(c: &mut Criterion) {
+    let data = photo(640, 480);
+    let mut decoder = Decoder::new();
+    c.bench_function("photo/640x480", |b| {
+        b.iter(|| decoder.decode(black_box(&data)).unwrap())
+    });
+    let mut out = vec![0; 640 * 480 * 4];
+    c.bench_function("photo/640x480 to rgba", |b| {
+        b.iter(|| decoder.decode_to_rgba(black_box(&data), &mut out).unwrap())
+    });
+    c.bench_function("photo/640x480 to rgb", |b| {
+        b.iter(|| decoder.decode_to_rgb(black_box(&data), &mut out).unwrap())
+    });
+}
+
+// Allocating a fresh image per frame against reusing one buffer for all of them.
+fn decode_frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frames");
+    for (width, height) in [(32, 32), (640, 480)] {
+        let data = frame(width, height);
+        let size = format!("{width}x{height}");
+        group.bench_with_input(BenchmarkId::new("decode", &size), &data, |b, data| {
+            let mut decoder = Decoder::new();
+            b.iter(|| decoder.decode(black_box(data)).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("decode_next", &size), &data, |b, data| {
+            let mut frames = FrameDecoder::new();
+            b.iter(|| frames.decode_next(black_box(data)).unwrap().pixels.len());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode_frames, decode_photo);
+criterion_main!(benches);
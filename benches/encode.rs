@@ -0,0 +1,34 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use qoi_rs::{Encoder, Image, Pixel};
+
+// Per-channel noise of growing amplitude, from mostly DIFF ops through LUMA to
+// mostly RGB, none of which the encoder can predict.
+fn noise(amplitude: u32) -> Image<Pixel> {
+    let mut seed = 3u32;
+    let mut prev = [128u8; 3];
+    Image::from_fn(640, 480, |_, _| {
+        for channel in &mut prev {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let step = ((seed >> 16) % (2 * amplitude + 1)) as u8;
+            *channel = channel.wrapping_add(step).wrapping_sub(amplitude as u8);
+        }
+        Pixel::new(prev[0], prev[1], prev[2], 255)
+    })
+}
+
+fn encode_noise(c: &mut Criterion) {
+    let mut group = c.benchmark_group("noise");
+    for amplitude in [2, 8, 32] {
+        let image = noise(amplitude);
+        let mut encoder = Encoder::new(640, 480);
+        group.bench_function(format!("amplitude {amplitude}"), |b| {
+            b.iter(|| encoder.encode(black_box(&image.pixels)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode_noise);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use qoi_rs::{Decoder, Limits};
+
+// Any input must come back as Ok or Err; a panic or abort is a bug.
+fuzz_target!(|data: &[u8]| {
+    let limits = Limits {
+        max_pixels: 1 << 24,
+        ..Limits::default()
+    };
+    let mut decoder = Decoder::new().with_limits(limits);
+    let _ = decoder.decode(data);
+    let _ = decoder.decode_region(data, 1, 1, 2, 2);
+    let _ = decoder.decode_subsampled(data, 4);
+    let mut out = vec![0; 64 * 64 * 4];
+    let _ = decoder.decode_to_rgba(data, &mut out);
+    let _ = decoder.decode_to_rgb(data, &mut out);
+});
@@ -0,0 +1,43 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use qoi_rs::{Decoder, Encoder, Pixel};
+
+// Runs the same input through qoi-rs and the reference qoi.h, once as pixels to
+// encode and once as a stream to decode, and panics on any divergence.
+fuzz_target!(|data: &[u8]| {
+    let Some((&width, rgba)) = data.split_first() else {
+        return;
+    };
+    let width = width as u32 + 1;
+    let height = (rgba.len() / 4) as u32 / width;
+    if height > 0 {
+        let rgba = &rgba[..(width * height * 4) as usize];
+        let pixels = rgba
+            .chunks_exact(4)
+            .map(|p| Pixel::new(p[0], p[1], p[2], p[3]))
+            .collect::<Vec<_>>();
+        let desc = qoi_sys::Desc {
+            width,
+            height,
+            channels: 4,
+            colorspace: 0,
+        };
+        let encoded = Encoder::new(width, height).encode(&pixels).unwrap();
+        let reference = qoi_sys::encode(rgba, desc).unwrap();
+        assert_eq!(encoded, reference, "encoded streams differ");
+        assert_eq!(qoi_sys::decode(&encoded, 4).unwrap().1, rgba);
+    }
+
+    if let Ok(decoded) = Decoder::new().decode(data) {
+        if decoded.pixels.is_empty() {
+            return;
+        }
+        let (_, reference) = qoi_sys::decode(data, 4).expect("qoi.h rejected a valid stream");
+        assert_eq!(
+            decoded.into_rgba_bytes(),
+            reference,
+            "decoded pixels differ"
+        );
+    }
+});
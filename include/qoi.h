@@ -0,0 +1,63 @@
+/* Generated by cbindgen from src/ffi.rs, do not edit. Mirrors the API of the reference qoi.h. */
+
+#ifndef QOI_H
+#define QOI_H
+
+#include <stdint.h>
+
+#define QOI_SRGB 0
+
+#define QOI_LINEAR 1
+
+#define QOI_OK 0
+
+#define QOI_ERROR_NULL_POINTER -1
+
+#define QOI_ERROR_INVALID_DATA -2
+
+#define QOI_ERROR_BUFFER_TOO_SMALL -3
+
+typedef struct qoi_desc {
+  uint32_t width;
+  uint32_t height;
+  uint8_t channels;
+  uint8_t colorspace;
+} qoi_desc;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Encodes `desc.width * desc.height` pixels of `desc.channels` bytes each. Returns
+// the encoded bytes and stores their length in `out_len`, or NULL on failure.
+void *qoi_encode(const void *data, const struct qoi_desc *desc, int *out_len);
+
+// Decodes `size` bytes of QOI data into pixels of `channels` bytes each (0 keeps the
+// file's channel count), filling in `desc` from the header. Returns NULL on failure.
+void *qoi_decode(const void *data, int size, struct qoi_desc *desc, int channels);
+
+// Decodes `in_len` bytes of QOI data as RGBA into the caller's `out` buffer of
+// `out_cap` bytes, without allocating. `out_desc` is filled in from the header
+// whenever the header is valid, so calling with a NULL `out` and 0 capacity
+// returns `QOI_ERROR_BUFFER_TOO_SMALL` along with the size to allocate.
+int qoi_decode_to_rgba(const void *data,
+                       uintptr_t in_len,
+                       void *out,
+                       uintptr_t out_cap,
+                       struct qoi_desc *out_desc);
+
+// Encodes the pixels and writes them to `filename`. Returns the number of bytes
+// written, or 0 on failure.
+int qoi_write(const char *filename, const void *data, const struct qoi_desc *desc);
+
+// Reads and decodes `filename` like `qoi_decode`. Returns NULL on failure.
+void *qoi_read(const char *filename, struct qoi_desc *desc, int channels);
+
+// Releases a buffer returned by any of the functions above.
+void qoi_free(void *data);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* QOI_H */
@@ -0,0 +1,135 @@
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use qoi_rs::{Decoder, Encoder, Image, Pixel};
+
+use crate::{extension, load};
+
+// Each file is encoded and decoded over and over for at least this long, and
+// the mean time of one round is reported.
+const MIN_TIME: Duration = Duration::from_millis(200);
+
+#[derive(Default)]
+struct Timing {
+    pixels: usize,
+    qoi_bytes: usize,
+    encode: Duration,
+    decode: Duration,
+}
+
+// Walks `dir` for .qoi and .png files and prints their encode and decode
+// throughput. MB/s is measured on the QOI data, so it is the rate at which
+// files are written and read.
+pub fn run(dir: &Path) -> Result<(), String> {
+    let mut files = Vec::new();
+    collect(dir, &mut files)?;
+    files.sort();
+    if files.is_empty() {
+        return Err(format!("{}: no .qoi or .png files found", dir.display()));
+    }
+
+    println!(
+        "{:<40} {:>11} {:>9} {:>9} {:>9} {:>9} {:>9}",
+        "file", "size", "qoi kB", "enc MP/s", "enc MB/s", "dec MP/s", "dec MB/s"
+    );
+    let mut total = Timing::default();
+    for path in &files {
+        let image = load(path)?;
+        let timing = measure(&image).map_err(|err| format!("{}: {err}", path.display()))?;
+        let name = path.strip_prefix(dir).unwrap_or(path).display().to_string();
+        let size = format!("{}x{}", image.width, image.height);
+        print_row(&name, &size, &timing);
+
+        total.pixels += timing.pixels;
+        total.qoi_bytes += timing.qoi_bytes;
+        total.encode += timing.encode;
+        total.decode += timing.decode;
+    }
+    print_row(&format!("total ({} files)", files.len()), "", &total);
+    Ok(())
+}
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    for entry in entries {
+        let path = entry.map_err(|err| err.to_string())?.path();
+        if path.is_dir() {
+            collect(&path, files)?;
+        } else if matches!(extension(&path).as_deref(), Some("qoi" | "png")) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn measure(image: &Image<Pixel>) -> Result<Timing, String> {
+    let mut encoder = Encoder::new(image.width as u32, image.height as u32);
+    let data = encoder
+        .encode(&image.pixels)
+        .map_err(|err| err.to_string())?;
+    let mut decoder = Decoder::new();
+    decoder.decode(&data).map_err(|err| err.to_string())?;
+
+    Ok(Timing {
+        pixels: image.pixels.len(),
+        qoi_bytes: data.len(),
+        encode: mean_time(|| {
+            black_box(encoder.encode(black_box(&image.pixels)).ok());
+        }),
+        decode: mean_time(|| {
+            black_box(decoder.decode(black_box(&data)).ok());
+        }),
+    })
+}
+
+fn mean_time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while runs == 0 || start.elapsed() < MIN_TIME {
+        f();
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+fn print_row(name: &str, size: &str, timing: &Timing) {
+    let rate = |amount: usize, time: Duration| amount as f64 / time.as_secs_f64() / 1e6;
+    println!(
+        "{name:<40} {size:>11} {:>9.1} {:>9.1} {:>9.1} {:>9.1} {:>9.1}",
+        timing.qoi_bytes as f64 / 1e3,
+        rate(timing.pixels, timing.encode),
+        rate(timing.qoi_bytes, timing.encode),
+        rate(timing.pixels, timing.decode),
+        rate(timing.qoi_bytes, timing.decode),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corpus() {
+        let dir = std::env::temp_dir().join("qoi-cli-bench");
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        let gradient =
+            || Image::from_fn(8, 4, |x, y| Pixel::new(x as u8 * 30, y as u8 * 60, 0, 255));
+        let image = gradient();
+        let data = Encoder::new(8, 4).encode(&image.pixels).unwrap();
+        std::fs::write(dir.join("a.qoi"), &data).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        image::RgbaImage::from(gradient())
+            .save(dir.join("nested/b.png"))
+            .unwrap();
+
+        let mut files = Vec::new();
+        collect(&dir, &mut files).unwrap();
+        files.sort();
+        assert_eq!(files, [dir.join("a.qoi"), dir.join("nested/b.png")]);
+        for path in &files {
+            assert!(load(path).unwrap().pixels == image.pixels);
+        }
+        assert!(run(&dir).is_ok());
+    }
+}
@@ -0,0 +1,199 @@
+//! `qoi-capture`, which grabs the screen (or part of it) as QOI, or as an
+//! animation when capturing at a frame rate. Built with the `capture` feature.
+//!
+//! Screenshots are what QOI was made for, so this doubles as a check on how
+//! fast the encoder keeps up with a live screen; the encode time per frame is
+//! printed at the end.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::{Duration, Instant};
+
+use qoi_rs::anim::AnimWriter;
+use qoi_rs::{Encoder, Image, Pixel};
+use xcap::Monitor;
+
+const USAGE: &str = "usage: qoi-capture [--monitor N] [--region X,Y,WxH] \
+                     [--fps RATE --frames N] <output.qoi|output.qoan>";
+
+#[derive(Clone, Copy)]
+struct Region {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    if let Err(err) = run(&args) {
+        eprintln!("qoi-capture: {err}");
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[&str]) -> Result<(), String> {
+    let mut monitor: Option<usize> = None;
+    let mut region = None;
+    let mut fps = None;
+    let mut frames = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        let mut value = |what: &str| args.next().copied().ok_or(format!("{arg} needs {what}"));
+        match arg {
+            "--monitor" => {
+                let index = value("an index")?;
+                monitor = Some(
+                    index
+                        .parse()
+                        .map_err(|_| format!("bad --monitor {index}"))?,
+                );
+            }
+            "--region" => {
+                let spec = value("X,Y,WxH")?;
+                region = Some(parse_region(spec).ok_or(format!("bad --region {spec}"))?);
+            }
+            "--fps" => {
+                let rate = value("a rate")?;
+                fps = Some(
+                    rate.parse::<f64>()
+                        .ok()
+                        .filter(|&fps| fps > 0.0)
+                        .ok_or(format!("bad --fps {rate}"))?,
+                );
+            }
+            "--frames" => {
+                let count = value("a count")?;
+                frames = Some(count.parse().map_err(|_| format!("bad --frames {count}"))?);
+            }
+            _ if output.is_none() && !arg.starts_with("--") => output = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let output = output.ok_or(USAGE)?;
+
+    let monitors = Monitor::all().map_err(|err| err.to_string())?;
+    let monitor = match monitor {
+        Some(index) => monitors.get(index),
+        None => monitors
+            .iter()
+            .find(|monitor| monitor.is_primary().unwrap_or(false))
+            .or(monitors.first()),
+    };
+    let monitor = monitor.ok_or("no such monitor")?;
+
+    match (fps, frames) {
+        (None, None) => {
+            let image = grab(monitor, region)?;
+            let data = encode(&image)?;
+            std::fs::write(output, data).map_err(|err| format!("{output}: {err}"))
+        }
+        (Some(fps), Some(frames)) => record(monitor, region, fps, frames, output),
+        _ => Err("--fps and --frames go together".into()),
+    }
+}
+
+// Captures `frames` frames at `fps` into an animation. Each frame lasts until
+// the next one was captured, so the timing stays true when capturing and
+// encoding cannot keep up.
+fn record(
+    monitor: &Monitor,
+    region: Option<Region>,
+    fps: f64,
+    frames: u32,
+    output: &str,
+) -> Result<(), String> {
+    let interval = Duration::from_secs_f64(1.0 / fps);
+    let first = grab(monitor, region)?;
+    let file = File::create(output).map_err(|err| format!("{output}: {err}"))?;
+    let mut writer = AnimWriter::new(
+        BufWriter::new(file),
+        first.width as u32,
+        first.height as u32,
+        0,
+    )
+    .map_err(|err| err.to_string())?;
+
+    let start = Instant::now();
+    let mut encoding = Duration::ZERO;
+    let mut pending: Option<(Vec<u8>, Instant)> = None;
+    let mut image = first;
+    for i in 0..frames {
+        if i > 0 {
+            let next = start + interval * i;
+            std::thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+        let captured = Instant::now();
+        if i > 0 {
+            image = grab(monitor, region)?;
+        }
+        let encode_start = Instant::now();
+        let data = encode(&image)?;
+        encoding += encode_start.elapsed();
+
+        if let Some((data, at)) = pending.replace((data, captured)) {
+            let duration = (captured - at).as_millis() as u32;
+            writer
+                .add_encoded(&data, duration)
+                .map_err(|err| format!("{output}: {err}"))?;
+        }
+    }
+    if let Some((data, _)) = pending {
+        let duration = interval.as_millis() as u32;
+        writer
+            .add_encoded(&data, duration)
+            .map_err(|err| format!("{output}: {err}"))?;
+    }
+    writer.finish().map_err(|err| format!("{output}: {err}"))?;
+
+    if frames > 0 {
+        let per_frame = encoding / frames;
+        let megapixels = (image.width * image.height) as f64 / 1e6;
+        println!(
+            "{frames} frames of {}x{} in {:.1}s, encoding took {:.2} ms per frame ({:.0} MP/s)",
+            image.width,
+            image.height,
+            start.elapsed().as_secs_f64(),
+            per_frame.as_secs_f64() * 1e3,
+            megapixels / per_frame.as_secs_f64().max(1e-9)
+        );
+    }
+    Ok(())
+}
+
+fn grab(monitor: &Monitor, region: Option<Region>) -> Result<Image<Pixel>, String> {
+    let image = match region {
+        Some(Region {
+            x,
+            y,
+            width,
+            height,
+        }) => monitor.capture_region(x, y, width, height),
+        None => monitor.capture_image(),
+    };
+    Ok(image.map_err(|err| err.to_string())?.into())
+}
+
+// screens are opaque, whatever the capture API says about alpha
+fn encode(image: &Image<Pixel>) -> Result<Vec<u8>, String> {
+    Encoder::new(image.width as u32, image.height as u32)
+        .force_opaque(true)
+        .encode(&image.pixels)
+        .map_err(|err| err.to_string())
+}
+
+// `X,Y,WxH`, relative to the monitor
+fn parse_region(spec: &str) -> Option<Region> {
+    let (x, rest) = spec.split_once(',')?;
+    let (y, size) = rest.split_once(',')?;
+    let (width, height) = size.split_once('x')?;
+    let region = Region {
+        x: x.parse().ok()?,
+        y: y.parse().ok()?,
+        width: width.parse().ok()?,
+        height: height.parse().ok()?,
+    };
+    (region.width > 0 && region.height > 0).then_some(region)
+}
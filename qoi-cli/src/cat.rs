@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+
+use qoi_rs::Decoder;
+use qoi_rs::anim::AnimWriter;
+
+const USAGE: &str = "usage: qoi cat [--duration MS] [--loop N] <frames.qoi...> <output.qoan>";
+
+// `qoi cat [--duration MS] [--loop N] <frames.qoi...> <output.qoan>`
+//
+// Packs QOI stills into an animation in the order given, each shown for
+// `--duration` milliseconds (100 by default). Frames are copied as they are,
+// after checking that they decode and all have the same dimensions.
+pub fn run(args: &[&str]) -> Result<(), String> {
+    let mut duration_ms = 100;
+    let mut loop_count = 0;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--duration" => {
+                let value = args.next().ok_or("--duration needs a value in ms")?;
+                duration_ms = value
+                    .parse()
+                    .map_err(|_| format!("bad --duration {value}"))?;
+            }
+            "--loop" => {
+                let value = args.next().ok_or("--loop needs a count, 0 for forever")?;
+                loop_count = value.parse().map_err(|_| format!("bad --loop {value}"))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unexpected argument {arg}")),
+            _ => paths.push(arg),
+        }
+    }
+    let Some((output, frames)) = paths.split_last().filter(|(_, frames)| !frames.is_empty()) else {
+        return Err(USAGE.into());
+    };
+
+    let mut files = Vec::new();
+    for pattern in frames {
+        files.extend(expand(pattern)?);
+    }
+    let data = pack(&files, duration_ms, loop_count)?;
+    std::fs::write(output, data).map_err(|err| format!("{output}: {err}"))?;
+    println!("packed {} frames into {output}", files.len());
+    Ok(())
+}
+
+// Builds the animation from the QOI files at `paths`.
+fn pack(paths: &[PathBuf], duration_ms: u32, loop_count: u32) -> Result<Vec<u8>, String> {
+    let mut frames = Vec::with_capacity(paths.len());
+    let mut size = None;
+    for path in paths {
+        let error = |err: &dyn std::fmt::Display| format!("{}: {err}", path.display());
+        let data = std::fs::read(path).map_err(|err| error(&err))?;
+        let image = Decoder::new().decode(&data).map_err(|err| error(&err))?;
+        let (width, height) = *size.get_or_insert((image.width, image.height));
+        if (image.width, image.height) != (width, height) {
+            return Err(error(&format!(
+                "{}x{}, but the first frame is {width}x{height}",
+                image.width, image.height
+            )));
+        }
+        frames.push(data);
+    }
+    let (width, height) = size.ok_or("no frames")?;
+
+    let mut writer = AnimWriter::new(Vec::new(), width as u32, height as u32, loop_count)
+        .map_err(|err| err.to_string())?;
+    for data in &frames {
+        writer
+            .add_encoded(data, duration_ms)
+            .map_err(|err| err.to_string())?;
+    }
+    writer.finish().map_err(|err| err.to_string())
+}
+
+// Expands `*` and `?` in the file name of `pattern`, sorted by name, for shells
+// that pass patterns through unexpanded. Other arguments are kept as they are.
+pub fn expand(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let path = Path::new(pattern);
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    if !name.contains(['*', '?']) {
+        return Ok(vec![path.into()]);
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let entries = std::fs::read_dir(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|err| err.to_string())?;
+        if let Some(file) = entry.file_name().to_str()
+            && matches(name.as_bytes(), file.as_bytes())
+        {
+            paths.push(dir.join(file));
+        }
+    }
+    if paths.is_empty() {
+        return Err(format!("{pattern}: no matching files"));
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((b'*', rest)), _) => {
+            matches(rest, name) || (!name.is_empty() && matches(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, tail))) => matches(rest, tail),
+        (Some((byte, rest)), Some((other, tail))) => byte == other && matches(rest, tail),
+        (Some(_), None) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::anim::AnimReader;
+    use qoi_rs::{Encoder, Image, Pixel};
+
+    use super::*;
+
+    #[test]
+    fn packs_frames() {
+        let dir = std::env::temp_dir().join("qoi-cli-cat");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut frames = Vec::new();
+        for i in 0..3u8 {
+            let image = Image::from_fn(6, 4, |x, y| Pixel::new(x as u8 * i, y as u8, i, 255));
+            let data = Encoder::new(6, 4).encode(&image.pixels).unwrap();
+            std::fs::write(dir.join(format!("frame_{i:04}.qoi")), &data).unwrap();
+            frames.push(data);
+        }
+        let big = Encoder::new(8, 4)
+            .encode(&[Pixel::new(0, 0, 0, 255); 32])
+            .unwrap();
+        std::fs::write(dir.join("big.qoi"), big).unwrap();
+
+        let pattern = dir.join("frame_*.qoi");
+        let paths = expand(pattern.to_str().unwrap()).unwrap();
+        assert_eq!(paths.len(), 3);
+        let anim = pack(&paths, 40, 2).unwrap();
+        let reader = AnimReader::new(&anim).unwrap();
+        assert_eq!((reader.width, reader.height, reader.loop_count), (6, 4, 2));
+        assert_eq!(reader.len(), 3);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(reader.get(i), Some(frame.as_slice()));
+            assert_eq!(reader.duration_ms(i), Some(40));
+        }
+
+        let mut paths = paths;
+        paths.push(dir.join("big.qoi"));
+        let err = pack(&paths, 40, 0).unwrap_err();
+        assert!(err.ends_with("8x4, but the first frame is 6x4"), "{err}");
+        assert!(pack(&[], 40, 0).is_err());
+        assert!(expand(dir.join("*.png").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches(b"frame_*.qoi", b"frame_0001.qoi"));
+        assert!(matches(b"*", b""));
+        assert!(matches(b"f?_*a*", b"fx_bab"));
+        assert!(!matches(b"frame_*.qoi", b"frame_0001.png"));
+        assert!(!matches(b"?", b""));
+    }
+}
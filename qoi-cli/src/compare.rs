@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use qoi_rs::{Image, Pixel};
+
+use crate::load;
+
+#[derive(Debug, PartialEq)]
+struct Report {
+    pixels: usize,
+    differing: usize,
+    // mean squared error of each of r, g, b and a
+    mse: [f64; 4],
+}
+
+impl Report {
+    fn new(a: &Image<Pixel>, b: &Image<Pixel>) -> Self {
+        let mut differing = 0;
+        let mut squared = [0u64; 4];
+        for (pa, pb) in a.pixels.iter().zip(&b.pixels) {
+            differing += (pa != pb) as usize;
+            for (sum, (ca, cb)) in squared
+                .iter_mut()
+                .zip(pa.to_bytes().into_iter().zip(pb.to_bytes()))
+            {
+                *sum += (ca.abs_diff(cb) as u64).pow(2);
+            }
+        }
+        Report {
+            pixels: a.pixels.len(),
+            differing,
+            mse: squared.map(|sum| sum as f64 / a.pixels.len().max(1) as f64),
+        }
+    }
+
+    // over all four channels; infinite for identical images
+    fn psnr(&self) -> f64 {
+        let mse = self.mse.iter().sum::<f64>() / 4.0;
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+// Prints how much the images differ and fails unless they are identical, so
+// scripts can check the exit code.
+pub fn run(a: &Path, b: &Path) -> Result<(), String> {
+    let (image_a, image_b) = (load(a)?, load(b)?);
+    let mismatch = || format!("{} and {} differ", a.display(), b.display());
+    if (image_a.width, image_a.height) != (image_b.width, image_b.height) {
+        println!("identical: no");
+        println!(
+            "dimensions: {}x{} vs {}x{}",
+            image_a.width, image_a.height, image_b.width, image_b.height
+        );
+        return Err(mismatch());
+    }
+
+    let report = Report::new(&image_a, &image_b);
+    let [r, g, b, alpha] = report.mse;
+    println!(
+        "identical: {}",
+        if report.differing == 0 { "yes" } else { "no" }
+    );
+    println!(
+        "differing pixels: {} of {} ({:.2}%)",
+        report.differing,
+        report.pixels,
+        100.0 * report.differing as f64 / report.pixels.max(1) as f64
+    );
+    println!("psnr: {:.2} dB", report.psnr());
+    println!("mse: r {r:.4} g {g:.4} b {b:.4} a {alpha:.4}");
+    match report.differing {
+        0 => Ok(()),
+        _ => Err(mismatch()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::Encoder;
+
+    use super::*;
+
+    #[test]
+    fn report() {
+        let a = Image::from_fn(4, 4, |x, y| Pixel::new(x as u8 * 60, y as u8 * 60, 0, 255));
+        let mut b = Image::from_fn(4, 4, |x, y| Pixel::new(x as u8 * 60, y as u8 * 60, 0, 255));
+        let same = Report::new(&a, &b);
+        assert_eq!(same.differing, 0);
+        assert_eq!(same.mse, [0.0; 4]);
+        assert_eq!(same.psnr(), f64::INFINITY);
+
+        b[(1, 2)].r += 4;
+        b[(3, 3)].a = 251;
+        let report = Report::new(&a, &b);
+        assert_eq!(
+            report,
+            Report {
+                pixels: 16,
+                differing: 2,
+                mse: [1.0, 0.0, 0.0, 1.0]
+            }
+        );
+        assert!((report.psnr() - 51.14).abs() < 0.01);
+
+        let dir = std::env::temp_dir().join("qoi-cli-compare");
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, image: &Image<Pixel>| {
+            let data = Encoder::new(4, 4).encode(&image.pixels).unwrap();
+            std::fs::write(dir.join(name), data).unwrap();
+            dir.join(name)
+        };
+        let (path_a, path_b) = (write("a.qoi", &a), write("b.qoi", &b));
+        image::RgbaImage::from(a).save(dir.join("a.png")).unwrap();
+        assert!(run(&path_a, &dir.join("a.png")).is_ok());
+        assert!(run(&path_a, &path_b).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use gif::{ColorOutput, DecodeOptions, DisposalMethod, Repeat};
+use qoi_rs::anim::AnimWriter;
+use qoi_rs::{Image, Pixel};
+
+const TRANSPARENT: Pixel = Pixel {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0,
+};
+
+// GIF frames only cover part of the screen and say what to do with that area
+// afterwards, so they are composited onto a full-size canvas before encoding.
+pub fn convert(input: &Path, output: &Path) -> Result<(), String> {
+    let file = File::open(input).map_err(|err| format!("{}: {err}", input.display()))?;
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    let mut decoder = options
+        .read_info(file)
+        .map_err(|err| format!("{}: {err}", input.display()))?;
+
+    let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+    let loop_count = match decoder.repeat() {
+        Repeat::Infinite => 0,
+        Repeat::Finite(repeats) => repeats as u32 + 1,
+    };
+
+    let out = File::create(output).map_err(|err| format!("{}: {err}", output.display()))?;
+    let mut writer = AnimWriter::new(BufWriter::new(out), width as u32, height as u32, loop_count)
+        .map_err(|err| err.to_string())?;
+
+    let mut canvas = Image {
+        width,
+        height,
+        pixels: vec![TRANSPARENT; width * height],
+    };
+    let mut frames = 0;
+    while let Some(frame) = decoder.read_next_frame().map_err(|err| err.to_string())? {
+        let saved = (frame.dispose == DisposalMethod::Previous).then(|| canvas.pixels.clone());
+
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        let (frame_width, frame_height) = (frame.width as usize, frame.height as usize);
+        for (y, row) in frame.buffer.chunks_exact(4 * frame_width).enumerate() {
+            for (x, rgba) in row.chunks_exact(4).enumerate() {
+                let (cx, cy) = (left + x, top + y);
+                if rgba[3] == 0 || cx >= width || cy >= height {
+                    continue;
+                }
+                canvas.pixels[cy * width + cx] = Pixel::new(rgba[0], rgba[1], rgba[2], rgba[3]);
+            }
+        }
+
+        // GIF delays are in hundredths of a second
+        writer
+            .add_frame(&canvas, frame.delay as u32 * 10)
+            .map_err(|err| err.to_string())?;
+        frames += 1;
+
+        match frame.dispose {
+            DisposalMethod::Background => {
+                for y in top..(top + frame_height).min(height) {
+                    let row = &mut canvas.pixels[y * width..(y + 1) * width];
+                    let end = (left + frame_width).min(width);
+                    row[left.min(end)..end].fill(TRANSPARENT);
+                }
+            }
+            DisposalMethod::Previous => canvas.pixels = saved.unwrap_or_default(),
+            DisposalMethod::Any | DisposalMethod::Keep => {}
+        }
+    }
+
+    writer.finish().map_err(|err| err.to_string())?;
+    println!("wrote {frames} frames to {}", output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use gif::{Encoder, Frame};
+    use qoi_rs::anim::AnimReader;
+
+    use super::*;
+
+    #[test]
+    fn disposal() {
+        let dir = std::env::temp_dir();
+        let (input, output) = (dir.join("qoi-cli-test.gif"), dir.join("qoi-cli-test.qoan"));
+
+        // a red 4x2 frame, then a green 2x1 patch that is cleared afterwards,
+        // then a blue 1x1 patch drawn over whatever is left
+        let palette = [0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let mut encoder = Encoder::new(File::create(&input).unwrap(), 4, 2, &palette).unwrap();
+        encoder.set_repeat(Repeat::Infinite).unwrap();
+        let patches = [
+            (0, 0, 4, 2, 1, DisposalMethod::Keep),
+            (1, 0, 2, 1, 2, DisposalMethod::Background),
+            (3, 1, 1, 1, 3, DisposalMethod::Keep),
+        ];
+        for (left, top, width, height, color, dispose) in patches {
+            let mut frame = Frame::from_indexed_pixels(
+                width,
+                height,
+                vec![color; (width * height) as usize],
+                None,
+            );
+            (frame.left, frame.top, frame.dispose, frame.delay) = (left, top, dispose, 5);
+            encoder.write_frame(&frame).unwrap();
+        }
+        drop(encoder);
+
+        convert(&input, &output).unwrap();
+        let data = std::fs::read(&output).unwrap();
+        let anim = AnimReader::new(&data).unwrap();
+        assert_eq!(
+            (anim.width, anim.height, anim.loop_count, anim.len()),
+            (4, 2, 0, 3)
+        );
+        assert_eq!(anim.duration_ms(0), Some(50));
+
+        let [red, green, blue] =
+            [(255, 0, 0), (0, 255, 0), (0, 0, 255)].map(|(r, g, b)| Pixel::new(r, g, b, 255));
+        let second = anim.decode(1).unwrap();
+        assert!(second.pixels[..4] == [red, green, green, red]);
+        let third = anim.decode(2).unwrap();
+        assert!(third.pixels == [red, TRANSPARENT, TRANSPARENT, red, red, red, red, blue]);
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+
+use qoi_rs::seek::SeekIndex;
+
+// rows between checkpoints unless --rows is given
+const DEFAULT_ROWS: u32 = 64;
+
+// `qoi index [--rows N] <file.qoi> [index.qix]`, next to the file by default.
+pub fn run(args: &[&str]) -> Result<(), String> {
+    let mut rows = DEFAULT_ROWS;
+    let mut paths = vec![];
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--rows" => {
+                let value = args.next().ok_or("--rows needs a number")?;
+                rows = value
+                    .parse()
+                    .ok()
+                    .filter(|&rows| rows > 0)
+                    .ok_or("--rows needs a positive number")?;
+            }
+            _ if !arg.starts_with("--") => paths.push(arg),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+    let (input, output) = match paths.as_slice() {
+        [input] => (Path::new(input), Path::new(input).with_extension("qix")),
+        [input, output] => (Path::new(input), PathBuf::from(output)),
+        _ => return Err("usage: qoi index [--rows N] <file.qoi> [index.qix]".into()),
+    };
+
+    let data = std::fs::read(input).map_err(|err| format!("{}: {err}", input.display()))?;
+    let index =
+        SeekIndex::build(&data, rows).map_err(|err| format!("{}: {err}", input.display()))?;
+    let bytes = index.to_bytes();
+    std::fs::write(&output, &bytes).map_err(|err| format!("{}: {err}", output.display()))?;
+    println!(
+        "{}: {} checkpoints every {rows} rows, {} bytes",
+        output.display(),
+        index.checkpoints.len(),
+        bytes.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::{Decoder, Encoder, Image, Pixel};
+
+    use super::*;
+
+    #[test]
+    fn writes_sidecar() {
+        let dir = std::env::temp_dir().join(format!("qoi-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = Image::from_fn(30, 200, |x, y| Pixel::new(x as u8, y as u8, 0, 255));
+        let data = Encoder::new(30, 200).encode(&image.pixels).unwrap();
+        let input = dir.join("tall.qoi");
+        std::fs::write(&input, &data).unwrap();
+
+        run(&[input.to_str().unwrap(), "--rows", "50"]).unwrap();
+        let bytes = std::fs::read(dir.join("tall.qix")).unwrap();
+        let index = SeekIndex::from_bytes(&bytes).unwrap();
+        assert_eq!((index.rows, index.checkpoints.len()), (50, 4));
+        let rows = Decoder::new().decode_rows(&data, &index, 120..130).unwrap();
+        assert!(rows.pixels == image.pixels[120 * 30..130 * 30]);
+
+        assert!(run(&[input.to_str().unwrap(), "--rows", "0"]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
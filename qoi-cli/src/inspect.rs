@@ -0,0 +1,211 @@
+use std::fmt::Write;
+use std::ops::Range;
+use std::path::Path;
+
+use qoi_rs::Decoder;
+use qoi_rs::ops::{Op, Ops, heatmap};
+
+use crate::{parse_range, save};
+
+// `qoi inspect [--ops] [--range FROM..TO] [--stats] [--heatmap out.png] <file.qoi>`
+pub fn run(args: &[&str]) -> Result<(), String> {
+    let mut show_ops = false;
+    let mut show_stats = false;
+    let mut heatmap_path = None;
+    let mut range = 0..usize::MAX;
+    let mut input = None;
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--ops" => show_ops = true,
+            "--stats" => show_stats = true,
+            "--heatmap" => heatmap_path = Some(*args.next().ok_or("--heatmap needs a file")?),
+            "--range" => {
+                let spec = args.next().ok_or("--range needs FROM..TO")?;
+                range = parse_range(spec).ok_or("bad --range, expected FROM..TO or FROM..")?;
+            }
+            _ if input.is_none() && !arg.starts_with("--") => input = Some(arg),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+    let input = Path::new(input.ok_or("missing input file")?);
+    let data = std::fs::read(input).map_err(|err| format!("{}: {err}", input.display()))?;
+    let mut report =
+        inspect(&data, show_ops, range).map_err(|err| format!("{}: {err}", input.display()))?;
+    if show_stats {
+        report += &stats(&data);
+    }
+    print!("{report}");
+    if let Some(path) = heatmap_path {
+        let image = heatmap(&data).map_err(|err| format!("{}: {err}", input.display()))?;
+        save(image, path.as_ref())?;
+    }
+    Ok(())
+}
+
+// Describes the header, the op counts and the end of the op stream, and with
+// `show_ops` every op that produces a pixel within `range`.
+fn inspect(data: &[u8], show_ops: bool, range: Range<usize>) -> Result<String, String> {
+    let mut ops = Ops::new(data).map_err(|err| err.to_string())?;
+    let header = ops.header();
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{}x{}, {} channels, colorspace {}, {} bytes",
+        header.width,
+        header.height,
+        header.channels,
+        header.colorspace,
+        data.len()
+    );
+    if show_ops {
+        let _ = writeln!(
+            out,
+            "{:>10} {:>10}  {:<5}  {:<26} pixel",
+            "offset", "index", "op", "operands"
+        );
+    }
+
+    let mut counts = [0usize; 6];
+    let mut error = None;
+    for info in ops.by_ref() {
+        let info = match info {
+            Ok(info) => info,
+            Err(err) => {
+                error = Some(err);
+                break;
+            }
+        };
+        let (kind, operands) = describe(info.op);
+        counts[kind] += 1;
+        let end = info.index + info.op.pixels();
+        if show_ops && info.index < range.end && end > range.start {
+            let _ = writeln!(
+                out,
+                "{:>10} {:>10}  {:<5}  {operands:<26} {}",
+                info.offset,
+                info.index,
+                info.op.name(),
+                info.pixel.to_hex()
+            );
+        }
+    }
+
+    let names = ["RGB", "RGBA", "INDEX", "DIFF", "LUMA", "RUN"];
+    let counts = names
+        .iter()
+        .zip(counts)
+        .map(|(name, count)| format!("{name} {count}"))
+        .collect::<Vec<_>>();
+    let _ = writeln!(out, "ops: {}", counts.join(", "));
+    let end = match error {
+        Some(err) => Err(err),
+        None => ops.end_marker(),
+    };
+    let _ = match end {
+        Ok(0) => writeln!(out, "end marker at byte {}", ops.offset()),
+        Ok(trailing) => writeln!(
+            out,
+            "end marker at byte {}, followed by {trailing} bytes",
+            ops.offset()
+        ),
+        Err(err) => writeln!(out, "error at byte {}: {err}", ops.offset()),
+    };
+    Ok(out)
+}
+
+// Per-channel ranges, colors and coverage of the decoded pixels.
+fn stats(data: &[u8]) -> String {
+    let image = match Decoder::new().lenient(true).decode(data) {
+        Ok(image) => image,
+        Err(err) => return format!("no stats: {err}\n"),
+    };
+    let stats = image.stats();
+    let mut out = String::new();
+    let names = ["red", "green", "blue", "alpha"];
+    for (name, channel) in names.iter().zip(stats.channels) {
+        let _ = writeln!(
+            out,
+            "{name}: {}..{}, mean {:.1}, entropy {:.2} bits",
+            channel.min, channel.max, channel.mean, channel.entropy
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{} colors, {:.1}% transparent, {:.1}% opaque, about {:.1} bits per pixel",
+        stats.unique_colors,
+        stats.transparent * 100.0,
+        stats.opaque * 100.0,
+        stats.entropy()
+    );
+    out
+}
+
+// the op's index in the counts, and its operands
+fn describe(op: Op) -> (usize, String) {
+    match op {
+        Op::Rgb { r, g, b } => (0, format!("r={r} g={g} b={b}")),
+        Op::Rgba { r, g, b, a } => (1, format!("r={r} g={g} b={b} a={a}")),
+        Op::Index { index } => (2, format!("index={index}")),
+        Op::Diff { dr, dg, db } => (3, format!("dr={dr} dg={dg} db={db}")),
+        Op::Luma { dg, dr_dg, db_dg } => (4, format!("dg={dg} dr-dg={dr_dg} db-dg={db_dg}")),
+        Op::Run { len } => (5, format!("len={len}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::{Encoder, Pixel};
+
+    use super::*;
+
+    #[test]
+    fn listing() {
+        let mut pixels = vec![Pixel::new(0, 0, 0, 255); 5];
+        pixels.extend([Pixel::new(1, 255, 0, 255), Pixel::new(200, 100, 50, 25)]);
+        pixels.push(Pixel::new(1, 255, 0, 255));
+        let data = Encoder::new(4, 2).encode(&pixels).unwrap();
+
+        let report = inspect(&data, true, 0..usize::MAX).unwrap();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "4x2, 4 channels, colorspace 0, 30 bytes");
+        let ops = lines[2..6]
+            .iter()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(ops[0], ["14", "0", "RUN", "len=5", "#000000"]);
+        assert_eq!(
+            ops[1],
+            ["15", "5", "DIFF", "dr=1", "dg=-1", "db=0", "#01ff00"]
+        );
+        assert_eq!(ops[2][..3], ["16", "6", "RGBA"]);
+        assert_eq!(ops[3], ["21", "7", "INDEX", "index=51", "#01ff00"]);
+        assert_eq!(
+            lines[6],
+            "ops: RGB 0, RGBA 1, INDEX 1, DIFF 1, LUMA 0, RUN 1"
+        );
+        assert_eq!(lines[7], "end marker at byte 22");
+
+        // only the op covering pixels 3 and 4, then those from pixel 6 on
+        let report = inspect(&data, true, 3..5).unwrap();
+        assert_eq!(report.lines().count(), 5);
+        assert!(report.contains("len=5") && !report.contains("dr=1"));
+        let range = parse_range("6..").unwrap();
+        assert_eq!(range, 6..usize::MAX);
+        assert!(!inspect(&data, true, range).unwrap().contains("len=5"));
+
+        let report = inspect(&data[..18], false, 0..usize::MAX).unwrap();
+        assert!(
+            report.ends_with("error at byte 16: unexpected end of data\n"),
+            "{report}"
+        );
+        assert!(inspect(b"qoif", false, 0..1).is_err());
+
+        let report = stats(&data);
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "red: 0..200, mean 25.2, entropy 1.30 bits");
+        assert_eq!(lines[3], "alpha: 25..255, mean 226.2, entropy 0.54 bits");
+        assert!(lines[4].starts_with("3 colors, 0.0% transparent, 87.5% opaque"));
+        assert!(stats(&data[..18]).starts_with("no stats"));
+    }
+}
@@ -0,0 +1,150 @@
+use std::ops::Range;
+use std::path::Path;
+
+use qoi_rs::{Decoder, Encoder, Header, Image, Pixel};
+
+mod bench;
+mod cat;
+mod compare;
+mod gif2anim;
+mod index;
+mod inspect;
+mod optimize;
+mod pack;
+mod repair;
+mod serve;
+mod split;
+mod video2anim;
+mod watch;
+
+const USAGE: &str = "usage: qoi <command> [args...]
+
+commands:
+    bench <dir>                           measure encode and decode throughput
+                                          over the .qoi and .png files in dir
+    cat [--duration MS] [--loop N] <frames.qoi...> <output.qoan>
+                                          pack QOI stills of the same size
+                                          into an animation, each shown for
+                                          MS milliseconds (100)
+    compare <a> <b>                       report how much two .qoi or .png
+                                          images differ, failing unless they
+                                          are identical
+    gif2anim <input.gif> <output.qoan>    convert a GIF to animated QOI
+    index [--rows N] <file.qoi> [index.qix]
+                                          write a seek index with a checkpoint
+                                          every N rows (64), next to the file
+                                          unless an output is given
+    inspect [--ops] [--range FROM..TO] [--stats] [--heatmap out.png] <file.qoi>
+                                          print the header and op counts, and
+                                          with --ops every op (or those for
+                                          pixels FROM..TO) with its offset,
+                                          with --stats channel ranges, colors
+                                          and entropy; --heatmap saves an
+                                          image of which op made each pixel:
+                                          RUN green, INDEX blue, DIFF yellow,
+                                          LUMA orange, RGB and RGBA red
+    optimize <input.qoi> [output.qoi]     re-encode a file to make it smaller,
+                                          in place without an output
+    pack [--padding N] <images...> <atlas.qoi>
+                                          pack images into a sprite sheet,
+                                          N pixels apart, with a JSON
+                                          manifest of where each went
+    repair <input.qoi> <output.qoi>       salvage a truncated or malformed
+                                          file and list what was fixed
+    serve [--port N] [--raw qoi_rs.wasm] <dir>
+                                          browse dir on localhost, with .qoi
+                                          files as PNG, or decoded in the
+                                          browser by the wasm build with --raw
+    split [--frames FROM..TO] [--png] <input.qoan|.qoia> <dir>
+                                          write the frames of an animation
+                                          or archive (or those numbered FROM
+                                          to TO) to dir as frame_0000.qoi
+                                          and on, or as PNG
+    video2anim [--raw WxH] [--fps RATE] [--keyframes N] <output.qoan>
+                                          convert y4m (or raw RGBA) frames
+                                          from stdin to animated QOI, with
+                                          only every Nth frame stored whole
+                                          and the rest as deltas
+    watch <dir> [--to png|qoi]            convert the .qoi files in dir to
+                                          .png as they are written, or .png
+                                          to .qoi with --to qoi";
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let result = match args.as_slice() {
+        ["bench", dir] => bench::run(dir.as_ref()),
+        ["cat", rest @ ..] => cat::run(rest),
+        ["compare", a, b] => compare::run(a.as_ref(), b.as_ref()),
+        ["gif2anim", input, output] => gif2anim::convert(input.as_ref(), output.as_ref()),
+        ["index", rest @ ..] => index::run(rest),
+        ["inspect", rest @ ..] => inspect::run(rest),
+        ["optimize", rest @ ..] => optimize::run(rest),
+        ["pack", rest @ ..] => pack::run(rest),
+        ["repair", input, output] => repair::run(input.as_ref(), output.as_ref()),
+        ["serve", rest @ ..] => serve::run(rest),
+        ["split", rest @ ..] => split::run(rest),
+        ["video2anim", rest @ ..] => video2anim::run(rest),
+        ["watch", rest @ ..] => watch::run(rest),
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("qoi: {err}");
+        std::process::exit(1);
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_ascii_lowercase())
+}
+
+// Decodes a .qoi file, or any other format the image crate reads.
+fn load(path: &Path) -> Result<Image<Pixel>, String> {
+    let error = |err: &dyn std::fmt::Display| format!("{}: {err}", path.display());
+    if extension(path).as_deref() == Some("qoi") {
+        let data = std::fs::read(path).map_err(|err| error(&err))?;
+        Decoder::new().decode(&data).map_err(|err| error(&err))
+    } else {
+        let image = image::open(path).map_err(|err| error(&err))?;
+        Ok(image.to_rgba8().into())
+    }
+}
+
+// Writes a .qoi file, with 3 channels if the image is opaque, or any other
+// format the image crate writes.
+fn save(image: Image<Pixel>, path: &Path) -> Result<(), String> {
+    let error = |err: &dyn std::fmt::Display| format!("{}: {err}", path.display());
+    if extension(path).as_deref() == Some("qoi") {
+        let opaque = image.pixels.iter().all(|pixel| pixel.a == 255);
+        let header = Header {
+            width: image.width as u32,
+            height: image.height as u32,
+            channels: if opaque { 3 } else { 4 },
+            colorspace: 0,
+        };
+        let data = Encoder::with_header(header)
+            .encode(&image.pixels)
+            .map_err(|err| error(&err))?;
+        std::fs::write(path, data).map_err(|err| error(&err))
+    } else {
+        image::RgbaImage::from(image)
+            .save(path)
+            .map_err(|err| error(&err))
+    }
+}
+
+// `FROM..TO` or `FROM..`, as for slices
+fn parse_range(spec: &str) -> Option<Range<usize>> {
+    let (from, to) = spec.split_once("..")?;
+    let to = if to.is_empty() {
+        usize::MAX
+    } else {
+        to.parse().ok()?
+    };
+    Some(from.parse().ok()?..to)
+}
@@ -0,0 +1,98 @@
+use std::path::Path;
+
+use qoi_rs::{Decoder, Encoder};
+
+// `qoi optimize <input.qoi> [output.qoi]`, in place without an output.
+//
+// The encoder already picks the smallest op for every pixel, so re-encoding is
+// all it takes to shrink files from encoders that do not. The result is only
+// written when it decodes to exactly the same pixels, and only replaces the
+// original when it is smaller.
+pub fn run(args: &[&str]) -> Result<(), String> {
+    let (input, output) = match args {
+        [input] => (Path::new(input), Path::new(input)),
+        [input, output] => (Path::new(input), Path::new(output)),
+        _ => return Err("usage: qoi optimize <input.qoi> [output.qoi]".into()),
+    };
+    let data = std::fs::read(input).map_err(|err| format!("{}: {err}", input.display()))?;
+    let optimized = optimize(&data).map_err(|err| format!("{}: {err}", input.display()))?;
+
+    let (before, after) = (data.len(), optimized.len());
+    if after < before {
+        let saved = 100.0 * (before - after) as f64 / before as f64;
+        println!("{before} -> {after} bytes ({saved:.1}% smaller)");
+        std::fs::write(output, optimized).map_err(|err| format!("{}: {err}", output.display()))?;
+    } else {
+        println!("{before} bytes, already as small as the encoder makes it");
+        if output != input {
+            std::fs::write(output, data).map_err(|err| format!("{}: {err}", output.display()))?;
+        }
+    }
+    Ok(())
+}
+
+// Re-encodes `data` with the same header, checking that nothing changed.
+fn optimize(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoder = Decoder::new();
+    let image = decoder.decode(data).map_err(|err| err.to_string())?;
+    let header = decoder.header().expect("decode read the header");
+    let optimized = Encoder::with_header(header)
+        .encode(&image.pixels)
+        .map_err(|err| err.to_string())?;
+
+    let round_trip = decoder.decode(&optimized).map_err(|err| err.to_string())?;
+    if round_trip.pixels != image.pixels || decoder.header() != Some(header) {
+        return Err("re-encoded file does not round-trip, leaving it alone".into());
+    }
+    Ok(optimized)
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::{Header, Pixel};
+
+    use super::*;
+
+    #[test]
+    fn shrinks_naive_files() {
+        // what a naive encoder writes: an RGBA op for every pixel
+        let pixels = (0..64)
+            .map(|i| Pixel::new(i as u8, 7, 7, 255))
+            .collect::<Vec<_>>();
+        let mut naive = vec![];
+        naive.extend(b"qoif");
+        naive.extend(8u32.to_be_bytes());
+        naive.extend(8u32.to_be_bytes());
+        naive.extend([3, 1]);
+        for pixel in &pixels {
+            naive.push(0xff);
+            naive.extend(pixel.to_bytes());
+        }
+        naive.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+
+        let optimized = optimize(&naive).unwrap();
+        assert_eq!((naive.len(), optimized.len()), (342, 87));
+        let mut decoder = Decoder::new();
+        assert!(decoder.decode(&optimized).unwrap().pixels == pixels);
+        let header = Header {
+            width: 8,
+            height: 8,
+            channels: 3,
+            colorspace: 1,
+        };
+        assert_eq!(decoder.header(), Some(header));
+
+        assert_eq!(optimize(&optimized).unwrap(), optimized);
+        assert!(optimize(&naive[..30]).is_err());
+
+        let dir = std::env::temp_dir().join("qoi-cli-optimize");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("naive.qoi");
+        std::fs::write(&path, &naive).unwrap();
+        let path = path.to_str().unwrap();
+        run(&[path]).unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), optimized);
+        run(&[path]).unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), optimized);
+    }
+}
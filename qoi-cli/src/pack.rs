@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use qoi_rs::atlas::Atlas;
+
+use crate::cat::expand;
+use crate::{load, save};
+
+const USAGE: &str = "usage: qoi pack [--padding N] <images...> <atlas.qoi>";
+
+// `qoi pack [--padding N] <images...> <atlas.qoi>`
+//
+// Packs .qoi or .png images into one sprite sheet, with `--padding` pixels
+// between them, and writes the manifest of where each went, named by file
+// name, to a .json next to it.
+pub fn run(args: &[&str]) -> Result<(), String> {
+    let mut padding = 0;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--padding" => {
+                let value = args.next().ok_or("--padding needs a value in pixels")?;
+                padding = value
+                    .parse()
+                    .map_err(|_| format!("bad --padding {value}"))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unexpected argument {arg}")),
+            _ => paths.push(arg),
+        }
+    }
+    let Some((output, inputs)) = paths.split_last().filter(|(_, inputs)| !inputs.is_empty()) else {
+        return Err(USAGE.into());
+    };
+
+    let mut files = Vec::new();
+    for pattern in inputs {
+        files.extend(expand(pattern)?);
+    }
+    let (atlas, manifest) = pack(&files, padding, Path::new(output))?;
+    println!(
+        "packed {} images into {output} ({}x{}) and {}",
+        files.len(),
+        atlas.image.width,
+        atlas.image.height,
+        manifest.display()
+    );
+    Ok(())
+}
+
+// Writes the sheet to `output` and the manifest next to it, returning the
+// atlas and where the manifest went.
+fn pack(paths: &[PathBuf], padding: usize, output: &Path) -> Result<(Atlas, PathBuf), String> {
+    let mut sprites = Vec::with_capacity(paths.len());
+    for path in paths {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| format!("{}: not a file", path.display()))?;
+        if sprites.iter().any(|(other, _)| *other == name) {
+            return Err(format!(
+                "{}: a file named {name} is already packed",
+                path.display()
+            ));
+        }
+        sprites.push((name, load(path)?));
+    }
+    let sprites = sprites
+        .iter()
+        .map(|(name, image)| (name.as_str(), image))
+        .collect::<Vec<_>>();
+    let atlas = Atlas::pack(&sprites, padding);
+
+    let manifest = output.with_extension("json");
+    std::fs::write(&manifest, atlas.manifest())
+        .map_err(|err| format!("{}: {err}", manifest.display()))?;
+    let sheet = qoi_rs::Image {
+        width: atlas.image.width,
+        height: atlas.image.height,
+        pixels: atlas.image.pixels.clone(),
+    };
+    save(sheet, output)?;
+    Ok((atlas, manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qoi_rs::{Image, Pixel};
+
+    #[test]
+    fn packs_sheet_and_manifest() {
+        let dir = std::env::temp_dir().join(format!("qoi-pack-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut paths = vec![];
+        for (i, (width, height)) in [(4, 6), (8, 2), (3, 3)].into_iter().enumerate() {
+            let image = Image::from_fn(width, height, |x, y| {
+                Pixel::new(i as u8, x as u8, y as u8, 255)
+            });
+            let path = dir.join(format!("sprite{i}.qoi"));
+            save(image, &path).unwrap();
+            paths.push(path);
+        }
+
+        let output = dir.join("sheet.qoi");
+        let (atlas, manifest) = pack(&paths, 2, &output).unwrap();
+        assert_eq!(manifest, dir.join("sheet.json"));
+
+        let sheet = load(&output).unwrap();
+        let manifest = std::fs::read_to_string(&manifest).unwrap();
+        let loaded = Atlas::from_manifest(sheet, &manifest).unwrap();
+        assert_eq!(loaded.sprites, atlas.sprites);
+        for path in &paths {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            assert_eq!(
+                loaded.slice(name).unwrap().pixels,
+                load(path).unwrap().pixels
+            );
+        }
+
+        assert!(pack(&[paths[0].clone(), paths[0].clone()], 0, &output).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use qoi_rs::{DecodeError, Decoder, Encoder};
+
+// Salvages what it can from a damaged file and writes it out as a valid one,
+// printing every fix. Only fails when the header is unusable.
+pub fn run(input: &Path, output: &Path) -> Result<(), String> {
+    let data = std::fs::read(input).map_err(|err| format!("{}: {err}", input.display()))?;
+    let (repaired, fixes) = repair(&data).map_err(|err| format!("{}: {err}", input.display()))?;
+    std::fs::write(output, repaired).map_err(|err| format!("{}: {err}", output.display()))?;
+    if fixes.is_empty() {
+        println!("nothing to repair");
+    }
+    for fix in fixes {
+        println!("{fix}");
+    }
+    Ok(())
+}
+
+// Returns the re-encoded file and a description of each fix.
+fn repair(data: &[u8]) -> Result<(Vec<u8>, Vec<String>), DecodeError> {
+    let mut decoder = Decoder::new().lenient(true);
+    let partial = decoder.decode_partial(data)?;
+    let mut header = decoder.header().expect("decode_partial read the header");
+    let total = partial.image.pixels.len();
+
+    let mut fixes = Vec::new();
+    if !matches!(header.channels, 3 | 4) {
+        fixes.push(format!("replaced channel count {} with 4", header.channels));
+        header.channels = 4;
+    }
+    if !matches!(header.colorspace, 0 | 1) {
+        fixes.push(format!("replaced colorspace {} with 0", header.colorspace));
+        header.colorspace = 0;
+    }
+    match (partial.error, decoder.error_offset()) {
+        (None, _) => {}
+        (Some(DecodeError::InvalidEndMarker), Some(offset)) => {
+            let dropped = data.len() - offset;
+            let replaced = match dropped {
+                0 => String::new(),
+                _ => format!(" in place of {dropped} bytes"),
+            };
+            fixes.push(format!(
+                "re-attached the end marker at byte {offset}{replaced}"
+            ));
+        }
+        (Some(err), offset) => {
+            let offset = offset.unwrap_or(data.len());
+            let padded = total - partial.decoded;
+            fixes.push(format!(
+                "ops broke off at byte {offset} ({err}) after {} of {total} pixels, \
+                 padded the other {padded} with the last one",
+                partial.decoded
+            ));
+            if data.len() > offset {
+                fixes.push(format!(
+                    "dropped {} bytes from byte {offset} on",
+                    data.len() - offset
+                ));
+            }
+        }
+    }
+    if decoder.trailing_len() > 0 {
+        fixes.push(format!(
+            "dropped {} bytes after the end marker",
+            decoder.trailing_len()
+        ));
+    }
+
+    let data = Encoder::with_header(header)
+        .encode(&partial.image.pixels)
+        .map_err(|_| DecodeError::InvalidHeader)?;
+    Ok((data, fixes))
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::{Image, Pixel};
+
+    use super::*;
+
+    #[test]
+    fn repairs() {
+        let image = Image::from_fn(16, 4, |x, y| Pixel::new(x as u8 * 4, y as u8 * 60, 90, 255));
+        let valid = Encoder::new(16, 4).encode(&image.pixels).unwrap();
+        let decode = |data: &[u8]| Decoder::new().decode(data).unwrap().pixels;
+
+        let (data, fixes) = repair(&valid).unwrap();
+        assert_eq!((data.as_slice(), fixes.len()), (valid.as_slice(), 0));
+
+        let mut damaged = valid[..valid.len() - 8].to_vec();
+        damaged[12] = 7;
+        let (data, fixes) = repair(&damaged).unwrap();
+        assert!(decode(&data) == image.pixels);
+        assert_eq!(fixes.len(), 2, "{fixes:?}");
+        assert!(fixes[1].starts_with("re-attached the end marker"));
+
+        let mut damaged = valid.clone();
+        damaged.extend(b"garbage");
+        let (data, fixes) = repair(&damaged).unwrap();
+        assert_eq!(
+            (data, fixes),
+            (
+                valid.clone(),
+                vec!["dropped 7 bytes after the end marker".into()]
+            )
+        );
+
+        let (data, fixes) = repair(&valid[..40]).unwrap();
+        let pixels = decode(&data);
+        assert_eq!(pixels.len(), 64);
+        assert!(pixels[..10] == image.pixels[..10]);
+        assert!(fixes[0].starts_with("ops broke off"), "{fixes:?}");
+
+        assert_eq!(repair(b"qoif").err(), Some(DecodeError::InvalidHeader));
+    }
+}
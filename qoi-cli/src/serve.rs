@@ -0,0 +1,330 @@
+use std::fmt::Write;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::{StatusCode, Uri, header};
+use axum::response::IntoResponse;
+use qoi_rs::Decoder;
+
+use crate::extension;
+
+const USAGE: &str = "usage: qoi serve [--port N] [--raw qoi_rs.wasm] <dir>";
+
+// Decodes QOI in the browser for `--raw`, with the allocator of the wasm viewer.
+const MEMORY_JS: &str = include_str!("../../qoi-viewer-wasm/memory.js");
+const VIEW_HTML: &str = r#"<!doctype html>
+<html>
+<head>
+    <title>QOI</title>
+    <style>body { background: #181818; margin: 0; }</style>
+</head>
+<body>
+    <canvas id="scene"></canvas>
+    <script src="/.qoi/memory.js"></script>
+    <script>
+    async function main() {
+        const module = await WebAssembly.instantiateStreaming(fetch("/.qoi/qoi_rs.wasm"));
+        const wasm = module.instance.exports;
+        const file = await fetch(location.hash.slice(1));
+        if (!file.ok) throw new Error(`Failed to fetch image: ${file.statusText}`);
+        const data = new Uint8Array(await file.arrayBuffer());
+
+        const alloc = new WasmPageAllocator(wasm.memory);
+        const _desc = alloc.u32(3);
+        const _image = alloc.u8(data.length);
+        alloc.reserve();
+        _image.view.set(data);
+        const imgPtr = wasm.qoi_decode(_image.byteOffset, _image.byteLength, _desc.byteOffset, 4);
+        if (imgPtr === 0) throw new Error("Failed to decode image");
+        const width = _desc.get(0);
+        const height = _desc.get(1);
+        const pixels = new Uint8ClampedArray(wasm.memory.buffer, imgPtr, width * height * 4);
+        const imageData = new ImageData(new Uint8ClampedArray(pixels), width, height);
+        wasm.qoi_free(imgPtr);
+
+        scene.width = width;
+        scene.height = height;
+        scene.getContext('2d').putImageData(imageData, 0, 0);
+    }
+    window.onload = main;
+    </script>
+</body>
+</html>
+"#;
+
+type Reply = (StatusCode, [(header::HeaderName, &'static str); 1], Vec<u8>);
+
+struct Site {
+    root: PathBuf,
+    // the decoder for `--raw`, which serves QOI files as they are
+    wasm: Option<Vec<u8>>,
+}
+
+// `qoi serve [--port N] [--raw qoi_rs.wasm] <dir>`
+//
+// Serves `dir` on localhost with a listing of each directory. QOI files are
+// transcoded to PNG so any browser shows them, or with `--raw` sent as
+// image/qoi and shown on a page that decodes them with the wasm build of this
+// crate (`cargo build --release --target wasm32-unknown-unknown`).
+pub fn run(args: &[&str]) -> Result<(), String> {
+    let mut port = 8000u16;
+    let mut wasm = None;
+    let mut dir = None;
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--port" => {
+                let value = args.next().ok_or("--port needs a number")?;
+                port = value.parse().map_err(|_| format!("bad --port {value}"))?;
+            }
+            "--raw" => {
+                let path = args.next().ok_or("--raw needs the path of qoi_rs.wasm")?;
+                wasm = Some(std::fs::read(path).map_err(|err| format!("{path}: {err}"))?);
+            }
+            _ if dir.is_none() && !arg.starts_with("--") => dir = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let root = PathBuf::from(dir.ok_or(USAGE)?);
+    if !root.is_dir() {
+        return Err(format!("{}: not a directory", root.display()));
+    }
+    let site = Arc::new(Site { root, wasm });
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .map_err(|err| err.to_string())?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+            .await
+            .map_err(|err| format!("port {port}: {err}"))?;
+        println!(
+            "serving {} on http://127.0.0.1:{port}/",
+            site.root.display()
+        );
+        let app = axum::Router::new().fallback(handle).with_state(site);
+        axum::serve(listener, app)
+            .await
+            .map_err(|err| err.to_string())
+    })
+}
+
+async fn handle(State(site): State<Arc<Site>>, uri: Uri) -> impl IntoResponse {
+    respond(&site, uri.path())
+}
+
+fn respond(site: &Site, path: &str) -> Reply {
+    let reply = |status, content_type, body: Vec<u8>| {
+        (status, [(header::CONTENT_TYPE, content_type)], body)
+    };
+    let not_found = || reply(StatusCode::NOT_FOUND, "text/plain", b"not found".to_vec());
+
+    if let Some(wasm) = &site.wasm {
+        match path {
+            "/.qoi/view" => return reply(StatusCode::OK, "text/html", VIEW_HTML.into()),
+            "/.qoi/memory.js" => return reply(StatusCode::OK, "text/javascript", MEMORY_JS.into()),
+            "/.qoi/qoi_rs.wasm" => return reply(StatusCode::OK, "application/wasm", wasm.clone()),
+            _ => {}
+        }
+    }
+    let Some(relative) = decode_path(path) else {
+        return not_found();
+    };
+    let file = site.root.join(&relative);
+    if file.is_dir() {
+        return match listing(site, &file, path) {
+            Ok(html) => reply(StatusCode::OK, "text/html", html.into()),
+            Err(_) => not_found(),
+        };
+    }
+    let Ok(data) = std::fs::read(&file) else {
+        return not_found();
+    };
+
+    let extension = extension(&file);
+    match extension.as_deref() {
+        Some("qoi") if site.wasm.is_some() => {
+            reply(StatusCode::OK, qoi_rs::http::CONTENT_TYPE, data)
+        }
+        Some("qoi") => match to_png(&data) {
+            Ok(png) => reply(StatusCode::OK, "image/png", png),
+            Err(err) => reply(StatusCode::UNPROCESSABLE_ENTITY, "text/plain", err.into()),
+        },
+        extension => reply(StatusCode::OK, content_type(extension), data),
+    }
+}
+
+fn to_png(data: &[u8]) -> Result<Vec<u8>, String> {
+    let image = Decoder::new().decode(data).map_err(|err| err.to_string())?;
+    let mut png = Vec::new();
+    image::RgbaImage::from(image)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|err| err.to_string())?;
+    Ok(png)
+}
+
+fn content_type(extension: Option<&str>) -> &'static str {
+    match extension {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("html" | "htm") => "text/html",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("wasm") => "application/wasm",
+        Some("txt" | "md") => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+// A page linking every entry of `dir`, showing the images inline.
+fn listing(site: &Site, dir: &Path, path: &str) -> std::io::Result<String> {
+    let mut entries = std::fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            (!name.starts_with('.')).then(|| (!entry.path().is_dir(), name))
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    // `path` is still percent-encoded, so it is fine for links but not display
+    let base = path.trim_end_matches('/');
+    let shown = dir
+        .strip_prefix(&site.root)
+        .unwrap_or(dir)
+        .to_string_lossy();
+    let title = escape(&format!("/{shown}"));
+    let mut html = format!(
+        "<!doctype html>\n<html>\n<head><title>{title}</title>\
+         <style>img {{ max-width: 256px; max-height: 256px; display: block; }}</style></head>\n\
+         <body>\n<h1>{title}</h1>\n<ul>\n"
+    );
+    if let Some((parent, _)) = base.rsplit_once('/') {
+        let _ = writeln!(html, "<li><a href=\"{parent}/\">../</a></li>");
+    }
+    for (is_file, name) in entries {
+        let href = format!("{base}/{}", encode_component(&name));
+        let label = escape(&name);
+        let extension = extension(Path::new(&name));
+        let _ = match extension.as_deref() {
+            _ if !is_file => writeln!(html, "<li><a href=\"{href}/\">{label}/</a></li>"),
+            Some("qoi") if site.wasm.is_some() => {
+                writeln!(html, "<li><a href=\"/.qoi/view#{href}\">{label}</a></li>")
+            }
+            Some("qoi" | "png" | "jpg" | "jpeg" | "gif" | "webp" | "svg") => writeln!(
+                html,
+                "<li><a href=\"{href}\">{label}<img src=\"{href}\" loading=\"lazy\"></a></li>"
+            ),
+            _ => writeln!(html, "<li><a href=\"{href}\">{label}</a></li>"),
+        };
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    Ok(html)
+}
+
+// The path relative to the served directory, refusing anything that would
+// leave it.
+fn decode_path(path: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    let mut relative = PathBuf::new();
+    for part in path
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+    {
+        if part == ".." || part.contains('\\') {
+            return None;
+        }
+        relative.push(part);
+    }
+    Some(relative)
+}
+
+fn encode_component(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::{Encoder, Image, Pixel};
+
+    use super::*;
+
+    #[test]
+    fn serves_directories() {
+        let root = std::env::temp_dir().join("qoi-cli-serve");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("sub dir")).unwrap();
+        let image = Image::from_fn(5, 3, |x, y| Pixel::new(x as u8 * 50, y as u8 * 80, 9, 255));
+        let qoi = Encoder::new(5, 3).encode(&image.pixels).unwrap();
+        std::fs::write(root.join("sub dir/a&b.qoi"), &qoi).unwrap();
+        std::fs::write(root.join("notes.txt"), "hello").unwrap();
+        let mut site = Site {
+            root: root.clone(),
+            wasm: None,
+        };
+
+        let (status, _, body) = respond(&site, "/");
+        let html = String::from_utf8(body).unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            html.contains("<a href=\"/sub%20dir/\">sub dir/</a>"),
+            "{html}"
+        );
+        assert!(html.contains("<a href=\"/notes.txt\">notes.txt</a>"));
+        let (_, _, body) = respond(&site, "/sub%20dir/");
+        let html = String::from_utf8(body).unwrap();
+        assert!(html.contains("<img src=\"/sub%20dir/a%26b.qoi\""), "{html}");
+
+        let (status, [(_, content_type)], body) = respond(&site, "/sub%20dir/a%26b.qoi");
+        assert_eq!((status, content_type), (StatusCode::OK, "image/png"));
+        let png = image::load_from_memory(&body).unwrap();
+        assert!(Image::from(png.to_rgba8()).pixels == image.pixels);
+        let (_, [(_, content_type)], _) = respond(&site, "/notes.txt");
+        assert_eq!(content_type, "text/plain");
+        assert_eq!(respond(&site, "/../etc/passwd").0, StatusCode::NOT_FOUND);
+        assert_eq!(respond(&site, "/missing.qoi").0, StatusCode::NOT_FOUND);
+        assert_eq!(respond(&site, "/.qoi/view").0, StatusCode::NOT_FOUND);
+
+        // raw QOI and the page that decodes it
+        site.wasm = Some(b"\0asm".to_vec());
+        let (_, [(_, content_type)], body) = respond(&site, "/sub%20dir/a%26b.qoi");
+        assert_eq!((content_type, body), ("image/qoi", qoi));
+        let (_, _, body) = respond(&site, "/sub%20dir");
+        let html = String::from_utf8(body).unwrap();
+        assert!(html.contains("/.qoi/view#/sub%20dir/a%26b.qoi"), "{html}");
+        let (_, [(_, content_type)], _) = respond(&site, "/.qoi/qoi_rs.wasm");
+        assert_eq!(content_type, "application/wasm");
+    }
+}
@@ -0,0 +1,164 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::Path;
+
+use qoi_rs::anim::AnimReader;
+use qoi_rs::archive::ArchiveReader;
+use qoi_rs::{Decoder, Encoder};
+
+use crate::{parse_range, save};
+
+const USAGE: &str = "usage: qoi split [--frames FROM..TO] [--png] <input.qoan|.qoia> <dir>";
+
+// `qoi split [--frames FROM..TO] [--png] <input.qoan|.qoia> <dir>`
+//
+// Writes the frames of an animation, or the images of an archive in order, to
+// `dir` as frame_0000.qoi and on, or as PNG with `--png`. Frames keep their
+// number in the input when only a range of them is written.
+pub fn run(args: &[&str]) -> Result<(), String> {
+    let mut range = 0..usize::MAX;
+    let mut png = false;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--frames" => {
+                let spec = args.next().ok_or("--frames needs FROM..TO")?;
+                range = parse_range(spec).ok_or("bad --frames, expected FROM..TO or FROM..")?;
+            }
+            "--png" => png = true,
+            _ if arg.starts_with("--") => return Err(format!("unexpected argument {arg}")),
+            _ => paths.push(arg),
+        }
+    }
+    let [input, dir] = paths[..] else {
+        return Err(USAGE.into());
+    };
+
+    let data = std::fs::read(input).map_err(|err| format!("{input}: {err}"))?;
+    let written =
+        split(&data, range, png, dir.as_ref()).map_err(|err| format!("{input}: {err}"))?;
+    println!("wrote {written} frames to {dir}");
+    Ok(())
+}
+
+// Writes the frames within `range`, returning how many there were.
+fn split(data: &[u8], range: Range<usize>, png: bool, dir: &Path) -> Result<usize, String> {
+    let frames = if let Some(anim) = AnimReader::new(data) {
+        // delta frames are written out whole
+        let mut frames = Vec::with_capacity(anim.len());
+        let mut previous = None;
+        for i in 0..anim.len() {
+            match anim.get(i) {
+                Some(data) => {
+                    if i + 1 < anim.len() && !anim.is_keyframe(i + 1) {
+                        previous = Decoder::new().decode(data).ok();
+                    }
+                    frames.push(Cow::Borrowed(data));
+                }
+                None => {
+                    let image = previous
+                        .as_ref()
+                        .and_then(|previous| anim.decode_after(i, previous))
+                        .ok_or(format!("frame {i} is corrupt"))?;
+                    let encoded = Encoder::new(image.width as u32, image.height as u32)
+                        .encode(&image.pixels)
+                        .map_err(|err| format!("frame {i}: {err}"))?;
+                    frames.push(Cow::Owned(encoded));
+                    previous = Some(image);
+                }
+            }
+        }
+        frames
+    } else if let Some(archive) = ArchiveReader::new(data) {
+        archive
+            .names()
+            .filter_map(|name| archive.get(name))
+            .map(Cow::Borrowed)
+            .collect()
+    } else {
+        return Err("not an animation or an archive".into());
+    };
+    let range = range.start.min(frames.len())..range.end.min(frames.len());
+    if range.is_empty() {
+        return Err(format!("no frames in range, it has {}", frames.len()));
+    }
+
+    std::fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    for (i, frame) in frames[range.clone()].iter().enumerate() {
+        let index = range.start + i;
+        if png {
+            let image = Decoder::new()
+                .decode(frame)
+                .map_err(|err| format!("frame {index}: {err}"))?;
+            save(image, &dir.join(format!("frame_{index:04}.png")))?;
+        } else {
+            let path = dir.join(format!("frame_{index:04}.qoi"));
+            std::fs::write(&path, frame).map_err(|err| format!("{}: {err}", path.display()))?;
+        }
+    }
+    Ok(range.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::anim::AnimWriter;
+    use qoi_rs::archive::ArchiveWriter;
+    use qoi_rs::{Encoder, Image, Pixel};
+
+    use super::*;
+    use crate::load;
+
+    #[test]
+    fn splits() {
+        let frames = (0..5u8)
+            .map(|i| Image::from_fn(4, 3, |x, y| Pixel::new(x as u8, y as u8, i * 40, 255)))
+            .collect::<Vec<_>>();
+        let mut writer = AnimWriter::new(Vec::new(), 4, 3, 0).unwrap();
+        let mut archive = ArchiveWriter::new();
+        for (i, frame) in frames.iter().enumerate() {
+            writer.add_frame(frame, 50).unwrap();
+            archive.add_image(&format!("f{i}"), frame).unwrap();
+        }
+        let anim = writer.finish().unwrap();
+        let archive = archive.finish();
+
+        let dir = std::env::temp_dir().join("qoi-cli-split");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(split(&anim, 1..3, false, &dir), Ok(2));
+        let names = |dir: &Path| {
+            let mut names = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&dir), ["frame_0001.qoi", "frame_0002.qoi"]);
+        let data = std::fs::read(dir.join("frame_0002.qoi")).unwrap();
+        let expected = Encoder::new(4, 3).encode(&frames[2].pixels).unwrap();
+        assert_eq!(data, expected);
+
+        // delta frames come out as whole frames
+        let mut writer = AnimWriter::new(Vec::new(), 4, 3, 0)
+            .unwrap()
+            .keyframe_interval(2);
+        for frame in &frames {
+            writer.add_frame(frame, 50).unwrap();
+        }
+        let deltas = writer.finish().unwrap();
+        let delta_dir = dir.join("deltas");
+        assert_eq!(split(&deltas, 1..3, false, &delta_dir), Ok(2));
+        let frame = load(&delta_dir.join("frame_0001.qoi")).unwrap();
+        assert!(frame.pixels == frames[1].pixels);
+
+        let dir = dir.join("png");
+        assert_eq!(split(&archive, 3..usize::MAX, true, &dir), Ok(2));
+        assert_eq!(names(&dir), ["frame_0003.png", "frame_0004.png"]);
+        let image = load(&dir.join("frame_0004.png")).unwrap();
+        assert!(image.pixels == frames[4].pixels);
+
+        assert!(split(&anim, 5..9, false, &dir).is_err());
+        assert!(split(&expected, 0..1, false, &dir).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! `qoi-thumbnailer -s SIZE <input> <output.png>`, a thumbnailer for Linux file
+//! managers following the freedesktop thumbnail spec; see `qoi.thumbnailer`.
+//!
+//! The input may be a path or a `file://` URI. Large images are decoded
+//! subsampled, which is much faster than decoding everything to throw most of
+//! it away, and then scaled to fit within SIZE pixels.
+
+use std::path::PathBuf;
+
+use qoi_rs::{Decoder, Image, Pixel};
+
+const USAGE: &str = "usage: qoi-thumbnailer [-s SIZE] <input.qoi> <output.png>";
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let (size, input, output) = match args.as_slice() {
+        ["-s", size, input, output] => (size.parse().ok(), *input, *output),
+        [input, output] => (Some(256), *input, *output),
+        _ => (None, "", ""),
+    };
+    let Some(size) = size.filter(|&size| size > 0) else {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    };
+
+    if let Err(err) = run(input, output, size) {
+        eprintln!("qoi-thumbnailer: {err}");
+        std::process::exit(1);
+    }
+}
+
+fn run(input: &str, output: &str, size: usize) -> Result<(), String> {
+    let path = path_from_uri(input).ok_or_else(|| format!("{input}: not a local file"))?;
+    let data = std::fs::read(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let thumbnail = thumbnail(&data, size).map_err(|err| format!("{}: {err}", path.display()))?;
+    image::RgbaImage::from(thumbnail)
+        .save_with_format(output, image::ImageFormat::Png)
+        .map_err(|err| format!("{output}: {err}"))
+}
+
+fn thumbnail(data: &[u8], size: usize) -> Result<Image<Pixel>, qoi_rs::DecodeError> {
+    let (width, height) = Decoder::dimensions(data)?;
+    // the largest factor that keeps the preview at least `size` pixels across
+    let factor = (width.max(height) / size).max(1);
+    let preview = Decoder::new().decode_subsampled(data, factor)?;
+    Ok(preview.thumbnail(size))
+}
+
+// Accepts plain paths and `file://` URIs, whose percent-escapes are decoded.
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let Some(rest) = uri.strip_prefix("file://") else {
+        return (!uri.contains("://")).then(|| uri.into());
+    };
+    // an optional host, which must be the local one
+    let path = &rest[rest.find('/')?..];
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    #[cfg(unix)]
+    return Some(<std::ffi::OsString as std::os::unix::ffi::OsStringExt>::from_vec(bytes).into());
+    #[cfg(not(unix))]
+    return Some(String::from_utf8(bytes).ok()?.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::Encoder;
+
+    use super::*;
+
+    #[test]
+    fn uris() {
+        assert_eq!(path_from_uri("/tmp/a b.qoi"), Some("/tmp/a b.qoi".into()));
+        assert_eq!(
+            path_from_uri("file:///tmp/a%20b.qoi"),
+            Some("/tmp/a b.qoi".into())
+        );
+        assert_eq!(
+            path_from_uri("file://localhost/x.qoi"),
+            Some("/x.qoi".into())
+        );
+        assert_eq!(path_from_uri("file:///bad%2"), None);
+        assert_eq!(path_from_uri("sftp://host/x.qoi"), None);
+    }
+
+    #[test]
+    fn thumbnails() {
+        let image = Image::from_fn(1000, 400, |x, y| Pixel::new(x as u8, y as u8, 0, 255));
+        let data = Encoder::new(1000, 400).encode(&image.pixels).unwrap();
+        let thumb = thumbnail(&data, 128).unwrap();
+        assert_eq!((thumb.width, thumb.height), (128, 51));
+        let small = thumbnail(&data, 2000).unwrap();
+        assert_eq!((small.width, small.height), (1000, 400));
+
+        let dir = std::env::temp_dir().join("qoi-thumbnailer");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in put.qoi");
+        std::fs::write(&input, &data).unwrap();
+        let uri = format!("file://{}", input.display()).replace(' ', "%20");
+        let output = dir.join("out.png");
+        run(&uri, output.to_str().unwrap(), 64).unwrap();
+        let png = image::open(&output).unwrap();
+        assert_eq!((png.width(), png.height()), (64, 25));
+    }
+}
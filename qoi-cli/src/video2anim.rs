@@ -0,0 +1,294 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+
+use qoi_rs::anim::AnimWriter;
+use qoi_rs::{Image, Pixel};
+
+pub enum Input {
+    Y4m,
+    // raw RGBA frames carry no header, so the size and rate come from the command line
+    Raw {
+        width: u32,
+        height: u32,
+        fps: (u32, u32),
+    },
+}
+
+enum Chroma {
+    C420,
+    C422,
+    C444,
+    Mono,
+}
+
+struct Format {
+    width: usize,
+    height: usize,
+    fps: (u32, u32),
+    chroma: Option<Chroma>,
+}
+
+impl Format {
+    fn frame_len(&self) -> usize {
+        let (w, h) = (self.width, self.height);
+        match self.chroma {
+            None => 4 * w * h,
+            Some(Chroma::C420) => w * h + 2 * w.div_ceil(2) * h.div_ceil(2),
+            Some(Chroma::C422) => w * h + 2 * w.div_ceil(2) * h,
+            Some(Chroma::C444) => 3 * w * h,
+            Some(Chroma::Mono) => w * h,
+        }
+    }
+}
+
+// `qoi video2anim [--raw WIDTHxHEIGHT] [--fps RATE] [--keyframes N] <output.qoan>`,
+// frames on stdin
+pub fn run(args: &[&str]) -> Result<(), String> {
+    let mut raw_size = None;
+    let mut fps = (30, 1);
+    let mut keyframes = 1;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--raw" => {
+                let size = args.next().ok_or("--raw needs WIDTHxHEIGHT")?;
+                raw_size = Some(parse_pair(size, 'x').ok_or("bad --raw size")?);
+            }
+            "--fps" => {
+                let rate = args.next().ok_or("--fps needs a rate")?;
+                fps = parse_pair(rate, '/')
+                    .or_else(|| rate.parse().ok().map(|n| (n, 1)))
+                    .filter(|&(n, d)| n > 0 && d > 0)
+                    .ok_or("bad --fps rate")?;
+            }
+            "--keyframes" => {
+                let interval = args.next().ok_or("--keyframes needs an interval")?;
+                keyframes = interval
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or("bad --keyframes interval")?;
+            }
+            path if output.is_none() => output = Some(path),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+    let output = output.ok_or("missing output path")?;
+
+    let input = match raw_size {
+        Some((width, height)) => Input::Raw { width, height, fps },
+        None => Input::Y4m,
+    };
+    let file = File::create(output).map_err(|err| format!("{output}: {err}"))?;
+    let frames = convert(io::stdin().lock(), BufWriter::new(file), input, keyframes)?;
+    println!("wrote {frames} frames to {output}");
+    Ok(())
+}
+
+// Streams frames from `reader` into an animation written to `writer`, holding
+// only one frame in memory at a time, with every `keyframe_interval`th frame a
+// keyframe and the others deltas. Returns the number of frames written.
+pub fn convert(
+    mut reader: impl BufRead,
+    writer: impl Write,
+    input: Input,
+    keyframe_interval: u32,
+) -> Result<usize, String> {
+    let format = match input {
+        Input::Y4m => read_y4m_header(&mut reader)?,
+        Input::Raw { width, height, fps } => Format {
+            width: width as usize,
+            height: height as usize,
+            fps,
+            chroma: None,
+        },
+    };
+
+    let (width, height) = (format.width as u32, format.height as u32);
+    let mut anim = AnimWriter::new(writer, width, height, 0)
+        .map_err(|err| err.to_string())?
+        .keyframe_interval(keyframe_interval);
+    let mut data = vec![0; format.frame_len()];
+    let mut image = Image {
+        width: format.width,
+        height: format.height,
+        pixels: vec![Pixel::new(0, 0, 0, 255); format.width * format.height],
+    };
+
+    let mut frames = 0;
+    loop {
+        if format.chroma.is_some() {
+            let mut line = vec![];
+            reader
+                .read_until(b'\n', &mut line)
+                .map_err(|err| err.to_string())?;
+            if line.is_empty() {
+                break;
+            }
+            if !line.starts_with(b"FRAME") {
+                return Err("bad y4m frame header".into());
+            }
+        } else if reader.fill_buf().map_err(|err| err.to_string())?.is_empty() {
+            break;
+        }
+        reader
+            .read_exact(&mut data)
+            .map_err(|err| format!("truncated frame: {err}"))?;
+
+        match &format.chroma {
+            None => {
+                for (pixel, rgba) in image.pixels.iter_mut().zip(data.chunks_exact(4)) {
+                    *pixel = Pixel::new(rgba[0], rgba[1], rgba[2], rgba[3]);
+                }
+            }
+            Some(chroma) => yuv_to_rgb(&data, chroma, &mut image),
+        }
+
+        // spread rounding over the whole stream so long recordings do not drift
+        let (num, den) = (format.fps.0 as u64, format.fps.1 as u64);
+        let start = frames as u64 * 1000 * den / num;
+        let end = (frames as u64 + 1) * 1000 * den / num;
+        anim.add_frame(&image, (end - start) as u32)
+            .map_err(|err| err.to_string())?;
+        frames += 1;
+    }
+
+    anim.finish().map_err(|err| err.to_string())?;
+    Ok(frames)
+}
+
+fn parse_pair(s: &str, separator: char) -> Option<(u32, u32)> {
+    let (a, b) = s.split_once(separator)?;
+    Some((a.parse().ok()?, b.parse().ok()?))
+}
+
+fn read_y4m_header(reader: &mut impl BufRead) -> Result<Format, String> {
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|err| err.to_string())?;
+    let mut params = line.trim_end().split(' ');
+    if params.next() != Some("YUV4MPEG2") {
+        return Err("not a y4m stream".into());
+    }
+
+    let (mut width, mut height, mut fps, mut chroma) = (None, None, (30, 1), Chroma::C420);
+    for param in params {
+        let (key, value) = param.split_at(param.len().min(1));
+        match key {
+            "W" => width = value.parse().ok(),
+            "H" => height = value.parse().ok(),
+            "F" => {
+                fps = parse_pair(value, ':')
+                    .filter(|&(n, d)| n > 0 && d > 0)
+                    .ok_or("bad y4m frame rate")?
+            }
+            "C" => {
+                chroma = match value {
+                    "420" | "420jpeg" | "420paldv" | "420mpeg2" => Chroma::C420,
+                    "422" => Chroma::C422,
+                    "444" => Chroma::C444,
+                    "mono" => Chroma::Mono,
+                    _ => return Err(format!("unsupported y4m colorspace {value}")),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Format {
+        width: width.ok_or("y4m header without width")?,
+        height: height.ok_or("y4m header without height")?,
+        fps,
+        chroma: Some(chroma),
+    })
+}
+
+// BT.601 with limited range, which is what ffmpeg writes by default
+fn yuv_to_rgb(data: &[u8], chroma: &Chroma, image: &mut Image<Pixel>) {
+    let (width, height) = (image.width, image.height);
+    let (luma, planes) = data.split_at(width * height);
+    let (cw, ch) = match chroma {
+        Chroma::C420 => (width.div_ceil(2), height.div_ceil(2)),
+        Chroma::C422 => (width.div_ceil(2), height),
+        Chroma::C444 => (width, height),
+        Chroma::Mono => (0, 0),
+    };
+    let (u_plane, v_plane) = planes.split_at(cw * ch);
+
+    for y in 0..height {
+        for x in 0..width {
+            let (u, v) = match chroma {
+                Chroma::Mono => (128, 128),
+                _ => {
+                    let i = (y * ch / height) * cw + x * cw / width;
+                    (u_plane[i], v_plane[i])
+                }
+            };
+            let c = 298 * (luma[y * width + x] as i32 - 16);
+            let (d, e) = (u as i32 - 128, v as i32 - 128);
+            let clamp = |value: i32| ((value + 128) >> 8).clamp(0, 255) as u8;
+            image.pixels[y * width + x] = Pixel::new(
+                clamp(c + 409 * e),
+                clamp(c - 100 * d - 208 * e),
+                clamp(c + 516 * d),
+                255,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::anim::AnimReader;
+
+    use super::*;
+
+    #[test]
+    fn y4m() {
+        let mut stream = b"YUV4MPEG2 W2 H2 F25:1 Ip A1:1 C420jpeg\n".to_vec();
+        for (y, u, v) in [(235, 128, 128), (16, 128, 128), (81, 90, 240)] {
+            stream.extend(b"FRAME\n");
+            stream.extend([y, y, y, y, u, v]);
+        }
+
+        let mut out = vec![];
+        assert_eq!(convert(&stream[..], &mut out, Input::Y4m, 1), Ok(3));
+        let anim = AnimReader::new(&out).unwrap();
+        assert_eq!(
+            (anim.width, anim.height, anim.duration_ms(0)),
+            (2, 2, Some(40))
+        );
+        let white = anim.decode(0).unwrap();
+        assert!(
+            white
+                .pixels
+                .iter()
+                .all(|&p| p == Pixel::new(255, 255, 255, 255))
+        );
+        let red = anim.decode(2).unwrap().pixels[3];
+        assert!(red.r > 250 && red.g < 5 && red.b < 5);
+
+        // a frame cut short is an error rather than a silently dropped frame
+        assert!(convert(&stream[..stream.len() - 1], vec![], Input::Y4m, 1).is_err());
+    }
+
+    #[test]
+    fn raw() {
+        let stream = (0..2 * 3 * 4 * 3).map(|i| i as u8).collect::<Vec<_>>();
+        let input = Input::Raw {
+            width: 2,
+            height: 3,
+            fps: (30000, 1001),
+        };
+
+        let mut out = vec![];
+        assert_eq!(convert(&stream[..], &mut out, input, 2), Ok(3));
+        let anim = AnimReader::new(&out).unwrap();
+        let durations = (0..3)
+            .map(|i| anim.duration_ms(i).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(durations, [33, 33, 34]);
+        assert!(!anim.is_keyframe(1));
+        assert!(anim.decode(1).unwrap().pixels[0] == Pixel::new(24, 25, 26, 27));
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::{extension, load, save};
+use notify::{EventKind, RecursiveMode, Watcher};
+
+const USAGE: &str = "usage: qoi watch <dir> [--to png|qoi]";
+
+// Changed files are converted once no events have arrived for this long, so a
+// file that is still being written is not read half way through.
+const SETTLE: Duration = Duration::from_millis(250);
+
+// `qoi watch <dir> [--to png|qoi]`
+//
+// Converts the .qoi files in `dir` to .png next to them, or the other way
+// around with `--to qoi`, first those that are out of date and then every one
+// that is created or modified until interrupted.
+pub fn run(args: &[&str]) -> Result<(), String> {
+    let (dir, to) = match args {
+        [dir] | [dir, "--to", "png"] | ["--to", "png", dir] => (Path::new(dir), "png"),
+        [dir, "--to", "qoi"] | ["--to", "qoi", dir] => (Path::new(dir), "qoi"),
+        _ => return Err(USAGE.into()),
+    };
+    let from = if to == "png" { "qoi" } else { "png" };
+    convert_dir(dir, from, to)?;
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(|err| err.to_string())?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(|err| format!("{}: {err}", dir.display()))?;
+    println!("watching {} for .{from} files", dir.display());
+
+    let mut pending = BTreeSet::new();
+    loop {
+        let timeout = if pending.is_empty() {
+            Duration::MAX
+        } else {
+            SETTLE
+        };
+        match events.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    let paths = event.paths.into_iter();
+                    pending.extend(paths.filter(|path| extension(path).as_deref() == Some(from)));
+                }
+            }
+            Ok(Err(err)) => eprintln!("qoi: {err}"),
+            Err(RecvTimeoutError::Timeout) => {
+                for path in std::mem::take(&mut pending) {
+                    report(&path, convert(&path, to));
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return Err("the watcher stopped".into()),
+        }
+    }
+}
+
+// Converts every .`from` file in `dir` that is out of date, returning how many
+// were converted. Files that fail are reported and skipped.
+fn convert_dir(dir: &Path, from: &str, to: &str) -> Result<usize, String> {
+    let entries = std::fs::read_dir(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|err| err.to_string())?.path();
+        if path.is_file() && extension(&path).as_deref() == Some(from) {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    let mut converted = 0;
+    for path in files {
+        let result = convert(&path, to);
+        converted += matches!(result, Ok(Some(_))) as usize;
+        report(&path, result);
+    }
+    Ok(converted)
+}
+
+// Converts `path` to a file with the extension `to` next to it, unless that is
+// already newer or `path` is gone. Returns the converted file.
+fn convert(path: &Path, to: &str) -> Result<Option<PathBuf>, String> {
+    let output = path.with_extension(to);
+    let modified = |path: &Path| {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    };
+    match (modified(path), modified(&output)) {
+        (None, _) => return Ok(None),
+        (Some(source), Some(target)) if target >= source => return Ok(None),
+        _ => {}
+    }
+    save(load(path)?, &output)?;
+    Ok(Some(output))
+}
+
+fn report(path: &Path, result: Result<Option<PathBuf>, String>) {
+    match result {
+        Ok(Some(output)) => println!("{} -> {}", path.display(), output.display()),
+        Ok(None) => {}
+        Err(err) => eprintln!("qoi: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qoi_rs::{Encoder, Image, Pixel};
+
+    use super::*;
+
+    #[test]
+    fn converts_stale_files() {
+        let dir = std::env::temp_dir().join("qoi-cli-watch");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = Image::from_fn(12, 5, |x, y| Pixel::new(x as u8 * 20, y as u8 * 50, 0, 255));
+        let data = Encoder::new(12, 5).encode(&image.pixels).unwrap();
+        std::fs::write(dir.join("a.qoi"), &data).unwrap();
+        std::fs::write(dir.join("b.qoi"), &data).unwrap();
+        std::fs::write(dir.join("broken.qoi"), &data[..20]).unwrap();
+
+        assert_eq!(convert_dir(&dir, "qoi", "png"), Ok(2));
+        assert!(load(&dir.join("a.png")).unwrap().pixels == image.pixels);
+        assert_eq!(convert_dir(&dir, "qoi", "png"), Ok(0));
+        std::fs::remove_file(dir.join("b.png")).unwrap();
+        assert_eq!(convert_dir(&dir, "qoi", "png"), Ok(1));
+        assert_eq!(convert(&dir.join("gone.qoi"), "png"), Ok(None));
+
+        // and back, as an RGB file since the image is opaque
+        std::fs::remove_file(dir.join("a.qoi")).unwrap();
+        assert_eq!(
+            convert(&dir.join("a.png"), "qoi"),
+            Ok(Some(dir.join("a.qoi")))
+        );
+        let data = std::fs::read(dir.join("a.qoi")).unwrap();
+        assert_eq!(data[12], 3);
+        assert!(load(&dir.join("a.qoi")).unwrap().pixels == image.pixels);
+    }
+}
@@ -0,0 +1,70 @@
+//! Node.js bindings, built as a native addon with napi-rs.
+//!
+//! ```js
+//! const qoi = require("./qoi.node");
+//! const file = qoi.encode(rgba, width, height, 4);
+//! const { width, height, channels, data } = qoi.decode(file);
+//! ```
+
+use napi::bindgen_prelude::Buffer;
+use napi::{Error, Result};
+use napi_derive::napi;
+use qoi_rs::{Decoder, Encoder, Pixel};
+
+#[napi(object)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u32,
+    pub data: Buffer,
+}
+
+// `data` holds `width * height` pixels of 3 (RGB) or 4 (RGBA) bytes each
+#[napi]
+pub fn encode(data: Buffer, width: u32, height: u32, channels: u32) -> Result<Buffer> {
+    if channels != 3 && channels != 4 {
+        return Err(Error::from_reason("channels must be 3 or 4"));
+    }
+    let expected = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|n| n.checked_mul(channels as usize));
+    if expected != Some(data.len()) {
+        return Err(Error::from_reason(
+            "data length does not match width * height * channels",
+        ));
+    }
+
+    let pixels = data
+        .chunks_exact(channels as usize)
+        .map(|px| Pixel::new(px[0], px[1], px[2], px.get(3).copied().unwrap_or(255)))
+        .collect::<Vec<_>>();
+    let encoded = Encoder::new(width, height)
+        .force_opaque(channels == 3)
+        .encode(&pixels)
+        .map_err(|err| Error::from_reason(err.to_string()))?;
+    Ok(encoded.into())
+}
+
+// Decodes to RGBA, or to RGB when `channels` is 3.
+#[napi]
+pub fn decode(data: Buffer, channels: Option<u32>) -> Result<DecodedImage> {
+    let channels = channels.unwrap_or(4);
+    if channels != 3 && channels != 4 {
+        return Err(Error::from_reason("channels must be 3 or 4"));
+    }
+    let image = Decoder::new()
+        .decode(&data)
+        .map_err(|err| Error::from_reason(err.to_string()))?;
+
+    let bytes = image
+        .pixels
+        .iter()
+        .flat_map(|px| px.to_bytes().into_iter().take(channels as usize))
+        .collect::<Vec<_>>();
+    Ok(DecodedImage {
+        width: image.width as u32,
+        height: image.height as u32,
+        channels,
+        data: bytes.into(),
+    })
+}
@@ -0,0 +1,15 @@
+// cargo build -p qoi-node && cp ../target/debug/libqoi_node.so qoi.node && node test.js
+const assert = require("node:assert");
+const qoi = require("./qoi.node");
+
+const rgb = Buffer.from([255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255]);
+const file = qoi.encode(rgb, 2, 2, 3);
+
+const rgba = qoi.decode(file);
+assert.deepStrictEqual([rgba.width, rgba.height, rgba.channels], [2, 2, 4]);
+assert.deepStrictEqual([...rgba.data.subarray(0, 8)], [255, 0, 0, 255, 0, 255, 0, 255]);
+assert.deepStrictEqual([...qoi.decode(file, 3).data], [...rgb]);
+
+assert.throws(() => qoi.encode(rgb, 3, 3, 3));
+assert.throws(() => qoi.decode(Buffer.from([1, 2, 3])));
+console.log("ok");
@@ -0,0 +1,12 @@
+fn main() {
+    println!("cargo:rerun-if-changed=vendor/qoi.h");
+    println!("cargo:rerun-if-changed=src/qoi.c");
+    // qoi-rs exports the same C API, so the reference symbols get a prefix to
+    // let both live in one test binary
+    cc::Build::new()
+        .file("src/qoi.c")
+        .include("vendor")
+        .define("qoi_encode", "qoi_ref_encode")
+        .define("qoi_decode", "qoi_ref_decode")
+        .compile("qoi_ref");
+}
@@ -0,0 +1,69 @@
+//! Bindings to the reference `qoi.h` (vendored from phoboslab/qoi), used to
+//! check qoi-rs against it in tests and fuzzing. Not meant for production use.
+
+use std::ffi::{c_int, c_void};
+use std::slice;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desc {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u8,
+    pub colorspace: u8,
+}
+
+unsafe extern "C" {
+    fn qoi_ref_encode(data: *const c_void, desc: *const Desc, out_len: *mut c_int) -> *mut c_void;
+    fn qoi_ref_decode(
+        data: *const c_void,
+        size: c_int,
+        desc: *mut Desc,
+        channels: c_int,
+    ) -> *mut c_void;
+    fn free(ptr: *mut c_void);
+}
+
+// Copies a buffer allocated by qoi.h and frees the original.
+unsafe fn take(ptr: *mut c_void, len: usize) -> Vec<u8> {
+    let data = unsafe { slice::from_raw_parts(ptr.cast::<u8>(), len) }.to_vec();
+    unsafe { free(ptr) };
+    data
+}
+
+// `pixels` holds `desc.channels` bytes per pixel. Returns None wherever
+// `qoi_encode` returns NULL, e.g. for zero dimensions.
+pub fn encode(pixels: &[u8], desc: Desc) -> Option<Vec<u8>> {
+    let len = desc.width as usize * desc.height as usize * desc.channels as usize;
+    if pixels.len() < len {
+        return None;
+    }
+    let mut out_len = 0;
+    let ptr = unsafe { qoi_ref_encode(pixels.as_ptr().cast(), &desc, &mut out_len) };
+    if ptr.is_null() {
+        return None;
+    }
+    Some(unsafe { take(ptr, out_len as usize) })
+}
+
+// Decodes to `channels` bytes per pixel (0 uses the channels from the header).
+pub fn decode(data: &[u8], channels: u8) -> Option<(Desc, Vec<u8>)> {
+    let size = c_int::try_from(data.len()).ok()?;
+    let mut desc = Desc {
+        width: 0,
+        height: 0,
+        channels: 0,
+        colorspace: 0,
+    };
+    let ptr = unsafe { qoi_ref_decode(data.as_ptr().cast(), size, &mut desc, channels as c_int) };
+    if ptr.is_null() {
+        return None;
+    }
+    let channels = if channels == 0 {
+        desc.channels
+    } else {
+        channels
+    };
+    let len = desc.width as usize * desc.height as usize * channels as usize;
+    Some((desc, unsafe { take(ptr, len) }))
+}
@@ -34,8 +34,7 @@ const char* fragmentShaderSource = SHADER_SRC(
         FragColor = texture(myTexture, texCoord);
     });
 
-unsigned char* qoi_decode(const char* data, int len, int* width, int* height);
-void qoi_free(char* data);
+#include "../include/qoi.h"
 
 int main(int argc, char **argv) {
     if (argc < 2) {
@@ -50,9 +49,10 @@ int main(int argc, char **argv) {
         return 1;
     }
 
-    int width, height;
-    unsigned char* image = qoi_decode(sb.data, sb.count, &width, &height);
+    qoi_desc desc;
+    unsigned char* image = qoi_decode(sb.data, sb.count, &desc, 4);
     free(sb.data);
+    int width = desc.width, height = desc.height;
 
     if (!image) {
         printf("Failed to decode. Make sure image is valid QOI format.\n");
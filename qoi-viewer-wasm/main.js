@@ -9,8 +9,8 @@ async function main() {
     const data = new Uint8Array(await suz.arrayBuffer());
 
     const alloc = new WasmPageAllocator(wasm.memory);
-    const _width = alloc.u32();
-    const _height = alloc.u32();
+    // qoi_desc: u32 width, u32 height, u8 channels, u8 colorspace, padding
+    const _desc = alloc.u32(3);
     const _image = alloc.u8(data.length);
     alloc.reserve();
 
@@ -18,19 +18,18 @@ async function main() {
     const imgPtr = wasm.qoi_decode(
         _image.byteOffset,
         _image.byteLength,
-        _width.byteOffset,
-        _height.byteOffset);
-    const width = _width.get(0);
-    const height = _height.get(0);
+        _desc.byteOffset,
+        4);
+    const width = _desc.get(0);
+    const height = _desc.get(1);
 
     const imgSize = width * height * 4;
     const image = new Uint8ClampedArray(wasm.memory.buffer, imgPtr, imgSize);
 
     const ctx = scene.getContext('2d');
-    const imageData = new ImageData(image, width, height);
-
-    // TODO: panics
-    // wasm.qoi_free(imgPtr);
+    // copy the pixels out so the wasm buffer can be released
+    const imageData = new ImageData(new Uint8ClampedArray(image), width, height);
+    wasm.qoi_free(imgPtr);
 
     // resizing the canvas clears it so redraw
     (window.onresize = function() {
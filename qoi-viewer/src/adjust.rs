@@ -0,0 +1,75 @@
+const EXPOSURE_STEP: f32 = 0.5;
+const GAMMA_STEP: f32 = 0.1;
+
+pub struct Adjustments {
+    pub exposure: f32,
+    pub gamma: f32,
+    // encode with the sRGB transfer function, for images stored linear
+    linear: bool,
+    lut: [u8; 256],
+}
+
+impl Adjustments {
+    pub fn new() -> Self {
+        let mut adjustments = Self {
+            exposure: 0.0,
+            gamma: 1.0,
+            linear: false,
+            lut: [0; 256],
+        };
+        adjustments.rebuild();
+        adjustments
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.exposure == 0.0 && self.gamma == 1.0
+    }
+
+    pub fn apply(&self, value: u8) -> u8 {
+        self.lut[value as usize]
+    }
+
+    pub fn step_exposure(&mut self, steps: f32) {
+        self.exposure += steps * EXPOSURE_STEP;
+        self.rebuild();
+    }
+
+    pub fn step_gamma(&mut self, steps: f32) {
+        self.gamma = (self.gamma + steps * GAMMA_STEP).max(GAMMA_STEP);
+        self.rebuild();
+    }
+
+    pub fn set_linear(&mut self, linear: bool) {
+        if self.linear != linear {
+            self.linear = linear;
+            self.rebuild();
+        }
+    }
+
+    // back to no exposure or gamma change; the sRGB conversion stays
+    pub fn reset(&mut self) {
+        let linear = self.linear;
+        *self = Self::new();
+        self.set_linear(linear);
+    }
+
+    fn rebuild(&mut self) {
+        let scale = self.exposure.exp2();
+        for (i, out) in self.lut.iter_mut().enumerate() {
+            let mut value = (i as f32 / 255.0 * scale).min(1.0);
+            if self.linear {
+                value = linear_to_srgb(value);
+            }
+            let value = value.powf(1.0 / self.gamma);
+            *out = (value * 255.0).round() as u8;
+        }
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
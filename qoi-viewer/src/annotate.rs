@@ -0,0 +1,191 @@
+use nalgebra::{Matrix3, Point2};
+
+use qoi_rs::{Image, Pixel};
+
+use crate::draw::{Canvas, line};
+
+// what "c" cycles through
+const COLORS: [(&str, Pixel); 6] = [
+    ("red", rgb(0xff, 0x30, 0x30)),
+    ("yellow", rgb(0xff, 0xd0, 0x20)),
+    ("green", rgb(0x30, 0xd0, 0x50)),
+    ("blue", rgb(0x30, 0x80, 0xff)),
+    ("white", rgb(0xff, 0xff, 0xff)),
+    ("black", rgb(0x00, 0x00, 0x00)),
+];
+
+const fn rgb(r: u8, g: u8, b: u8) -> Pixel {
+    Pixel { r, g, b, a: 255 }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tool {
+    Rect,
+    Arrow,
+    Freehand,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Rect => "rectangle",
+            Tool::Arrow => "arrow",
+            Tool::Freehand => "freehand",
+        }
+    }
+}
+
+// A shape in image coordinates, so it stays put when panning and zooming.
+struct Shape {
+    tool: Tool,
+    color: Pixel,
+    points: Vec<Point2<f32>>,
+}
+
+impl Shape {
+    // the line segments that make up the shape
+    fn segments(&self) -> Vec<(Point2<f32>, Point2<f32>)> {
+        let (Some(&from), Some(&to)) = (self.points.first(), self.points.last()) else {
+            return vec![];
+        };
+        match self.tool {
+            Tool::Rect => {
+                let (a, b) = (Point2::new(to.x, from.y), Point2::new(from.x, to.y));
+                vec![(from, a), (a, to), (to, b), (b, from)]
+            }
+            Tool::Arrow => {
+                let shaft = to - from;
+                let length = shaft.norm();
+                if length < 1.0 {
+                    return vec![(from, to)];
+                }
+                // two barbs at 30 degrees, a quarter of the shaft long
+                let back = -shaft / length * (length * 0.25).min(24.0);
+                let (sin, cos) = 30f32.to_radians().sin_cos();
+                let rotate = |sin: f32| {
+                    Point2::new(cos * back.x - sin * back.y, sin * back.x + cos * back.y)
+                };
+                let left = to + rotate(sin).coords;
+                let right = to + rotate(-sin).coords;
+                vec![(from, to), (to, left), (to, right)]
+            }
+            Tool::Freehand => self.points.windows(2).map(|w| (w[0], w[1])).collect(),
+        }
+    }
+}
+
+pub struct Annotations {
+    pub active: bool,
+    pub tool: Tool,
+    color: usize,
+    shapes: Vec<Shape>,
+    drawing: Option<Shape>,
+}
+
+impl Annotations {
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            tool: Tool::Rect,
+            color: 0,
+            shapes: vec![],
+            drawing: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty() && self.drawing.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+        self.drawing = None;
+    }
+
+    pub fn undo(&mut self) {
+        self.shapes.pop();
+    }
+
+    pub fn next_color(&mut self) {
+        self.color = (self.color + 1) % COLORS.len();
+    }
+
+    pub fn status(&self) -> String {
+        format!(
+            "annotate: {} {}  r/a/f tool  c color  u undo",
+            COLORS[self.color].0,
+            self.tool.name()
+        )
+    }
+
+    pub fn begin(&mut self, at: Point2<f32>) {
+        self.drawing = Some(Shape {
+            tool: self.tool,
+            color: COLORS[self.color].1,
+            points: vec![at, at],
+        });
+    }
+
+    pub fn extend(&mut self, to: Point2<f32>) {
+        let Some(shape) = self.drawing.as_mut() else {
+            return;
+        };
+        match shape.tool {
+            Tool::Freehand => shape.points.push(to),
+            _ => shape.points[1] = to,
+        }
+    }
+
+    pub fn end(&mut self) {
+        self.shapes.extend(self.drawing.take());
+    }
+
+    pub fn is_drawing(&self) -> bool {
+        self.drawing.is_some()
+    }
+
+    fn all(&self) -> impl Iterator<Item = &Shape> {
+        self.shapes.iter().chain(&self.drawing)
+    }
+
+    // Draws the shapes over an image shown with `transform`, scaling the
+    // strokes with the zoom.
+    pub fn draw(&self, canvas: &mut Canvas, image: &Image<Pixel>, transform: &Matrix3<f32>) {
+        let width = stroke_width(image) * transform[(0, 0)];
+        for shape in self.all() {
+            let [r, g, b, _] = shape.color.to_bytes();
+            let color = u32::from_be_bytes([0, r, g, b]);
+            for (from, to) in shape.segments() {
+                let from = transform.transform_point(&from);
+                let to = transform.transform_point(&to);
+                line(from, to, width, |x, y| canvas.put(x, y, color));
+            }
+        }
+    }
+
+    // The image with the shapes burnt in, for saving.
+    pub fn composite(&self, image: &Image<Pixel>) -> Image<Pixel> {
+        let mut out = Image {
+            width: image.width,
+            height: image.height,
+            pixels: image.pixels.clone(),
+        };
+        let width = stroke_width(image);
+        for shape in self.all() {
+            for (from, to) in shape.segments() {
+                line(from, to, width, |x, y| {
+                    // negative coordinates wrap around to far outside the image
+                    if let Some(pixel) = out.get_mut(x as usize, y as usize) {
+                        *pixel = shape.color;
+                    }
+                });
+            }
+        }
+        out
+    }
+}
+
+// thick enough to be seen on a screenshot, in image pixels
+fn stroke_width(image: &Image<Pixel>) -> f32 {
+    (image.width.max(image.height) as f32 / 400.0).max(2.0)
+}
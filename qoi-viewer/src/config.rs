@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+// Settings kept between runs, as `key = value` lines in
+// `$XDG_CONFIG_HOME/qoi-viewer/config` (or the platform's equivalent).
+#[derive(Default)]
+pub struct Config {
+    // 1-based, in winit's order; the monitor to go fullscreen on
+    pub monitor: Option<usize>,
+}
+
+fn path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| std::env::var_os("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("qoi-viewer").join("config"))
+}
+
+impl Config {
+    // A missing or unreadable file gives the defaults; unknown keys are skipped.
+    pub fn load() -> Self {
+        let mut config = Config::default();
+        let Some(text) = path().and_then(|path| std::fs::read_to_string(path).ok()) else {
+            return config;
+        };
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            if key.trim() == "monitor" {
+                config.monitor = value.trim().parse().ok().filter(|&n| n > 0);
+            }
+        }
+        config
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = path().ok_or("no config directory")?;
+        let mut text = String::new();
+        if let Some(monitor) = self.monitor {
+            text += &format!("monitor = {monitor}\n");
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+        }
+        std::fs::write(&path, text).map_err(|err| format!("{}: {err}", path.display()))
+    }
+}
@@ -0,0 +1,92 @@
+use nalgebra::Point2;
+
+use qoi_rs::shared::SharedImage;
+use qoi_rs::{Pixel, PixelOrder, convert};
+
+pub struct Canvas<'a> {
+    pub pixels: &'a mut [u32],
+    pub width: usize,
+    pub height: usize,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(pixels: &'a mut [u32], width: usize, height: usize) -> Self {
+        Self {
+            pixels,
+            width,
+            height,
+        }
+    }
+
+    pub fn put(&mut self, x: isize, y: isize, color: u32) {
+        if x < 0 || y < 0 || x >= self.width as isize || y >= self.height as isize {
+            return;
+        }
+        self.pixels[y as usize * self.width + x as usize] = color;
+    }
+
+    pub fn fill_rect(&mut self, x: isize, y: isize, w: usize, h: usize, color: u32) {
+        let x0 = x.clamp(0, self.width as isize) as usize;
+        let y0 = y.clamp(0, self.height as isize) as usize;
+        let x1 = (x + w as isize).clamp(0, self.width as isize) as usize;
+        let y1 = (y + h as isize).clamp(0, self.height as isize) as usize;
+
+        for row in y0..y1 {
+            self.pixels[row * self.width + x0..row * self.width + x1].fill(color);
+        }
+    }
+
+    // darkens to a quarter, for text drawn over the image to stay readable
+    pub fn dim_rect(&mut self, x: isize, y: isize, w: usize, h: usize) {
+        let x0 = x.clamp(0, self.width as isize) as usize;
+        let y0 = y.clamp(0, self.height as isize) as usize;
+        let x1 = (x + w as isize).clamp(0, self.width as isize) as usize;
+        let y1 = (y + h as isize).clamp(0, self.height as isize) as usize;
+
+        for row in y0..y1 {
+            for px in &mut self.pixels[row * self.width + x0..row * self.width + x1] {
+                *px = (*px >> 2) & 0x3f3f3f;
+            }
+        }
+    }
+
+    pub fn blit(&mut self, x: isize, y: isize, image: &SharedImage<Pixel>) {
+        // the part of the image that lands on the canvas, converted a row at a time
+        let x0 = (-x).clamp(0, image.width as isize) as usize;
+        let x1 = (self.width as isize - x).clamp(x0 as isize, image.width as isize) as usize;
+        if x0 == x1 {
+            return;
+        }
+        for (iy, row) in image.pixels.chunks_exact(image.width).enumerate() {
+            let cy = y + iy as isize;
+            if cy < 0 || cy >= self.height as isize {
+                continue;
+            }
+            let start = cy as usize * self.width + (x + x0 as isize) as usize;
+            let dst = &mut self.pixels[start..start + x1 - x0];
+            convert::pixels_to_u32(&row[x0..x1], dst, PixelOrder::Xrgb);
+        }
+    }
+}
+
+// Stamps a square brush of `width` along the line from `from` to `to`.
+pub fn line(from: Point2<f32>, to: Point2<f32>, width: f32, mut put: impl FnMut(isize, isize)) {
+    let width = width.max(1.0);
+    let half = (width / 2.0) as isize;
+    let size = width as isize;
+    let steps = (to - from).abs().max().ceil().max(1.0) as usize;
+    let mut last = None;
+    for i in 0..=steps {
+        let point = from + (to - from) * (i as f32 / steps as f32);
+        let (cx, cy) = (point.x.round() as isize, point.y.round() as isize);
+        if last == Some((cx, cy)) {
+            continue;
+        }
+        last = Some((cx, cy));
+        for y in cy - half..cy - half + size {
+            for x in cx - half..cx - half + size {
+                put(x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use winit::event_loop::EventLoopProxy;
+
+use crate::ViewerEvent;
+
+// A written file is only shown once no events have arrived for this long, so
+// it is not read half way through.
+const SETTLE: Duration = Duration::from_millis(250);
+
+fn is_qoi(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("qoi"))
+}
+
+// the most recently modified QOI file in `dir`
+pub fn newest(dir: &Path) -> Option<PathBuf> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| is_qoi(path))
+        .max_by_key(|path| path.metadata().and_then(|m| m.modified()).ok())
+}
+
+// Sends the last QOI file written to `dir` to the event loop whenever writing
+// settles. The watcher stops when it is dropped.
+pub fn watch(dir: &Path, proxy: EventLoopProxy<ViewerEvent>) -> Result<RecommendedWatcher, String> {
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(|err| err.to_string())?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(|err| format!("{}: {err}", dir.display()))?;
+
+    thread::spawn(move || {
+        let mut pending = None;
+        loop {
+            let timeout = if pending.is_some() {
+                SETTLE
+            } else {
+                Duration::MAX
+            };
+            match events.recv_timeout(timeout) {
+                Ok(Ok(event)) => {
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        pending = event
+                            .paths
+                            .into_iter()
+                            .rfind(|path| is_qoi(path))
+                            .or(pending);
+                    }
+                }
+                Ok(Err(err)) => eprintln!("{err}"),
+                Err(RecvTimeoutError::Timeout) => {
+                    let path = pending.take().expect("only waiting with a file pending");
+                    if proxy.send_event(ViewerEvent::Written(path)).is_err() {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+
+    Ok(watcher)
+}
@@ -0,0 +1,292 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use winit::event_loop::EventLoopProxy;
+
+use qoi_rs::shared::SharedImage;
+use qoi_rs::{Decoder, Image, Pixel};
+
+use crate::ViewerEvent;
+use crate::draw::Canvas;
+
+const THUMBNAIL_SIZE: usize = 160;
+const PADDING: usize = 12;
+const TILE_SIZE: usize = THUMBNAIL_SIZE + 2 * PADDING;
+
+// the filmstrip along the bottom of the single image view
+const STRIP_SIZE: usize = 64;
+const STRIP_PADDING: usize = 6;
+const STRIP_TILE: usize = STRIP_SIZE + 2 * STRIP_PADDING;
+pub const STRIP_HEIGHT: usize = STRIP_TILE;
+
+const BACKGROUND: u32 = 0x202020;
+const PLACEHOLDER: u32 = 0x383838;
+const SELECTION: u32 = 0x3d7eff;
+
+#[derive(Clone, Copy, Default)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Mtime,
+    Size,
+}
+
+impl SortKey {
+    pub fn parse(key: &str) -> Option<Self> {
+        match key {
+            "name" => Some(Self::Name),
+            "mtime" => Some(Self::Mtime),
+            "size" => Some(Self::Size),
+            _ => None,
+        }
+    }
+}
+
+pub struct Gallery {
+    pub entries: Vec<PathBuf>,
+    pub selected: usize,
+    thumbnails: Vec<Option<SharedImage<Pixel>>>,
+    // the thumbnails scaled down once more for the filmstrip, or the same
+    // pixels when they already fit
+    strip: Vec<Option<SharedImage<Pixel>>>,
+    requested: Vec<bool>,
+    requests: Sender<(usize, PathBuf)>,
+    scroll: usize,
+    columns: usize,
+}
+
+impl Gallery {
+    pub fn open(
+        dir: &Path,
+        sort: SortKey,
+        reverse: bool,
+        proxy: EventLoopProxy<ViewerEvent>,
+    ) -> io::Result<Self> {
+        let mut entries = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("qoi"))
+            })
+            .collect::<Vec<_>>();
+
+        match sort {
+            SortKey::Name => entries.sort(),
+            SortKey::Mtime => entries.sort_by_cached_key(|path| {
+                (
+                    path.metadata().and_then(|m| m.modified()).ok(),
+                    path.clone(),
+                )
+            }),
+            SortKey::Size => entries
+                .sort_by_cached_key(|path| (path.metadata().map(|m| m.len()).ok(), path.clone())),
+        }
+        if reverse {
+            entries.reverse();
+        }
+
+        Ok(Self {
+            thumbnails: entries.iter().map(|_| None).collect(),
+            strip: entries.iter().map(|_| None).collect(),
+            requested: vec![false; entries.len()],
+            entries,
+            selected: 0,
+            requests: spawn_worker(proxy),
+            scroll: 0,
+            columns: 1,
+        })
+    }
+
+    pub fn selected_path(&self) -> Option<&Path> {
+        self.entries.get(self.selected).map(PathBuf::as_path)
+    }
+
+    pub fn set_thumbnail(&mut self, index: usize, thumbnail: Image<Pixel>) {
+        let strip = (thumbnail.width.max(thumbnail.height) > STRIP_SIZE)
+            .then(|| thumbnail.thumbnail(STRIP_SIZE).share());
+        let thumbnail = thumbnail.share();
+        if let Some(slot) = self.strip.get_mut(index) {
+            *slot = Some(strip.unwrap_or_else(|| thumbnail.clone()));
+        }
+        if let Some(slot) = self.thumbnails.get_mut(index) {
+            *slot = Some(thumbnail);
+        }
+    }
+
+    pub fn step(&mut self, offset: isize) -> Option<&Path> {
+        let index = self.selected.checked_add_signed(offset)?;
+        if index >= self.entries.len() {
+            return None;
+        }
+        self.selected = index;
+        self.selected_path()
+    }
+
+    pub fn move_selection(&mut self, dx: isize, dy: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let offset = dx + dy * self.columns as isize;
+        let last = self.entries.len() as isize - 1;
+        self.selected = (self.selected as isize + offset).clamp(0, last) as usize;
+    }
+
+    pub fn scroll_by(&mut self, delta: f32) {
+        let scroll = self.scroll as f32 - delta * TILE_SIZE as f32 / 2.0;
+        self.scroll = scroll.max(0.0) as usize;
+    }
+
+    pub fn select_at(&mut self, x: f64, y: f64) {
+        let column = x as usize / TILE_SIZE;
+        let row = (y as usize + self.scroll) / TILE_SIZE;
+        let index = row * self.columns + column;
+        if column < self.columns && index < self.entries.len() {
+            self.selected = index;
+        }
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index < self.entries.len() {
+            self.selected = index;
+        }
+    }
+
+    // The entry under `x` in a filmstrip `width` pixels wide.
+    pub fn filmstrip_at(&self, x: f64, width: usize) -> Option<usize> {
+        let offset = (x - strip_center(width) as f64) / STRIP_TILE as f64;
+        let index = self.selected.checked_add_signed(offset.floor() as isize)?;
+        (index < self.entries.len()).then_some(index)
+    }
+
+    // Draws a row of thumbnails along the bottom of the canvas with the
+    // selected entry in the middle.
+    pub fn draw_filmstrip(&mut self, canvas: &mut Canvas) {
+        let top = canvas.height as isize - STRIP_HEIGHT as isize;
+        canvas.fill_rect(0, top, canvas.width, STRIP_HEIGHT, BACKGROUND);
+
+        let center = strip_center(canvas.width);
+        let before = center.div_ceil(STRIP_TILE);
+        let after = (canvas.width - center).div_ceil(STRIP_TILE);
+        let first = self.selected.saturating_sub(before);
+        let last = (self.selected + after).min(self.entries.len());
+        for index in first..last {
+            let x =
+                center as isize + (index as isize - self.selected as isize) * STRIP_TILE as isize;
+            if index == self.selected {
+                canvas.fill_rect(x, top, STRIP_TILE, STRIP_TILE, SELECTION);
+            }
+
+            let (x, y) = (x + STRIP_PADDING as isize, top + STRIP_PADDING as isize);
+            match &self.strip[index] {
+                Some(thumbnail) => {
+                    let x = x + ((STRIP_SIZE - thumbnail.width) / 2) as isize;
+                    let y = y + ((STRIP_SIZE - thumbnail.height) / 2) as isize;
+                    canvas.blit(x, y, thumbnail);
+                }
+                None => {
+                    canvas.fill_rect(x, y, STRIP_SIZE, STRIP_SIZE, PLACEHOLDER);
+                    self.request(index);
+                }
+            }
+        }
+    }
+
+    // asks the worker for a thumbnail, once
+    fn request(&mut self, index: usize) {
+        if !self.requested[index] {
+            self.requested[index] = true;
+            let _ = self.requests.send((index, self.entries[index].clone()));
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas) {
+        let (width, height) = (canvas.width, canvas.height);
+        canvas.pixels.fill(BACKGROUND);
+
+        self.columns = (width / TILE_SIZE).max(1);
+        let rows = self.entries.len().div_ceil(self.columns);
+
+        // keep the selected tile in view
+        let selected_top = self.selected / self.columns * TILE_SIZE;
+        if selected_top < self.scroll {
+            self.scroll = selected_top;
+        } else if selected_top + TILE_SIZE > self.scroll + height {
+            self.scroll = (selected_top + TILE_SIZE).saturating_sub(height);
+        }
+        self.scroll = self.scroll.min((rows * TILE_SIZE).saturating_sub(height));
+
+        let first_row = self.scroll / TILE_SIZE;
+        let last_row = ((self.scroll + height) / TILE_SIZE + 1).min(rows);
+
+        for row in first_row..last_row {
+            for column in 0..self.columns {
+                let index = row * self.columns + column;
+                if index >= self.entries.len() {
+                    break;
+                }
+
+                let x = (column * TILE_SIZE) as isize;
+                let y = (row * TILE_SIZE) as isize - self.scroll as isize;
+
+                if index == self.selected {
+                    canvas.fill_rect(x, y, TILE_SIZE, TILE_SIZE, SELECTION);
+                }
+
+                let (x, y) = (x + PADDING as isize, y + PADDING as isize);
+                match &self.thumbnails[index] {
+                    Some(thumbnail) => {
+                        // center the thumbnail within its tile
+                        let x = x + ((THUMBNAIL_SIZE - thumbnail.width) / 2) as isize;
+                        let y = y + ((THUMBNAIL_SIZE - thumbnail.height) / 2) as isize;
+                        canvas.blit(x, y, thumbnail);
+                    }
+                    None => {
+                        canvas.fill_rect(x, y, THUMBNAIL_SIZE, THUMBNAIL_SIZE, PLACEHOLDER);
+                        self.request(index);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the left edge of the selected tile in a filmstrip `width` pixels wide
+fn strip_center(width: usize) -> usize {
+    width.saturating_sub(STRIP_TILE) / 2
+}
+
+fn spawn_worker(proxy: EventLoopProxy<ViewerEvent>) -> Sender<(usize, PathBuf)> {
+    let (sender, receiver) = mpsc::channel::<(usize, PathBuf)>();
+
+    thread::spawn(move || {
+        for (index, path) in receiver {
+            let Ok(file) = std::fs::read(&path) else {
+                continue;
+            };
+            let Ok((width, height)) = Decoder::dimensions(&file) else {
+                continue;
+            };
+            let factor = (width.max(height) / THUMBNAIL_SIZE).max(1);
+            let Ok(image) = Decoder::new().decode_subsampled(&file, factor) else {
+                continue;
+            };
+
+            // small images are passed on as they are rather than copied
+            let thumbnail = if image.width.max(image.height) > THUMBNAIL_SIZE {
+                image.thumbnail(THUMBNAIL_SIZE)
+            } else {
+                image
+            };
+            if proxy
+                .send_event(ViewerEvent::Thumbnail(index, thumbnail))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    sender
+}
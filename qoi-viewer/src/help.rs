@@ -0,0 +1,73 @@
+use crate::draw::Canvas;
+use crate::text::{GLYPH_SIZE, draw_text, text_width};
+
+const KEY_COLOR: u32 = 0x00e0ff;
+const TEXT_COLOR: u32 = 0xe0e0e0;
+const PADDING: usize = 16;
+const LINE_HEIGHT: usize = GLYPH_SIZE + 4;
+// the descriptions start this many glyphs in
+const KEY_COLUMN: usize = 16;
+
+const SHORTCUTS: &[(&str, &str)] = &[
+    ("? / F1", "show or hide this help"),
+    ("Ctrl+O", "open a file"),
+    ("Ctrl+V", "paste an image from the clipboard"),
+    ("Ctrl+S", "save the annotated or pasted image"),
+    ("Ctrl+Shift+S", "save the image as a new file"),
+    ("Ctrl+[ / ]", "rotate left / right and save"),
+    ("Ctrl+H / J", "flip horizontally / vertically and save"),
+    ("F11", "fullscreen on or off"),
+    ("Shift+F11", "fullscreen on the next monitor"),
+    ("F12", "save a screenshot of the window"),
+    ("wheel / drag", "zoom / pan"),
+    ("right-drag, z", "zoom to a rectangle"),
+    ("Space", "reset zoom and pan"),
+    ("Left / Right", "previous / next file in the directory"),
+    ("Enter", "open the selected thumbnail"),
+    ("Escape", "leave the mode, back to the thumbnails, quit"),
+    ("t", "filmstrip of the neighbouring files"),
+    ("n", "annotate: r/a/f tool, c color, u undo"),
+    ("m", "measure between two pixels"),
+    ("[ / ]", "exposure down / up"),
+    ("- / =", "gamma down / up"),
+    ("0", "reset exposure and gamma"),
+    ("l", "show linear images raw or as sRGB"),
+    ("h", "heat map of the ops that stored each pixel"),
+    ("p", "pause or play an animation"),
+    (", / .", "previous / next frame"),
+    ("w", "set the image as the wallpaper"),
+];
+
+// Draws the shortcuts, followed by the state of each mode, on a dimmed box in
+// the middle of the canvas.
+pub fn draw(canvas: &mut Canvas, modes: &[(&str, String)]) {
+    let lines = SHORTCUTS
+        .iter()
+        .map(|&(key, text)| (key, text.to_string()))
+        .chain([("", String::new())])
+        .chain(modes.iter().cloned())
+        .collect::<Vec<_>>();
+    let width = lines
+        .iter()
+        .map(|(_, text)| KEY_COLUMN * GLYPH_SIZE + text_width(text))
+        .max()
+        .unwrap_or(0)
+        + 2 * PADDING;
+    let height = lines.len() * LINE_HEIGHT + 2 * PADDING;
+
+    let x = (canvas.width as isize - width as isize) / 2;
+    let y = (canvas.height as isize - height as isize).max(0) / 2;
+    canvas.dim_rect(x, y, width, height);
+    for (i, (key, text)) in lines.iter().enumerate() {
+        let ly = y + (PADDING + i * LINE_HEIGHT) as isize;
+        let lx = x + PADDING as isize;
+        draw_text(canvas, lx, ly, key, KEY_COLOR);
+        draw_text(
+            canvas,
+            lx + (KEY_COLUMN * GLYPH_SIZE) as isize,
+            ly,
+            text,
+            TEXT_COLOR,
+        );
+    }
+}
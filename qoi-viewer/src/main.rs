@@ -1,39 +1,573 @@
 use std::error::Error;
 use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use nalgebra::{Matrix2x1, Matrix3, Point2};
-use softbuffer::{Buffer, Context, Surface};
+use softbuffer::{Context, Surface};
 use winit::application::ApplicationHandler;
 use winit::dpi::PhysicalPosition;
-use winit::event::{KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::event::{KeyEvent, Modifiers, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop, OwnedDisplayHandle};
 use winit::keyboard::{Key, NamedKey};
-use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
-use winit::window::{Window, WindowId};
+use winit::window::{Fullscreen, Window, WindowId};
 
-use qoi_rs::{Decoder, Image, Pixel};
+use qoi_rs::anim::AnimReader;
+use qoi_rs::ops;
+use qoi_rs::{Decoder, Encoder, Header, Image, Pixel};
+
+use adjust::Adjustments;
+use annotate::{Annotations, Tool};
+use config::Config;
+use draw::Canvas;
+use gallery::{Gallery, STRIP_HEIGHT, SortKey};
+use measure::Ruler;
+use playback::Playback;
+use zoom::ZoomBox;
+
+mod adjust;
+mod annotate;
+mod config;
+mod draw;
+mod follow;
+mod gallery;
+mod help;
+mod measure;
+mod playback;
+mod text;
+mod wallpaper;
+mod zoom;
+
+const TEXT_COLOR: u32 = 0xe0e0e0;
+const ERROR_COLOR: u32 = 0xff6060;
+
+pub enum ViewerEvent {
+    Thumbnail(usize, Image<Pixel>),
+    // the newest file in the directory given with --watch-dir
+    Written(PathBuf),
+}
+
+// what a file holds: an image, or an animation shown from its first frame
+struct Loaded {
+    image: Image<Pixel>,
+    header: Option<Header>,
+    path: Option<PathBuf>,
+    playback: Option<Playback>,
+    // what went wrong in a damaged file, of which only the start was decoded
+    corrupt: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum View {
+    Single,
+    Gallery,
+}
 
 struct App {
     window: Option<Window>,
     context: Option<Context<OwnedDisplayHandle>>,
     image: Image<Pixel>,
+    // the file the image came from and its header, for writing it back
+    path: Option<PathBuf>,
+    header: Option<Header>,
+    playback: Option<Playback>,
     transform: Matrix3<f32>,
     saved_transform: Matrix3<f32>,
     cursor: Option<(f64, f64)>,
     saved: Option<(f64, f64)>,
+    modifiers: Modifiers,
+    pasted: bool,
+    view: View,
+    gallery: Option<Gallery>,
+    message: Option<String>,
+    adjustments: Adjustments,
+    annotations: Annotations,
+    ruler: Ruler,
+    zoom_box: ZoomBox,
+    filmstrip: bool,
+    // the image is stored linear (colorspace 1), and whether to show it as is
+    linear: bool,
+    raw_linear: bool,
+    help: bool,
+    // which op made each pixel, shown in place of the image
+    heatmap: Option<Image<Pixel>>,
+    // shown across the top while a damaged file is open
+    corrupt: Option<String>,
+    // 1-based, the monitor F11 goes fullscreen on; None for the window's own
+    monitor: Option<usize>,
+    // with --watch-dir, the file being shown and the watcher, which has to be kept
+    followed: Option<PathBuf>,
+    watcher: Option<notify::RecommendedWatcher>,
+    screenshot: bool,
 }
 
 impl App {
-    fn new(image: Image<Pixel>) -> Self {
+    fn new(image: Image<Pixel>, gallery: Option<Gallery>) -> Self {
         Self {
             window: None,
             context: None,
             image,
+            path: None,
+            header: None,
+            playback: None,
             transform: Matrix3::<f32>::identity(),
             saved_transform: Matrix3::<f32>::identity(),
             cursor: None,
             saved: None,
+            modifiers: Modifiers::default(),
+            pasted: false,
+            view: if gallery.is_some() {
+                View::Gallery
+            } else {
+                View::Single
+            },
+            gallery,
+            message: None,
+            adjustments: Adjustments::new(),
+            annotations: Annotations::new(),
+            ruler: Ruler::new(),
+            zoom_box: ZoomBox::new(),
+            filmstrip: false,
+            linear: false,
+            raw_linear: false,
+            help: false,
+            heatmap: None,
+            corrupt: None,
+            monitor: None,
+            followed: None,
+            watcher: None,
+            screenshot: false,
+        }
+    }
+
+    fn show_message(&mut self, message: String) {
+        println!("{message}");
+        self.message = Some(message);
+        self.redraw();
+    }
+
+    fn show_image(&mut self, loaded: Loaded) {
+        self.set_colorspace(loaded.header.map_or(0, |header| header.colorspace));
+        self.image = loaded.image;
+        self.path = loaded.path;
+        self.header = loaded.header;
+        self.playback = loaded.playback;
+        self.corrupt = loaded.corrupt;
+        self.heatmap = None;
+        self.update_title();
+        self.pasted = false;
+        self.message = None;
+        self.annotations.clear();
+        self.ruler.clear();
+        self.view = View::Single;
+        self.redraw();
+    }
+
+    // replaces the image with the animation's current frame
+    fn show_frame(&mut self) {
+        let Some(playback) = self.playback.as_mut() else {
+            return;
+        };
+        match playback.decode(&self.image) {
+            Ok(image) => self.image = image,
+            Err(err) => self.message = Some(err),
+        }
+        self.update_title();
+        self.redraw();
+    }
+
+    fn update_title(&self) {
+        let Some(window) = self.window.as_ref() else {
+            return;
+        };
+        let title = match &self.playback {
+            Some(playback) => format!(
+                "qoi-viewer - frame {}/{}{}",
+                playback.frame + 1,
+                playback.len(),
+                if playback.paused { " (paused)" } else { "" }
+            ),
+            None => "qoi-viewer".into(),
+        };
+        window.set_title(&title);
+    }
+
+    fn set_colorspace(&mut self, colorspace: u8) {
+        self.linear = colorspace == 1;
+        self.adjustments.set_linear(self.linear && !self.raw_linear);
+    }
+
+    // the image coordinates under the cursor
+    fn cursor_in_image(&self) -> Option<Point2<f32>> {
+        let (x, y) = self.cursor?;
+        let inv = self.transform.try_inverse()?;
+        Some(inv.transform_point(&Point2::new(x as f32, y as f32)))
+    }
+
+    fn status_text(&self) -> String {
+        let zoom = self.transform[(0, 0)] * 100.0;
+        let mut status = format!("{zoom:.0}%");
+
+        let cursor = self
+            .cursor_in_image()
+            .filter(|pt| pt.x >= 0.0 && pt.y >= 0.0)
+            .map(|pt| (pt.x as usize, pt.y as usize));
+        if let Some((x, y)) = cursor.filter(|&(x, y)| x < self.image.width && y < self.image.height)
+        {
+            let Pixel { r, g, b, a } = self.image.pixels[y * self.image.width + x];
+            status += &format!("  {x},{y}  rgba({r}, {g}, {b}, {a})");
+        }
+        if !self.adjustments.is_identity() {
+            let Adjustments {
+                exposure, gamma, ..
+            } = self.adjustments;
+            status += &format!("  exposure {exposure:+.1}  gamma {gamma:.1}");
+        }
+        if self.linear {
+            status += match self.raw_linear {
+                true => "  linear, shown raw",
+                false => "  linear->sRGB",
+            };
+        }
+        if let Some(name) = self.followed.as_ref().and_then(|path| path.file_name()) {
+            status += &format!("  newest: {}", name.to_string_lossy());
+        }
+        if self.annotations.active {
+            status += &format!("  {}", self.annotations.status());
+        }
+        if self.ruler.active {
+            status += &format!("  {}", self.ruler.status());
+        }
+        if self.zoom_box.active {
+            status += "  zoom: drag a rectangle to zoom to";
+        }
+        if self.heatmap.is_some() {
+            status += "  ops: RUN green, INDEX blue, DIFF yellow, LUMA orange, RGB red";
+        }
+
+        status
+    }
+
+    // for the help overlay
+    fn mode_states(&self) -> Vec<(&'static str, String)> {
+        let on_off = |on: bool| if on { "on" } else { "off" }.to_string();
+        let colorspace = match (self.linear, self.raw_linear) {
+            (false, _) => "sRGB",
+            (true, false) => "linear, shown as sRGB",
+            (true, true) => "linear, shown raw",
+        };
+        let animation = match &self.playback {
+            Some(playback) => format!(
+                "frame {}/{}{}",
+                playback.frame + 1,
+                playback.len(),
+                if playback.paused { ", paused" } else { "" }
+            ),
+            None => "none".into(),
+        };
+        let Adjustments {
+            exposure, gamma, ..
+        } = self.adjustments;
+        let fullscreen = self.window.as_ref().and_then(Window::fullscreen);
+        let fullscreen = match (fullscreen, self.monitor) {
+            (None, _) => "off".into(),
+            (Some(_), Some(n)) => format!("on, monitor {n}"),
+            (Some(_), None) => "on".into(),
+        };
+        vec![
+            ("annotate", on_off(self.annotations.active)),
+            ("measure", on_off(self.ruler.active)),
+            ("zoom box", on_off(self.zoom_box.active)),
+            ("filmstrip", on_off(self.filmstrip)),
+            ("heat map", on_off(self.heatmap.is_some())),
+            ("fullscreen", fullscreen),
+            ("colorspace", colorspace.into()),
+            ("exposure", format!("{exposure:+.1}")),
+            ("gamma", format!("{gamma:.1}")),
+            ("animation", animation),
+        ]
+    }
+
+    fn open_selected(&mut self) {
+        let Some(path) = self.gallery.as_ref().and_then(Gallery::selected_path) else {
+            return;
+        };
+        match load_image(path) {
+            Ok(loaded) => {
+                self.transform = Matrix3::identity();
+                self.show_image(loaded);
+            }
+            Err(err) => {
+                self.view = View::Single;
+                self.show_message(err);
+            }
+        }
+    }
+
+    fn step(&mut self, offset: isize) {
+        let Some(path) = self
+            .gallery
+            .as_mut()
+            .and_then(|gallery| gallery.step(offset))
+        else {
+            return;
+        };
+        match load_image(path) {
+            Ok(loaded) => self.show_image(loaded),
+            Err(err) => self.show_message(err),
+        }
+    }
+
+    fn gallery_key(&mut self, event_loop: &ActiveEventLoop, key: Key) {
+        let Some(gallery) = self.gallery.as_mut() else {
+            return;
+        };
+
+        match key {
+            Key::Named(NamedKey::Escape) => event_loop.exit(),
+            Key::Named(NamedKey::Enter) => self.open_selected(),
+            Key::Named(NamedKey::ArrowLeft) => gallery.move_selection(-1, 0),
+            Key::Named(NamedKey::ArrowRight) => gallery.move_selection(1, 0),
+            Key::Named(NamedKey::ArrowUp) => gallery.move_selection(0, -1),
+            Key::Named(NamedKey::ArrowDown) => gallery.move_selection(0, 1),
+            _ => return,
+        }
+        self.redraw();
+    }
+
+    // Shows a file written to the watched directory, keeping zoom and pan. A
+    // file that does not decode is most likely still being written, and the
+    // next event for it will bring it in.
+    fn follow(&mut self, path: PathBuf) {
+        if let Ok(loaded) = load_image(&path)
+            && loaded.corrupt.is_none()
+        {
+            self.show_image(loaded);
+            self.followed = Some(path);
+        }
+    }
+
+    fn paste(&mut self) {
+        let pasted = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_image());
+        let data = match pasted {
+            Ok(data) => data,
+            Err(err) => {
+                self.show_message(format!("clipboard has no image: {err}"));
+                return;
+            }
+        };
+
+        let pixels = data
+            .bytes
+            .chunks_exact(4)
+            .map(|px| Pixel::new(px[0], px[1], px[2], px[3]))
+            .collect::<Vec<_>>();
+
+        self.transform = Matrix3::identity();
+        let image = Image {
+            width: data.width,
+            height: data.height,
+            pixels,
+        };
+        self.show_image(Loaded {
+            image,
+            header: None,
+            path: None,
+            playback: None,
+            corrupt: None,
+        });
+        self.pasted = true;
+    }
+
+    fn open(&mut self) {
+        let Some(path) = pick_file() else {
+            return;
+        };
+        match load_image(&path) {
+            Ok(loaded) => {
+                self.transform = Matrix3::identity();
+                self.show_image(loaded);
+            }
+            Err(err) => self.show_message(err),
+        }
+    }
+
+    fn save_pasted(&mut self) {
+        if !self.pasted {
+            return;
+        }
+
+        let filename = format!("pasted-{}.qoi", timestamp());
+        self.show_message(save_image(&filename, &self.image));
+    }
+
+    fn save_annotated(&mut self) {
+        let filename = format!("annotated-{}.qoi", timestamp());
+        let image = self.annotations.composite(&self.image);
+        self.show_message(save_image(&filename, &image));
+    }
+
+    // Rotates or flips the pixels, which is lossless, and writes the result
+    // back to the file the image came from.
+    fn transform_pixels(&mut self, op: fn(&Image<Pixel>) -> Image<Pixel>) {
+        if self.view == View::Gallery {
+            return;
+        }
+        if self.playback.is_some() {
+            self.show_message("animations cannot be rotated or flipped".into());
+            return;
+        }
+        if self.heatmap.is_some() {
+            self.show_message("hide the heat map to rotate or flip the image".into());
+            return;
+        }
+        self.image = op(&self.image);
+        self.annotations.clear();
+        self.ruler.clear();
+        match self.path.as_deref() {
+            // the padding of a partly decoded file must not replace what is left of it
+            Some(_) if self.corrupt.is_some() => self.save_as(),
+            Some(path) => {
+                let message = write_image(path, &self.image, self.header);
+                self.show_message(message);
+            }
+            None => self.redraw(),
+        }
+    }
+
+    fn save_as(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("QOI image", &["qoi"])
+            .save_file()
+        else {
+            return;
+        };
+        let message = write_image(&path, &self.image, self.header);
+        self.path = Some(path);
+        self.show_message(message);
+    }
+
+    // the filmstrip entry under the cursor, if it is shown
+    fn filmstrip_at_cursor(&self) -> Option<usize> {
+        let (gallery, window) = (self.gallery.as_ref()?, self.window.as_ref()?);
+        let (x, y) = self.cursor?;
+        let size = window.inner_size();
+        if !self.filmstrip || y < size.height.saturating_sub(STRIP_HEIGHT as u32) as f64 {
+            return None;
+        }
+        gallery.filmstrip_at(x, size.width as usize)
+    }
+
+    // Shows or hides the ops of the file the image came from.
+    fn toggle_heatmap(&mut self) {
+        if self.heatmap.take().is_some() {
+            self.redraw();
+            return;
+        }
+        let Some(path) = self.path.as_deref().filter(|_| self.playback.is_none()) else {
+            self.show_message("only QOI files have a heat map".into());
+            return;
+        };
+        let heatmap = std::fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|data| ops::heatmap(&data).map_err(|err| err.to_string()));
+        match heatmap {
+            Ok(heatmap) => {
+                self.heatmap = Some(heatmap);
+                self.redraw();
+            }
+            Err(err) => self.show_message(format!("{}: {err}", path.display())),
+        }
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let Some(window) = self.window.as_ref() else {
+            return;
+        };
+        if window.fullscreen().is_some() {
+            window.set_fullscreen(None);
+            return;
+        }
+        // a monitor that has gone away falls back to the window's
+        let monitor = self
+            .monitor
+            .and_then(|n| window.available_monitors().nth(n - 1));
+        window.set_fullscreen(Some(Fullscreen::Borderless(monitor)));
+    }
+
+    // Moves fullscreen on to the next monitor, or picks it for the next time,
+    // and remembers the choice.
+    fn next_monitor(&mut self) {
+        let Some(window) = self.window.as_ref() else {
+            return;
+        };
+        let monitors = window.available_monitors().collect::<Vec<_>>();
+        if monitors.is_empty() {
+            return;
+        }
+        let current = self
+            .monitor
+            .filter(|&n| n <= monitors.len())
+            .or_else(|| {
+                let current = window.current_monitor()?;
+                monitors.iter().position(|m| *m == current).map(|i| i + 1)
+            })
+            .unwrap_or(1);
+        let next = current % monitors.len() + 1;
+        self.monitor = Some(next);
+
+        let monitor = &monitors[next - 1];
+        if window.fullscreen().is_some() {
+            window.set_fullscreen(Some(Fullscreen::Borderless(Some(monitor.clone()))));
+        }
+        let name = monitor
+            .name()
+            .map_or(String::new(), |name| format!(" ({name})"));
+        let mut message = format!("monitor {next} of {}{name}", monitors.len());
+        let config = Config {
+            monitor: self.monitor,
+        };
+        if let Err(err) = config.save() {
+            message += &format!(", not saved: {err}");
+        }
+        self.show_message(message);
+    }
+
+    fn set_wallpaper(&mut self) {
+        let message = match wallpaper::set(&self.image, timestamp()) {
+            Ok(path) => format!("set {} as the wallpaper", path.display()),
+            Err(err) => err,
+        };
+        self.show_message(message);
+    }
+
+    // starts or ends a drag of the rectangle to zoom to
+    fn zoom_drag(&mut self, pressed: bool) {
+        let (Some(window), Some((x, y))) = (self.window.as_ref(), self.cursor) else {
+            return;
+        };
+        if pressed {
+            self.zoom_box.begin(Point2::new(x as f32, y as f32));
+        } else {
+            let size = window.inner_size();
+            let (width, height) = (size.width as f32, size.height as f32);
+            if let Some(transform) = self.zoom_box.finish(&self.transform, width, height) {
+                self.transform = transform;
+            }
+        }
+        self.redraw();
+    }
+
+    fn annotation_key(&mut self, key: &str) {
+        match key {
+            "r" => self.annotations.tool = Tool::Rect,
+            "a" => self.annotations.tool = Tool::Arrow,
+            "f" => self.annotations.tool = Tool::Freehand,
+            "c" => self.annotations.next_color(),
+            "u" => self.annotations.undo(),
+            _ => return,
         }
+        self.redraw();
     }
 
     fn redraw(&self) {
@@ -43,31 +577,172 @@ impl App {
     }
 }
 
-impl ApplicationHandler for App {
+impl ApplicationHandler<ViewerEvent> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         self.window = event_loop.create_window(Window::default_attributes()).ok();
         self.context = softbuffer::Context::new(event_loop.owned_display_handle()).ok();
+        self.update_title();
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(playback) = self.playback.as_mut() else {
+            event_loop.set_control_flow(ControlFlow::Wait);
+            return;
+        };
+        let advanced = playback.advance(Instant::now());
+        event_loop.set_control_flow(match playback.deadline() {
+            Some(deadline) => ControlFlow::WaitUntil(deadline),
+            None => ControlFlow::Wait,
+        });
+        if advanced {
+            self.show_frame();
+        }
+    }
+
+    fn user_event(&mut self, _: &ActiveEventLoop, event: ViewerEvent) {
+        match event {
+            ViewerEvent::Thumbnail(index, thumbnail) => {
+                if let Some(gallery) = self.gallery.as_mut() {
+                    gallery.set_thumbnail(index, thumbnail);
+                }
+                if self.view == View::Gallery || self.filmstrip {
+                    self.redraw();
+                }
+            }
+            ViewerEvent::Written(path) => self.follow(path),
+        }
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
         // println!("{event:?}");
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers,
             WindowEvent::KeyboardInput {
-                event: KeyEvent { logical_key, .. },
+                event: KeyEvent {
+                    logical_key, state, ..
+                },
                 ..
-            } => match logical_key {
+            } if state.is_pressed() => match logical_key {
+                Key::Character(c) if self.modifiers.state().control_key() => match c.as_str() {
+                    "o" => self.open(),
+                    "v" => self.paste(),
+                    "s" if !self.annotations.is_empty() => self.save_annotated(),
+                    "s" => self.save_pasted(),
+                    "S" => self.save_as(),
+                    "[" => self.transform_pixels(Image::rotate_ccw),
+                    "]" => self.transform_pixels(Image::rotate_cw),
+                    "h" => self.transform_pixels(Image::flip_horizontal),
+                    "j" => self.transform_pixels(Image::flip_vertical),
+                    _ => (),
+                },
+                Key::Named(NamedKey::F1) => {
+                    self.help = !self.help;
+                    self.redraw();
+                }
+                Key::Character(c) if c.as_str() == "?" => {
+                    self.help = !self.help;
+                    self.redraw();
+                }
+                Key::Named(NamedKey::Escape) if self.help => {
+                    self.help = false;
+                    self.redraw();
+                }
+                Key::Named(NamedKey::F12) => {
+                    self.screenshot = true;
+                    self.redraw();
+                }
+                Key::Named(NamedKey::F11) if self.modifiers.state().shift_key() => {
+                    self.next_monitor()
+                }
+                Key::Named(NamedKey::F11) => self.toggle_fullscreen(),
+                key if self.view == View::Gallery => self.gallery_key(event_loop, key),
+                Key::Character(c) if c.as_str() == "n" => {
+                    self.annotations.active = !self.annotations.active;
+                    self.ruler.active = false;
+                    self.zoom_box.active = false;
+                    self.redraw();
+                }
+                Key::Character(c) if c.as_str() == "m" => {
+                    self.ruler.active = !self.ruler.active;
+                    self.annotations.active = false;
+                    self.zoom_box.active = false;
+                    self.redraw();
+                }
+                Key::Character(c) if c.as_str() == "z" => {
+                    self.zoom_box.active = !self.zoom_box.active;
+                    self.annotations.active = false;
+                    self.ruler.active = false;
+                    self.redraw();
+                }
+                Key::Named(NamedKey::Escape)
+                    if self.annotations.active || self.ruler.active || self.zoom_box.active =>
+                {
+                    self.annotations.active = false;
+                    self.ruler.active = false;
+                    self.zoom_box.active = false;
+                    self.redraw();
+                }
+                Key::Character(c) if self.annotations.active => self.annotation_key(c.as_str()),
+                Key::Character(c) if c.as_str() == "w" => self.set_wallpaper(),
+                Key::Character(c) if c.as_str() == "h" => self.toggle_heatmap(),
+                Key::Character(c) if c.as_str() == "l" => {
+                    self.raw_linear = !self.raw_linear;
+                    self.set_colorspace(if self.linear { 1 } else { 0 });
+                    self.redraw();
+                }
+                Key::Character(c) if self.playback.is_some() && c.as_str() == "p" => {
+                    if let Some(playback) = self.playback.as_mut() {
+                        playback.toggle_pause();
+                    }
+                    self.update_title();
+                }
+                Key::Character(c)
+                    if self.playback.is_some() && [",", "."].contains(&c.as_str()) =>
+                {
+                    if let Some(playback) = self.playback.as_mut() {
+                        playback.step(if c.as_str() == "," { -1 } else { 1 });
+                    }
+                    self.show_frame();
+                }
+                Key::Character(c) if c.as_str() == "t" && self.gallery.is_some() => {
+                    self.filmstrip = !self.filmstrip;
+                    self.redraw();
+                }
+                Key::Named(NamedKey::Escape) if self.gallery.is_some() => {
+                    self.view = View::Gallery;
+                    self.redraw();
+                }
                 Key::Named(NamedKey::Escape) => event_loop.exit(),
+                Key::Named(NamedKey::ArrowLeft) => self.step(-1),
+                Key::Named(NamedKey::ArrowRight) => self.step(1),
                 Key::Named(NamedKey::Space) => {
                     self.transform = Matrix3::identity();
                     self.redraw();
                 }
+                Key::Character(c) => {
+                    match c.as_str() {
+                        "[" => self.adjustments.step_exposure(-1.0),
+                        "]" => self.adjustments.step_exposure(1.0),
+                        "-" => self.adjustments.step_gamma(-1.0),
+                        "=" => self.adjustments.step_gamma(1.0),
+                        "0" => self.adjustments.reset(),
+                        _ => return,
+                    }
+                    self.redraw();
+                }
                 _ => (),
             },
             WindowEvent::MouseWheel {
                 delta: MouseScrollDelta::LineDelta(_, scroll_y),
                 ..
             } => {
+                if let (View::Gallery, Some(gallery)) = (self.view, self.gallery.as_mut()) {
+                    gallery.scroll_by(scroll_y);
+                    self.redraw();
+                    return;
+                }
+
                 let current_scaling = f32::min(
                     *self.transform.get(0).unwrap(),
                     *self.transform.get(4).unwrap(),
@@ -77,7 +752,7 @@ impl ApplicationHandler for App {
                     return;
                 }
 
-                let (ox, oy) = self.cursor.unwrap_or_else(|| (0f64, 0f64));
+                let (ox, oy) = self.cursor.unwrap_or((0f64, 0f64));
                 let mut trans = Matrix2x1::new(-ox as f32, -oy as f32);
 
                 self.transform.append_translation_mut(&trans);
@@ -92,20 +767,84 @@ impl ApplicationHandler for App {
                 button: MouseButton::Left,
                 ..
             } => {
+                if let (View::Gallery, Some(gallery)) = (self.view, self.gallery.as_mut()) {
+                    if let (true, Some((x, y))) = (state.is_pressed(), self.cursor) {
+                        gallery.select_at(x, y);
+                        self.redraw();
+                    }
+                    return;
+                }
+
+                if let Some(index) = self.filmstrip_at_cursor() {
+                    if state.is_pressed() {
+                        if let Some(gallery) = self.gallery.as_mut() {
+                            gallery.select(index);
+                        }
+                        self.open_selected();
+                    }
+                    return;
+                }
+
+                if self.zoom_box.active {
+                    self.zoom_drag(state.is_pressed());
+                    return;
+                }
+
+                if self.ruler.active {
+                    if let (true, Some(at)) = (state.is_pressed(), self.cursor_in_image()) {
+                        self.ruler.click(at);
+                        self.redraw();
+                    }
+                    return;
+                }
+
+                if self.annotations.active {
+                    match (state.is_pressed(), self.cursor_in_image()) {
+                        (true, Some(at)) => self.annotations.begin(at),
+                        (true, None) => {}
+                        (false, _) => self.annotations.end(),
+                    }
+                    self.redraw();
+                    return;
+                }
+
                 if state.is_pressed() {
-                    self.saved = self.cursor.clone();
-                    self.saved_transform = self.transform.clone();
+                    self.saved = self.cursor;
+                    self.saved_transform = self.transform;
                 } else {
                     self.saved = None;
                 }
             }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } if self.view == View::Single => self.zoom_drag(state.is_pressed()),
             WindowEvent::CursorMoved {
                 position: PhysicalPosition { x, y },
                 ..
             } => {
                 self.cursor = Some((x, y));
 
+                if self.zoom_box.is_dragging() {
+                    self.zoom_box.extend(Point2::new(x as f32, y as f32));
+                    self.redraw();
+                    return;
+                }
+
+                if self.annotations.is_drawing() {
+                    if let Some(at) = self.cursor_in_image() {
+                        self.annotations.extend(at);
+                    }
+                    self.redraw();
+                    return;
+                }
+
                 let Some((prev_x, prev_y)) = self.saved else {
+                    // keep the cursor readout in the status line current
+                    if self.view == View::Single {
+                        self.redraw();
+                    }
                     return;
                 };
                 let delta = Point2::new(x - prev_x, y - prev_y);
@@ -113,7 +852,7 @@ impl ApplicationHandler for App {
 
                 self.transform = self.saved_transform.append_translation(&trans);
                 self.redraw();
-            },
+            }
             WindowEvent::RedrawRequested => {
                 let window = self.window.as_ref().unwrap();
                 let context = self.context.as_ref().unwrap();
@@ -129,27 +868,97 @@ impl ApplicationHandler for App {
                     .unwrap();
 
                 let mut buffer = surface.buffer_mut().unwrap();
-                draw_image(&self.image, &self.transform, &mut buffer);
+                let (width, height) = (size.width as usize, size.height as usize);
+                let mut canvas = Canvas::new(&mut buffer, width, height);
+                match (self.view, self.gallery.as_mut()) {
+                    (View::Gallery, Some(gallery)) => gallery.draw(&mut canvas),
+                    _ => {
+                        match &self.heatmap {
+                            Some(heatmap) => {
+                                let plain = Adjustments::new();
+                                draw_image(heatmap, &self.transform, &plain, &mut canvas);
+                            }
+                            None => draw_image(
+                                &self.image,
+                                &self.transform,
+                                &self.adjustments,
+                                &mut canvas,
+                            ),
+                        }
+                        self.annotations
+                            .draw(&mut canvas, &self.image, &self.transform);
+                        if self.ruler.active {
+                            let cursor = self.cursor_in_image();
+                            self.ruler.draw(&mut canvas, &self.transform, cursor);
+                        }
+                        self.zoom_box.draw(&mut canvas);
+                        let mut bottom = canvas.height;
+                        if let (true, Some(gallery)) = (self.filmstrip, self.gallery.as_mut()) {
+                            gallery.draw_filmstrip(&mut canvas);
+                            bottom -= STRIP_HEIGHT.min(bottom);
+                        }
+                        let (status, message) = (self.status_text(), self.message.as_deref());
+                        draw_status(&mut canvas, bottom, &status, message);
+                        if let Some(corrupt) = &self.corrupt {
+                            text::draw_label(&mut canvas, 0, 0, corrupt, ERROR_COLOR);
+                        }
+                    }
+                }
+
+                if self.help {
+                    help::draw(&mut canvas, &self.mode_states());
+                }
+
+                let screenshot = std::mem::take(&mut self.screenshot).then(|| Image {
+                    width,
+                    height,
+                    pixels: canvas
+                        .pixels
+                        .iter()
+                        .map(|&px| {
+                            let [_, r, g, b] = px.to_be_bytes();
+                            Pixel::new(r, g, b, 255)
+                        })
+                        .collect(),
+                });
 
                 // Notify that you're about to draw.
                 window.pre_present_notify();
                 buffer.present().unwrap();
+                drop(surface);
+
+                if let Some(screenshot) = screenshot {
+                    let filename = format!("screenshot-{}.qoi", timestamp());
+                    self.show_message(save_image(&filename, &screenshot));
+                }
             }
             _ => (),
         }
     }
 }
 
-fn draw_image<D: HasDisplayHandle, W: HasWindowHandle>(
+// `bottom` is the y coordinate the status line ends at
+fn draw_status(canvas: &mut Canvas, bottom: usize, status: &str, message: Option<&str>) {
+    let mut y = bottom as isize - text::label_height(status) as isize;
+    text::draw_label(canvas, 0, y, status, TEXT_COLOR);
+
+    if let Some(message) = message {
+        y -= text::label_height(message) as isize;
+        text::draw_label(canvas, 0, y, message, ERROR_COLOR);
+    }
+}
+
+fn draw_image(
     image: &Image<Pixel>,
     transform: &Matrix3<f32>,
-    buffer: &mut Buffer<'_, D, W>,
+    adjustments: &Adjustments,
+    canvas: &mut Canvas,
 ) {
     let tl_i = Point2::new(0 as f32, 0 as f32);
     let br_i = Point2::new(image.width as f32, image.height as f32);
 
     let tl_b = Point2::new(0 as f32, 0 as f32);
-    let br_b = Point2::new(buffer.width().get() as f32, buffer.height().get() as f32);
+    let br_b = Point2::new(canvas.width as f32, canvas.height as f32);
 
     let tl_t = transform.transform_point(&tl_i);
     let br_t = transform.transform_point(&br_i);
@@ -165,7 +974,7 @@ fn draw_image<D: HasDisplayHandle, W: HasWindowHandle>(
         println!("transform matrix = {transform:?}");
         return;
     };
-    let bwidth = buffer.width().get() as usize;
+    let bwidth = canvas.width;
 
     for y in tl.y as usize..br.y as usize {
         for x in tl.x as usize..br.x as usize {
@@ -174,7 +983,7 @@ fn draw_image<D: HasDisplayHandle, W: HasWindowHandle>(
             let pt_b = Point2::new(x as f32, y as f32);
             let pt_i = inv.transform_point(&pt_b);
 
-            let Some(output) = buffer.get_mut(y * bwidth + x) else {
+            let Some(output) = canvas.pixels.get_mut(y * bwidth + x) else {
                 continue;
             };
 
@@ -182,24 +991,168 @@ fn draw_image<D: HasDisplayHandle, W: HasWindowHandle>(
             let Some(&Pixel { r, g, b, .. }) = image.pixels.get(index) else {
                 continue;
             };
+            let [r, g, b] = [r, g, b].map(|c| adjustments.apply(c));
             *output = u32::from_be_bytes([0, r, g, b]);
         }
     }
 }
 
+fn pick_file() -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .add_filter("QOI image", &["qoi", "qoan"])
+        .pick_file()
+}
+
+fn save_image(filename: &str, image: &Image<Pixel>) -> String {
+    write_image(Path::new(filename), image, None)
+}
+
+// Keeps the channels and colorspace of `header`, if the image came with one.
+fn write_image(path: &Path, image: &Image<Pixel>, header: Option<Header>) -> String {
+    let (width, height) = (image.width as u32, image.height as u32);
+    let mut encoder = match header {
+        Some(header) => Encoder::with_header(Header {
+            width,
+            height,
+            ..header
+        }),
+        None => Encoder::new(width, height),
+    };
+    let data = match encoder.encode(&image.pixels) {
+        Ok(data) => data,
+        Err(err) => return format!("failed to save {}: {err}", path.display()),
+    };
+    match std::fs::write(path, data) {
+        Ok(()) => format!("saved {}", path.display()),
+        Err(err) => format!("failed to save {}: {err}", path.display()),
+    }
+}
+
+fn timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default()
+}
+
+fn empty_image() -> Image<Pixel> {
+    Image {
+        width: 0,
+        height: 0,
+        pixels: vec![],
+    }
+}
+
+fn load_image(path: &Path) -> Result<Loaded, String> {
+    let file =
+        std::fs::read(path).map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+
+    if AnimReader::new(&file).is_some() {
+        let mut playback = Playback::new(file)
+            .ok_or_else(|| format!("{}: animation has no frames", path.display()))?;
+        let image = playback
+            .decode(&empty_image())
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        return Ok(Loaded {
+            image,
+            header: None,
+            path: Some(path.to_owned()),
+            playback: Some(playback),
+            corrupt: None,
+        });
+    }
+
+    let mut decoder = Decoder::new();
+    if let Ok(image) = decoder.decode(&file) {
+        return Ok(Loaded {
+            image,
+            header: decoder.header(),
+            path: Some(path.to_owned()),
+            playback: None,
+            corrupt: None,
+        });
+    }
+
+    // a damaged file shows what decoded, with the rest in magenta
+    let mut decoder = decoder.pad_with(Some(Pixel::new(255, 0, 255, 255)));
+    let partial = decoder
+        .decode_partial(&file)
+        .map_err(|err| format!("{}: {err}", path.display()))?;
+    let corrupt = partial.error.map(|err| {
+        let total = partial.image.pixels.len();
+        let at = partial
+            .offset
+            .map_or(String::new(), |offset| format!(" at byte {offset}"));
+        format!(
+            "corrupt file: {err}{at}, decoded {} of {total} pixels",
+            partial.decoded
+        )
+    });
+    Ok(Loaded {
+        image: partial.image,
+        header: decoder.header(),
+        path: Some(path.to_owned()),
+        playback: None,
+        corrupt,
+    })
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut args = std::env::args().into_iter();
+    let mut args = std::env::args();
     let _program = args.next().expect("program name");
-    let filename = args.next().expect("filename");
 
-    let file = std::fs::read(filename).expect("file exists and is readable");
-    let mut decoder = Decoder::new();
-    let image = decoder.decode(&file).expect("file is valid QOI image");
+    let mut path = None;
+    let mut watch_dir = None;
+    let mut sort = SortKey::default();
+    let mut reverse = false;
+    let mut monitor = Config::load().monitor;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sort" => {
+                let key = args.next().ok_or("--sort expects name, mtime or size")?;
+                sort = SortKey::parse(&key).ok_or("--sort expects name, mtime or size")?;
+            }
+            "--reverse" => reverse = true,
+            "--monitor" => {
+                let n = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0);
+                monitor = Some(n.ok_or("--monitor expects a number from 1")?);
+            }
+            "--watch-dir" => {
+                let dir = args.next().ok_or("--watch-dir expects a directory")?;
+                watch_dir = Some(PathBuf::from(dir));
+            }
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
 
-    let event_loop = EventLoop::new()?;
+    let event_loop = EventLoop::<ViewerEvent>::with_user_event().build()?;
     event_loop.set_control_flow(ControlFlow::Wait);
 
-    let mut app = App::new(image);
+    if let Some(dir) = watch_dir {
+        let mut app = App::new(empty_image(), None);
+        app.monitor = monitor;
+        app.watcher = Some(follow::watch(&dir, event_loop.create_proxy())?);
+        match follow::newest(&dir) {
+            Some(newest) => app.follow(newest),
+            None => app.message = Some(format!("waiting for QOI files in {}", dir.display())),
+        }
+        return event_loop.run_app(&mut app).map_err(|e| e.into());
+    }
+
+    let Some(path) = path.or_else(pick_file) else {
+        return Ok(());
+    };
+
+    let mut app = if path.is_dir() {
+        let gallery = Gallery::open(&path, sort, reverse, event_loop.create_proxy())?;
+        App::new(empty_image(), Some(gallery))
+    } else {
+        let loaded = load_image(&path)?;
+        let mut app = App::new(empty_image(), None);
+        app.show_image(loaded);
+        app
+    };
+    app.monitor = monitor;
 
     // For alternative loop run options see `pump_events` and `run_on_demand` examples.
     event_loop.run_app(&mut app).map_err(|e| e.into())
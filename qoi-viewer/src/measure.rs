@@ -0,0 +1,84 @@
+use nalgebra::{Matrix3, Point2, Vector2};
+
+use crate::draw::{Canvas, line};
+use crate::text;
+
+const LINE_COLOR: u32 = 0x00e0ff;
+const LABEL_COLOR: u32 = 0xe0e0e0;
+
+// Measures between two clicked pixels; a third click starts over.
+pub struct Ruler {
+    pub active: bool,
+    start: Option<Point2<f32>>,
+    end: Option<Point2<f32>>,
+}
+
+impl Ruler {
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            start: None,
+            end: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.start = None;
+        self.end = None;
+    }
+
+    // `at` is in image coordinates and snaps to the pixel it falls in
+    pub fn click(&mut self, at: Point2<f32>) {
+        let at = Point2::new(at.x.floor(), at.y.floor());
+        match (self.start, self.end) {
+            (Some(_), None) => self.end = Some(at),
+            _ => {
+                self.start = Some(at);
+                self.end = None;
+            }
+        }
+    }
+
+    pub fn status(&self) -> &'static str {
+        match (self.start, self.end) {
+            (Some(_), None) => "measure: click the second point",
+            _ => "measure: click the first point",
+        }
+    }
+
+    // Draws the measured line, or one following the cursor while the second
+    // point is still to be clicked, with its label.
+    pub fn draw(&self, canvas: &mut Canvas, transform: &Matrix3<f32>, cursor: Option<Point2<f32>>) {
+        let Some(start) = self.start else {
+            return;
+        };
+        let cursor = cursor.map(|at| Point2::new(at.x.floor(), at.y.floor()));
+        let Some(end) = self.end.or(cursor) else {
+            return;
+        };
+
+        // between pixel centers on screen
+        let center = |pt: Point2<f32>| transform.transform_point(&(pt + Vector2::new(0.5, 0.5)));
+        let (from, to) = (center(start), center(end));
+        line(from, to, 1.0, |x, y| canvas.put(x, y, LINE_COLOR));
+        for pt in [from, to] {
+            canvas.fill_rect(pt.x as isize - 2, pt.y as isize - 2, 5, 5, LINE_COLOR);
+        }
+        text::draw_label(
+            canvas,
+            to.x as isize + 8,
+            to.y as isize + 8,
+            &label(start, end),
+            LABEL_COLOR,
+        );
+    }
+}
+
+// Offsets in pixels, and the angle counterclockwise from the x axis as on a
+// protractor, even though image y grows downwards.
+fn label(start: Point2<f32>, end: Point2<f32>) -> String {
+    let (dx, dy) = ((end.x - start.x) as i64, (end.y - start.y) as i64);
+    let distance = (dx as f64).hypot(dy as f64);
+    let angle = (-dy as f64).atan2(dx as f64).to_degrees();
+    format!("dx {dx}  dy {dy}\ndistance {distance:.1}  angle {angle:.1} deg")
+}
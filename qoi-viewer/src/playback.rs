@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+use qoi_rs::anim::AnimReader;
+use qoi_rs::{Image, Pixel};
+
+// An animation being shown; frames are decoded from the file as they come up.
+pub struct Playback {
+    data: Vec<u8>,
+    len: usize,
+    loop_count: u32,
+    loops: u32,
+    pub frame: usize,
+    pub paused: bool,
+    // the frame last decoded, which is the one on screen
+    shown: Option<usize>,
+    // when the current frame is due to be replaced
+    next: Instant,
+}
+
+impl Playback {
+    // None unless `data` is an animation with at least one frame
+    pub fn new(data: Vec<u8>) -> Option<Self> {
+        let reader = AnimReader::new(&data).filter(|reader| !reader.is_empty())?;
+        let (len, loop_count) = (reader.len(), reader.loop_count);
+        let mut playback = Self {
+            data,
+            len,
+            loop_count,
+            loops: 0,
+            frame: 0,
+            paused: false,
+            shown: None,
+            next: Instant::now(),
+        };
+        playback.schedule();
+        Some(playback)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    fn reader(&self) -> AnimReader<'_> {
+        AnimReader::new(&self.data).expect("checked in Playback::new")
+    }
+
+    // Decodes the current frame, from `shown` if that is the frame before and
+    // the current one is a delta of it.
+    pub fn decode(&mut self, shown: &Image<Pixel>) -> Result<Image<Pixel>, String> {
+        let reader = self.reader();
+        let image = match self.shown {
+            Some(shown_frame) if shown_frame + 1 == self.frame => {
+                reader.decode_after(self.frame, shown)
+            }
+            _ => reader.decode(self.frame),
+        };
+        let image = image.ok_or_else(|| format!("frame {} is corrupt", self.frame + 1))?;
+        self.shown = Some(self.frame);
+        Ok(image)
+    }
+
+    fn schedule(&mut self) {
+        let duration = self.reader().duration_ms(self.frame).unwrap_or(0);
+        // zero-length frames would spin, so give them one
+        self.next = Instant::now() + Duration::from_millis(duration.max(10) as u64);
+    }
+
+    // when to wake up for the next frame, if playing
+    pub fn deadline(&self) -> Option<Instant> {
+        (!self.paused).then_some(self.next)
+    }
+
+    // Moves on to the next frame once the current one has been shown long
+    // enough, or pauses on the last frame once the file's loop count has been
+    // played; returns whether either happened.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        if self.paused || now < self.next {
+            return false;
+        }
+        if self.frame + 1 == self.len {
+            self.loops += 1;
+            if self.loop_count != 0 && self.loops >= self.loop_count {
+                self.paused = true;
+                return true;
+            }
+        }
+        self.frame = (self.frame + 1) % self.len;
+        self.schedule();
+        true
+    }
+
+    // one frame back or forward, pausing playback
+    pub fn step(&mut self, offset: isize) {
+        self.paused = true;
+        self.frame = (self.frame as isize + offset).rem_euclid(self.len as isize) as usize;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if !self.paused {
+            self.loops = 0;
+            self.schedule();
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use font8x8::{BASIC_FONTS, UnicodeFonts};
+
+use crate::draw::Canvas;
+
+pub const GLYPH_SIZE: usize = 8;
+const LABEL_PADDING: usize = 4;
+const LABEL_BACKGROUND: u32 = 0x101010;
+
+pub fn text_width(text: &str) -> usize {
+    text.chars().count() * GLYPH_SIZE
+}
+
+pub fn draw_text(canvas: &mut Canvas, x: isize, y: isize, text: &str, color: u32) {
+    for (i, c) in text.chars().enumerate() {
+        // unknown characters still take up space so columns stay aligned
+        let Some(glyph) = BASIC_FONTS.get(c) else {
+            continue;
+        };
+
+        let gx = x + (i * GLYPH_SIZE) as isize;
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..GLYPH_SIZE {
+                if bits & (1 << col) != 0 {
+                    canvas.put(gx + col as isize, y + row as isize, color);
+                }
+            }
+        }
+    }
+}
+
+/// Draws each line of `text` on a dark box with its top-left corner at (x, y).
+pub fn draw_label(canvas: &mut Canvas, x: isize, y: isize, text: &str, color: u32) {
+    let lines = text.lines().collect::<Vec<_>>();
+    let width = lines.iter().map(|line| text_width(line)).max().unwrap_or(0);
+    let height = lines.len() * GLYPH_SIZE;
+
+    canvas.fill_rect(
+        x,
+        y,
+        width + 2 * LABEL_PADDING,
+        height + 2 * LABEL_PADDING,
+        LABEL_BACKGROUND,
+    );
+    for (i, line) in lines.iter().enumerate() {
+        let ly = y + (LABEL_PADDING + i * GLYPH_SIZE) as isize;
+        draw_text(canvas, x + LABEL_PADDING as isize, ly, line, color);
+    }
+}
+
+pub fn label_height(text: &str) -> usize {
+    text.lines().count() * GLYPH_SIZE + 2 * LABEL_PADDING
+}
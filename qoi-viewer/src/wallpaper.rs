@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+use qoi_rs::{Image, Pixel};
+
+const PREFIX: &str = "qoi-viewer-wallpaper-";
+
+// Writes the image to a PNG in the temp directory, which has to stay around
+// for as long as it is the wallpaper, and makes it the desktop background.
+pub fn set(image: &Image<Pixel>, timestamp: u128) -> Result<PathBuf, String> {
+    let dir = std::env::temp_dir();
+    // earlier wallpapers are no longer needed; desktops cache by path, so
+    // reusing one name would not show the new image
+    if let Ok(entries) = std::fs::read_dir(&dir) {
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(PREFIX) {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    let path = dir.join(format!("{PREFIX}{timestamp}.png"));
+    let rgba = image::RgbaImage::from_raw(
+        image.width as u32,
+        image.height as u32,
+        image.as_rgba_bytes().into_owned(),
+    )
+    .ok_or("image too large")?;
+    rgba.save(&path)
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+    apply(&path)?;
+    Ok(path)
+}
+
+// Tries the desktop's own setting first, then the usual standalone tools.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn apply(path: &Path) -> Result<(), String> {
+    use std::process::{Command, Stdio};
+
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .to_lowercase();
+    let uri = format!("file://{}", path.display());
+    let path = path.to_string_lossy();
+    let mut errors = Vec::new();
+    if ["gnome", "unity", "budgie", "pantheon"]
+        .iter()
+        .any(|name| desktop.contains(name))
+    {
+        let set = |key| {
+            Command::new("gsettings")
+                .args(["set", "org.gnome.desktop.background", key, &uri])
+                .status()
+        };
+        match set("picture-uri") {
+            Ok(status) if status.success() => {
+                // only there since GNOME 42, so it is fine for this to fail
+                let _ = set("picture-uri-dark");
+                return Ok(());
+            }
+            Ok(status) => errors.push(format!("gsettings: {status}")),
+            Err(err) => errors.push(format!("gsettings: {err}")),
+        }
+    }
+
+    // swaybg keeps running to draw the background, so it replaces the old one
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        let _ = Command::new("pkill").arg("-x").arg("swaybg").status();
+        let spawned = Command::new("swaybg")
+            .args(["-m", "fill", "-i", &path])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(_) => return Ok(()),
+            Err(err) => errors.push(format!("swaybg: {err}")),
+        }
+    }
+    match Command::new("feh").args(["--bg-fill", &path]).status() {
+        Ok(status) if status.success() => return Ok(()),
+        Ok(status) => errors.push(format!("feh: {status}")),
+        Err(err) => errors.push(format!("feh: {err}")),
+    }
+    Err(format!(
+        "could not set the wallpaper ({})",
+        errors.join(", ")
+    ))
+}
+
+#[cfg(windows)]
+fn apply(path: &Path) -> Result<(), String> {
+    use std::ffi::c_void;
+    use std::os::windows::ffi::OsStrExt;
+
+    const SPI_SETDESKWALLPAPER: u32 = 0x0014;
+    const SPIF_UPDATEINIFILE: u32 = 0x01;
+    const SPIF_SENDCHANGE: u32 = 0x02;
+
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn SystemParametersInfoW(action: u32, param: u32, value: *mut c_void, flags: u32) -> i32;
+    }
+
+    let mut wide = path
+        .as_os_str()
+        .encode_wide()
+        .chain([0])
+        .collect::<Vec<u16>>();
+    // SAFETY: `wide` is a NUL-terminated UTF-16 path that outlives the call.
+    let ok = unsafe {
+        SystemParametersInfoW(
+            SPI_SETDESKWALLPAPER,
+            0,
+            wide.as_mut_ptr().cast(),
+            SPIF_UPDATEINIFILE | SPIF_SENDCHANGE,
+        )
+    };
+    if ok == 0 {
+        return Err(format!(
+            "could not set the wallpaper: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+fn apply(_: &Path) -> Result<(), String> {
+    Err("setting the wallpaper is not supported on this platform".into())
+}
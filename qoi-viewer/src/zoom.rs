@@ -0,0 +1,69 @@
+use nalgebra::{Matrix3, Point2, Vector2};
+
+use crate::draw::{Canvas, line};
+
+const BOX_COLOR: u32 = 0xffffff;
+
+// A rectangle dragged on screen to zoom into, either in the zoom mode or
+// with the right mouse button.
+pub struct ZoomBox {
+    pub active: bool,
+    start: Option<Point2<f32>>,
+    end: Point2<f32>,
+}
+
+impl ZoomBox {
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            start: None,
+            end: Point2::origin(),
+        }
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.start.is_some()
+    }
+
+    // `at` is in window coordinates
+    pub fn begin(&mut self, at: Point2<f32>) {
+        self.start = Some(at);
+        self.end = at;
+    }
+
+    pub fn extend(&mut self, to: Point2<f32>) {
+        self.end = to;
+    }
+
+    // The transform that shows the dragged rectangle centered and as large as
+    // it fits in the window, or None when the drag was too small to mean one.
+    pub fn finish(
+        &mut self,
+        transform: &Matrix3<f32>,
+        width: f32,
+        height: f32,
+    ) -> Option<Matrix3<f32>> {
+        let start = self.start.take()?;
+        let size = (self.end - start).abs();
+        if size.x < 4.0 || size.y < 4.0 {
+            return None;
+        }
+
+        let scale = (width / size.x).min(height / size.y);
+        let center = start + (self.end - start) / 2.0;
+        let transform = transform.append_translation(&-center.coords);
+        let transform = transform.append_scaling(scale);
+        Some(transform.append_translation(&Vector2::new(width / 2.0, height / 2.0)))
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        let Some(from) = self.start else {
+            return;
+        };
+        let to = self.end;
+        let (a, b) = (Point2::new(to.x, from.y), Point2::new(from.x, to.y));
+        for (from, to) in [(from, a), (a, to), (to, b), (b, from)] {
+            line(from, to, 1.0, |x, y| canvas.put(x, y, BOX_COLOR));
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! Animated QOI: a sequence of QOI frames, each either a keyframe that stands
+//! on its own or a delta of the frame before it.
+//!
+//! Layout, all integers big-endian:
+//!
+//! ```text
+//! "qoan" | u32 width | u32 height | u32 loop count | frames... | u32 0
+//! keyframe: u32 length | u32 duration (ms) | QOI data
+//! delta:    u32 length | u32 duration (ms) with bit 31 set | RGBA key | QOI data
+//! ```
+//!
+//! Bit 31 (0x80000000) of the duration word marks a delta frame, leaving 31
+//! bits for the duration itself.
+//!
+//! The loop count is how many times the animation plays, 0 repeats forever. The
+//! frame count is not stored up front so frames can be streamed out as they are
+//! produced; a zero length ends the stream.
+//!
+//! In a delta frame every pixel that is the key color is the same as in the
+//! previous frame, so unchanged areas encode as runs. The key is chosen to be
+//! a color none of the changed pixels have. Delta frames are only written when
+//! asked for with `AnimWriter::keyframe_interval`; readers from before they
+//! existed cannot decode them.
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::{Decoder, Encoder, Image, Pixel};
+
+pub const MAGIC: &[u8; 4] = b"qoan";
+
+// set in the duration of delta frames
+const DELTA: u32 = 1 << 31;
+
+pub struct AnimWriter<W: Write> {
+    writer: W,
+    width: u32,
+    height: u32,
+    keyframe_interval: u32,
+    // frames written since the last keyframe, and the last frame's pixels
+    since_keyframe: u32,
+    previous: Option<Vec<Pixel>>,
+}
+
+impl<W: Write> AnimWriter<W> {
+    pub fn new(mut writer: W, width: u32, height: u32, loop_count: u32) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&width.to_be_bytes())?;
+        writer.write_all(&height.to_be_bytes())?;
+        writer.write_all(&loop_count.to_be_bytes())?;
+        Ok(Self {
+            writer,
+            width,
+            height,
+            keyframe_interval: 1,
+            since_keyframe: 0,
+            previous: None,
+        })
+    }
+
+    // Makes every `interval`th frame added with `add_frame` a keyframe and the
+    // others deltas. 1, the default, makes every frame a keyframe.
+    pub fn keyframe_interval(mut self, interval: u32) -> Self {
+        self.keyframe_interval = interval.max(1);
+        self
+    }
+
+    pub fn add_frame(&mut self, frame: &Image<Pixel>, duration_ms: u32) -> io::Result<()> {
+        if (frame.width, frame.height) != (self.width as usize, self.height as usize) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame size does not match the animation",
+            ));
+        }
+        let encode = |pixels: &[Pixel]| {
+            Encoder::new(self.width, self.height)
+                .encode(pixels)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+        };
+
+        let delta = self
+            .previous
+            .as_ref()
+            .filter(|_| self.since_keyframe + 1 < self.keyframe_interval);
+        match delta {
+            Some(previous) => {
+                let key = unused_color(&frame.pixels, previous);
+                let pixels = frame
+                    .pixels
+                    .iter()
+                    .zip(previous)
+                    .map(|(&pixel, &prev)| if pixel == prev { key } else { pixel })
+                    .collect::<Vec<_>>();
+                let data = encode(&pixels)?;
+                self.write_frame(&data, duration_ms, Some(key))?;
+                self.since_keyframe += 1;
+            }
+            None => {
+                let data = encode(&frame.pixels)?;
+                self.write_frame(&data, duration_ms, None)?;
+                self.since_keyframe = 0;
+            }
+        }
+        self.previous = Some(frame.pixels.clone());
+        Ok(())
+    }
+
+    // `qoi` must be a complete QOI file with the animation's dimensions. It is
+    // stored as a keyframe.
+    pub fn add_encoded(&mut self, qoi: &[u8], duration_ms: u32) -> io::Result<()> {
+        // the next frame from `add_frame` has nothing to be a delta of
+        self.previous = None;
+        self.write_frame(qoi, duration_ms, None)
+    }
+
+    fn write_frame(&mut self, qoi: &[u8], duration_ms: u32, key: Option<Pixel>) -> io::Result<()> {
+        let invalid = |what| io::Error::new(io::ErrorKind::InvalidInput, what);
+        if duration_ms & DELTA != 0 {
+            return Err(invalid("frame duration too long"));
+        }
+        let key_len = if key.is_some() { 4 } else { 0 };
+        let len = u32::try_from(qoi.len() + key_len)
+            .ok()
+            .filter(|_| !qoi.is_empty())
+            .ok_or_else(|| invalid("bad frame length"))?;
+        self.writer.write_all(&len.to_be_bytes())?;
+        match key {
+            Some(key) => {
+                self.writer
+                    .write_all(&(duration_ms | DELTA).to_be_bytes())?;
+                self.writer.write_all(&key.to_bytes())?;
+            }
+            None => self.writer.write_all(&duration_ms.to_be_bytes())?,
+        }
+        self.writer.write_all(qoi)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&0u32.to_be_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+pub struct AnimReader<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub loop_count: u32,
+    // duration, the key color of delta frames, and the QOI data
+    frames: Vec<(u32, Option<Pixel>, &'a [u8])>,
+}
+
+impl<'a> AnimReader<'a> {
+    // Parses the frame table; frame data is only decoded on access.
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        let (magic, rest) = data.split_first_chunk::<4>()?;
+        if magic.ne(MAGIC) {
+            return None;
+        }
+        let (width, rest) = rest.split_first_chunk::<4>()?;
+        let (height, rest) = rest.split_first_chunk::<4>()?;
+        let (loop_count, mut rest) = rest.split_first_chunk::<4>()?;
+
+        let mut frames = vec![];
+        loop {
+            let (len, tail) = rest.split_first_chunk::<4>()?;
+            let len = u32::from_be_bytes(*len) as usize;
+            if len == 0 {
+                break;
+            }
+            let (duration, tail) = tail.split_first_chunk::<4>()?;
+            let (frame, tail) = tail.split_at_checked(len)?;
+            let duration = u32::from_be_bytes(*duration);
+            if duration & DELTA != 0 {
+                let (&[r, g, b, a], frame) = frame.split_first_chunk::<4>()?;
+                frames.push((duration & !DELTA, Some(Pixel::new(r, g, b, a)), frame));
+            } else {
+                frames.push((duration, None, frame));
+            }
+            rest = tail;
+        }
+
+        Some(Self {
+            width: u32::from_be_bytes(*width),
+            height: u32::from_be_bytes(*height),
+            loop_count: u32::from_be_bytes(*loop_count),
+            frames,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn duration_ms(&self, index: usize) -> Option<u32> {
+        self.frames.get(index).map(|&(duration, ..)| duration)
+    }
+
+    pub fn is_keyframe(&self, index: usize) -> bool {
+        self.frames
+            .get(index)
+            .is_some_and(|(_, key, _)| key.is_none())
+    }
+
+    // The encoded QOI bytes of frame `index`, if it is a keyframe; delta frames
+    // have to be decoded.
+    pub fn get(&self, index: usize) -> Option<&'a [u8]> {
+        match self.frames.get(index)? {
+            &(_, None, data) => Some(data),
+            _ => None,
+        }
+    }
+
+    // Decodes frame `index`, going back to the keyframe before it if it is a
+    // delta frame.
+    pub fn decode(&self, index: usize) -> Option<Image<Pixel>> {
+        let keyframe = (0..=index).rev().find(|&i| self.is_keyframe(i))?;
+        let mut image = Decoder::new().decode(self.get(keyframe)?).ok()?;
+        for i in keyframe + 1..=index {
+            image = self.decode_after(i, &image)?;
+        }
+        Some(image)
+    }
+
+    // Decodes frame `index` given the frame before it, which saves going back
+    // to the keyframe when playing in order.
+    pub fn decode_after(&self, index: usize, previous: &Image<Pixel>) -> Option<Image<Pixel>> {
+        let &(_, key, data) = self.frames.get(index)?;
+        let mut image = Decoder::new().decode(data).ok()?;
+        if let Some(key) = key {
+            if (previous.width, previous.height) != (image.width, image.height) {
+                return None;
+            }
+            for (pixel, &prev) in image.pixels.iter_mut().zip(&previous.pixels) {
+                if *pixel == key {
+                    *pixel = prev;
+                }
+            }
+        }
+        Some(image)
+    }
+}
+
+// A color that none of the pixels that changed since `previous` have, to key
+// the unchanged ones with. Transparent black nearly always is.
+fn unused_color(pixels: &[Pixel], previous: &[Pixel]) -> Pixel {
+    let changed = pixels
+        .iter()
+        .zip(previous)
+        .filter(|(pixel, prev)| pixel != prev);
+    let transparent = Pixel::new(0, 0, 0, 0);
+    if !changed.clone().any(|(&pixel, _)| pixel == transparent) {
+        return transparent;
+    }
+    let used = changed
+        .map(|(pixel, _)| u32::from_be_bytes(pixel.to_bytes()))
+        .collect::<HashSet<_>>();
+    // there are fewer pixels than colors, so one is always left
+    let [r, g, b, a] = (0..=u32::MAX)
+        .find(|value| !used.contains(value))
+        .expect("more colors than pixels")
+        .to_be_bytes();
+    Pixel::new(r, g, b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let frames = (0..3u8)
+            .map(|i| Image {
+                width: 4,
+                height: 2,
+                pixels: vec![Pixel::new(i, 0, 255 - i, 255); 8],
+            })
+            .collect::<Vec<_>>();
+
+        let mut writer = AnimWriter::new(vec![], 4, 2, 0).unwrap();
+        for (i, frame) in frames.iter().enumerate() {
+            writer.add_frame(frame, 40 * i as u32).unwrap();
+        }
+        let wrong_size = Image {
+            width: 1,
+            height: 1,
+            pixels: vec![Pixel::new(0, 0, 0, 0)],
+        };
+        assert!(writer.add_frame(&wrong_size, 10).is_err());
+        let data = writer.finish().unwrap();
+
+        let reader = AnimReader::new(&data).unwrap();
+        assert_eq!((reader.width, reader.height, reader.loop_count), (4, 2, 0));
+        assert_eq!(reader.len(), 3);
+        assert_eq!(reader.duration_ms(2), Some(80));
+        assert!(reader.decode(1).unwrap().pixels.eq(&frames[1].pixels));
+
+        // without the terminator the stream is incomplete
+        assert!(AnimReader::new(&data[..data.len() - 4]).is_none());
+    }
+
+    #[test]
+    fn delta_frames() {
+        // a dot moving across a gradient, with transparent black in the
+        // changed pixels so another key has to be found
+        let frames = (0..7)
+            .map(|i| {
+                Image::from_fn(16, 8, |x, y| match (x, y) {
+                    (x, 3) if x == i => Pixel::new(255, 255, 255, 255),
+                    (x, 4) if x == i => Pixel::new(0, 0, 0, 0),
+                    _ => Pixel::new(x as u8 * 16, y as u8 * 32, 128, 255),
+                })
+            })
+            .collect::<Vec<_>>();
+        let write = |interval| {
+            let mut writer = AnimWriter::new(vec![], 16, 8, 0)
+                .unwrap()
+                .keyframe_interval(interval);
+            for frame in &frames {
+                writer.add_frame(frame, 40).unwrap();
+            }
+            writer.finish().unwrap()
+        };
+        let (keyframes, deltas) = (write(1), write(3));
+        assert!(deltas.len() < keyframes.len());
+
+        let reader = AnimReader::new(&deltas).unwrap();
+        assert_eq!(reader.len(), 7);
+        let keys = (0..7).map(|i| reader.is_keyframe(i)).collect::<Vec<_>>();
+        assert_eq!(keys, [true, false, false, true, false, false, true]);
+        assert!(reader.get(1).is_none());
+        assert_eq!(reader.duration_ms(1), Some(40));
+        for (i, frame) in frames.iter().enumerate() {
+            assert!(reader.decode(i).unwrap().pixels.eq(&frame.pixels));
+        }
+        let next = reader.decode_after(5, &reader.decode(4).unwrap()).unwrap();
+        assert!(next.pixels.eq(&frames[5].pixels));
+    }
+}
@@ -0,0 +1,167 @@
+//! `.qoia` archives bundle many named QOI images into a single file.
+//!
+//! Layout, all integers big-endian:
+//!
+//! ```text
+//! "qoia" | u32 entry count | entries... | image data...
+//! entry: u16 name length | name (UTF-8) | u64 offset | u64 length
+//! ```
+//!
+//! Offsets are measured from the start of the archive, so any image can be
+//! read without touching the others.
+
+use std::io::{self, Write};
+
+use crate::{Decoder, EncodeError, Encoder, Image, Pixel};
+
+pub const MAGIC: &[u8; 4] = b"qoia";
+
+#[derive(Default)]
+pub struct ArchiveWriter {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl ArchiveWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // adding a name twice replaces the earlier image
+    pub fn add(&mut self, name: &str, qoi: Vec<u8>) {
+        match self.entries.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = qoi,
+            None => self.entries.push((name.to_owned(), qoi)),
+        }
+    }
+
+    pub fn add_image(&mut self, name: &str, image: &Image<Pixel>) -> Result<(), EncodeError> {
+        let mut encoder = Encoder::new(image.width as u32, image.height as u32);
+        self.add(name, encoder.encode(&image.pixels)?);
+        Ok(())
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let index_len = self.entries.iter().map(|(name, _)| 2 + name.len() + 16).sum::<usize>();
+        let mut offset = (MAGIC.len() + 4 + index_len) as u64;
+
+        let mut index = Vec::with_capacity(MAGIC.len() + 4 + index_len);
+        index.extend(MAGIC);
+        index.extend((self.entries.len() as u32).to_be_bytes());
+        for (name, data) in &self.entries {
+            let name_len = u16::try_from(name.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "entry name too long"))?;
+            index.extend(name_len.to_be_bytes());
+            index.extend(name.as_bytes());
+            index.extend(offset.to_be_bytes());
+            index.extend((data.len() as u64).to_be_bytes());
+            offset += data.len() as u64;
+        }
+
+        writer.write_all(&index)?;
+        for (_, data) in &self.entries {
+            writer.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.write_to(&mut buf).expect("writing to a Vec cannot fail");
+        buf
+    }
+}
+
+pub struct ArchiveReader<'a> {
+    data: &'a [u8],
+    entries: Vec<(&'a str, usize, usize)>,
+}
+
+impl<'a> ArchiveReader<'a> {
+    // Parses and validates the index; image data is only looked at on access.
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        let (magic, rest) = data.split_first_chunk::<4>()?;
+        if magic.ne(MAGIC) {
+            return None;
+        }
+        let (count, mut rest) = rest.split_first_chunk::<4>()?;
+        let count = u32::from_be_bytes(*count) as usize;
+
+        let mut entries = Vec::with_capacity(count.min(rest.len() / 18));
+        for _ in 0..count {
+            let (name_len, tail) = rest.split_first_chunk::<2>()?;
+            let (name, tail) = tail.split_at_checked(u16::from_be_bytes(*name_len) as usize)?;
+            let (offset, tail) = tail.split_first_chunk::<8>()?;
+            let (len, tail) = tail.split_first_chunk::<8>()?;
+            rest = tail;
+
+            let name = std::str::from_utf8(name).ok()?;
+            let offset = usize::try_from(u64::from_be_bytes(*offset)).ok()?;
+            let len = usize::try_from(u64::from_be_bytes(*len)).ok()?;
+            if offset.checked_add(len)? > data.len() {
+                return None;
+            }
+            entries.push((name, offset, len));
+        }
+
+        Some(Self { data, entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.entries.iter().map(|&(name, _, _)| name)
+    }
+
+    // the encoded QOI bytes stored under `name`
+    pub fn get(&self, name: &str) -> Option<&'a [u8]> {
+        let &(_, offset, len) = self.entries.iter().find(|(n, _, _)| *n == name)?;
+        Some(&self.data[offset..offset + len])
+    }
+
+    pub fn decode(&self, name: &str) -> Option<Image<Pixel>> {
+        Decoder::new().decode(self.get(name)?).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: usize, height: usize, pixel: Pixel) -> Image<Pixel> {
+        Image {
+            width,
+            height,
+            pixels: vec![pixel; width * height],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let red = solid(3, 2, Pixel::new(255, 0, 0, 255));
+        let blue = solid(5, 7, Pixel::new(0, 0, 255, 128));
+
+        let mut writer = ArchiveWriter::new();
+        writer.add_image("red", &red).unwrap();
+        writer.add_image("blue", &red).unwrap();
+        writer.add_image("blue", &blue).unwrap();
+        let data = writer.finish();
+
+        let reader = ArchiveReader::new(&data).unwrap();
+        assert_eq!(reader.names().collect::<Vec<_>>(), ["red", "blue"]);
+
+        let decoded = reader.decode("blue").unwrap();
+        assert_eq!((decoded.width, decoded.height), (5, 7));
+        assert!(decoded.pixels.eq(&blue.pixels));
+        assert!(reader.decode("red").unwrap().pixels.eq(&red.pixels));
+        assert!(reader.get("green").is_none());
+
+        // an index pointing past the end of the data is rejected up front
+        assert!(ArchiveReader::new(&data[..data.len() - 1]).is_none());
+    }
+}
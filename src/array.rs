@@ -0,0 +1,75 @@
+//! Conversions between images and `ndarray` arrays of shape (height, width, 4),
+//! with the channels in RGBA order.
+
+use ndarray::{Array3, ArrayView3};
+
+use crate::{DecodeError, Decoder, Image, Pixel};
+
+impl From<&Image<Pixel>> for Array3<u8> {
+    fn from(image: &Image<Pixel>) -> Self {
+        let data = image.pixels.iter().flat_map(Pixel::to_bytes).collect();
+        Array3::from_shape_vec((image.height, image.width, 4), data)
+            .expect("pixel count matches the image dimensions")
+    }
+}
+
+impl TryFrom<ArrayView3<'_, u8>> for Image<Pixel> {
+    type Error = ndarray::ShapeError;
+
+    // fails unless the last axis has exactly 4 channels
+    fn try_from(array: ArrayView3<'_, u8>) -> Result<Self, Self::Error> {
+        let (height, width, channels) = array.dim();
+        if channels != 4 {
+            return Err(ndarray::ShapeError::from_kind(
+                ndarray::ErrorKind::IncompatibleShape,
+            ));
+        }
+        let pixels = array
+            .rows()
+            .into_iter()
+            .map(|px| Pixel::new(px[0], px[1], px[2], px[3]))
+            .collect();
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+pub fn decode_to_ndarray(data: &[u8]) -> Result<Array3<u8>, DecodeError> {
+    Decoder::new()
+        .decode(data)
+        .map(|image| Array3::from(&image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encoder;
+
+    #[test]
+    fn round_trip() {
+        let pixels = (0..6u8)
+            .map(|i| Pixel::new(i, i * 2, i * 3, 255 - i))
+            .collect();
+        let image = Image {
+            width: 3,
+            height: 2,
+            pixels,
+        };
+
+        let array = Array3::from(&image);
+        assert_eq!(array.dim(), (2, 3, 4));
+        assert_eq!(array[[1, 2, 1]], 10);
+
+        let back = Image::try_from(array.view()).unwrap();
+        assert!(back.pixels.eq(&image.pixels));
+
+        let data = Encoder::new(3, 2).encode(&image.pixels).unwrap();
+        assert_eq!(decode_to_ndarray(&data).unwrap(), array);
+
+        let rgb = Array3::<u8>::zeros((2, 3, 3));
+        assert!(Image::try_from(rgb.view()).is_err());
+    }
+}
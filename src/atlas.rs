@@ -0,0 +1,316 @@
+//! Sprite sheets: many images packed into one, with a JSON manifest of the
+//! rectangle each was put in.
+//!
+//! The manifest lists the sprites in the order they were given:
+//!
+//! ```text
+//! {
+//!   "width": 96,
+//!   "height": 48,
+//!   "sprites": {
+//!     "player.png": {"x": 0, "y": 0, "w": 32, "h": 48},
+//!     "coin.png": {"x": 32, "y": 0, "w": 16, "h": 16}
+//!   }
+//! }
+//! ```
+
+use std::fmt::Write;
+
+use crate::{Image, Pixel};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+pub struct Atlas {
+    pub image: Image<Pixel>,
+    pub sprites: Vec<(String, Rect)>,
+}
+
+impl Atlas {
+    // Packs the sprites onto shelves, tallest first, in a sheet about as wide
+    // as it is high, with `padding` transparent pixels between them.
+    pub fn pack(sprites: &[(&str, &Image<Pixel>)], padding: usize) -> Atlas {
+        let area = sprites
+            .iter()
+            .map(|(_, image)| (image.width + padding) * (image.height + padding))
+            .sum::<usize>();
+        let widest = sprites
+            .iter()
+            .map(|(_, image)| image.width)
+            .max()
+            .unwrap_or(0);
+        let max_width = widest.max(area.isqrt());
+
+        let mut order = (0..sprites.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| std::cmp::Reverse(sprites[i].1.height));
+        let mut rects = vec![Rect::default(); sprites.len()];
+        let (mut x, mut y, mut shelf, mut width) = (0, 0, 0, 0);
+        for i in order {
+            let image = sprites[i].1;
+            if x > 0 && x + image.width > max_width {
+                y += shelf + padding;
+                (x, shelf) = (0, 0);
+            }
+            rects[i] = Rect {
+                x,
+                y,
+                width: image.width,
+                height: image.height,
+            };
+            width = width.max(x + image.width);
+            shelf = shelf.max(image.height);
+            x += image.width + padding;
+        }
+        let height = y + shelf;
+
+        let mut sheet = Image::from_fn(width, height, |_, _| Pixel::new(0, 0, 0, 0));
+        for ((_, image), rect) in sprites.iter().zip(&rects) {
+            for (x, y, &pixel) in image.enumerate_pixels() {
+                sheet[(rect.x + x, rect.y + y)] = pixel;
+            }
+        }
+        Atlas {
+            image: sheet,
+            sprites: sprites
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .zip(rects)
+                .collect(),
+        }
+    }
+
+    // Pairs a sheet with its manifest, checking that every rectangle lies
+    // within the sheet.
+    pub fn from_manifest(image: Image<Pixel>, manifest: &str) -> Option<Atlas> {
+        let mut parser = Parser {
+            rest: manifest.trim_start(),
+        };
+        let mut size = (None, None);
+        let mut sprites = None;
+        parser.object(|parser, key| {
+            match key.as_str() {
+                "width" => size.0 = Some(parser.number()?),
+                "height" => size.1 = Some(parser.number()?),
+                "sprites" => {
+                    let mut list = vec![];
+                    parser.object(|parser, name| {
+                        list.push((name, parser.rect()?));
+                        Some(())
+                    })?;
+                    sprites = Some(list);
+                }
+                _ => return None,
+            }
+            Some(())
+        })?;
+        if !parser.rest.trim().is_empty() || size != (Some(image.width), Some(image.height)) {
+            return None;
+        }
+
+        let sprites = sprites?;
+        // checked, as a crafted manifest can make the sums overflow
+        let inside = |rect: &Rect| {
+            rect.x.checked_add(rect.width).is_some_and(|end| end <= image.width)
+                && rect.y.checked_add(rect.height).is_some_and(|end| end <= image.height)
+        };
+        if !sprites.iter().all(|(_, rect)| inside(rect)) {
+            return None;
+        }
+        Some(Atlas { image, sprites })
+    }
+
+    pub fn manifest(&self) -> String {
+        let mut json = format!(
+            "{{\n  \"width\": {},\n  \"height\": {},\n  \"sprites\": {{",
+            self.image.width, self.image.height
+        );
+        for (i, (name, rect)) in self.sprites.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            let Rect {
+                x,
+                y,
+                width,
+                height,
+            } = rect;
+            let _ = write!(
+                json,
+                "{separator}\n    {}: {{\"x\": {x}, \"y\": {y}, \"w\": {width}, \"h\": {height}}}",
+                quote(name)
+            );
+        }
+        json += "\n  }\n}\n";
+        json
+    }
+
+    // the first sprite called `name`
+    pub fn get(&self, name: &str) -> Option<Rect> {
+        self.sprites
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|&(_, rect)| rect)
+    }
+
+    // A copy of the sprite's pixels.
+    pub fn slice(&self, name: &str) -> Option<Image<Pixel>> {
+        let rect = self.get(name)?;
+        Some(Image::from_fn(rect.width, rect.height, |x, y| {
+            self.image[(rect.x + x, rect.y + y)]
+        }))
+    }
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            c if c < ' ' => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Just enough JSON for manifests: objects, strings and unsigned integers.
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, token: char) -> Option<()> {
+        self.rest = self.rest.strip_prefix(token)?.trim_start();
+        Some(())
+    }
+
+    // calls `field` with each key, which has to consume the value
+    fn object(&mut self, mut field: impl FnMut(&mut Self, String) -> Option<()>) -> Option<()> {
+        self.eat('{')?;
+        if self.eat('}').is_some() {
+            return Some(());
+        }
+        loop {
+            let key = self.string()?;
+            self.eat(':')?;
+            field(self, key)?;
+            if self.eat('}').is_some() {
+                return Some(());
+            }
+            self.eat(',')?;
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let mut chars = self.rest.strip_prefix('"')?.char_indices();
+        let mut text = String::new();
+        loop {
+            let (i, c) = chars.next()?;
+            match c {
+                '"' => {
+                    self.rest = self.rest[i + 2..].trim_start();
+                    return Some(text);
+                }
+                '\\' => text.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'u' => {
+                        let hex = (0..4)
+                            .map(|_| chars.next().map(|(_, c)| c))
+                            .collect::<Option<String>>()?;
+                        char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                    }
+                    c @ ('"' | '\\' | '/') => c,
+                    _ => return None,
+                }),
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let end = self
+            .rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.rest.len());
+        let number = self.rest[..end].parse().ok()?;
+        self.rest = self.rest[end..].trim_start();
+        Some(number)
+    }
+
+    fn rect(&mut self) -> Option<Rect> {
+        let mut fields = [None; 4];
+        self.object(|parser, key| {
+            let slot = ["x", "y", "w", "h"].iter().position(|&k| k == key)?;
+            fields[slot] = Some(parser.number()?);
+            Some(())
+        })?;
+        let [x, y, width, height] = fields;
+        Some(Rect {
+            x: x?,
+            y: y?,
+            width: width?,
+            height: height?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let sprite = |width, height, value| {
+            Image::from_fn(width, height, |x, y| {
+                Pixel::new(value, x as u8, y as u8, 255)
+            })
+        };
+        let (tall, wide, small) = (sprite(3, 5, 1), sprite(6, 2, 2), sprite(1, 1, 3));
+        let sprites = [("tall", &tall), ("wide \"one\"", &wide), ("small", &small)];
+        let atlas = Atlas::pack(&sprites, 1);
+
+        // no two sprites overlap, padding included
+        for (i, (_, a)) in atlas.sprites.iter().enumerate() {
+            for (_, b) in &atlas.sprites[i + 1..] {
+                let apart = a.x + a.width < b.x
+                    || b.x + b.width < a.x
+                    || a.y + a.height < b.y
+                    || b.y + b.height < a.y;
+                assert!(apart, "{a:?} and {b:?} overlap");
+            }
+        }
+
+        let manifest = atlas.manifest();
+        let image = Image {
+            width: atlas.image.width,
+            height: atlas.image.height,
+            pixels: atlas.image.pixels.clone(),
+        };
+        let loaded = Atlas::from_manifest(image, &manifest).unwrap();
+        assert_eq!(loaded.sprites, atlas.sprites);
+        for (name, image) in sprites {
+            assert_eq!(loaded.slice(name).unwrap().pixels, image.pixels);
+        }
+        assert!(loaded.slice("missing").is_none());
+
+        // rectangles have to fit the sheet
+        let sheet = || Image::from_fn(2, 2, |_, _| Pixel::new(0, 0, 0, 0));
+        let manifest = |x| {
+            format!(
+                r#"{{"width": 2, "height": 2, "sprites": {{"a": {{"x": {x}, "y": 0, "w": 2, "h": 1}}}}}}"#
+            )
+        };
+        assert!(Atlas::from_manifest(sheet(), &manifest(0)).is_some());
+        assert!(Atlas::from_manifest(sheet(), &manifest(1)).is_none());
+        assert!(Atlas::from_manifest(sheet(), &manifest(usize::MAX)).is_none());
+    }
+}
@@ -0,0 +1,228 @@
+//! Encoding and decoding many images at once across threads, for asset
+//! pipelines and the like.
+//!
+//! Work is spread over scoped threads, one image per task. Results come back in
+//! input order, and at most `2 * threads` of them are held before being handed
+//! over, so the `_each` variants decode or encode arbitrarily many images in
+//! bounded memory as long as the callback lets go of them.
+
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+
+use crate::{Image, Pixel};
+#[cfg(feature = "decode")]
+use crate::{DecodeError, Decoder};
+#[cfg(feature = "encode")]
+use crate::{EncodeError, Encoder};
+
+#[cfg(feature = "encode")]
+pub fn encode_all(images: &[Image<Pixel>], threads: usize) -> Vec<Result<Vec<u8>, EncodeError>> {
+    let mut results = Vec::with_capacity(images.len());
+    encode_each(images, threads, |_, result| results.push(result));
+    results
+}
+
+#[cfg(feature = "decode")]
+pub fn decode_all<D: AsRef<[u8]> + Sync>(
+    files: &[D],
+    threads: usize,
+) -> Vec<Result<Image<Pixel>, DecodeError>> {
+    let mut results = Vec::with_capacity(files.len());
+    decode_each(files, threads, |_, result| results.push(result));
+    results
+}
+
+// Calls `f` with the index of each image and its encoding, in order. A
+// `threads` of 0 uses one per available core.
+#[cfg(feature = "encode")]
+pub fn encode_each(
+    images: &[Image<Pixel>],
+    threads: usize,
+    f: impl FnMut(usize, Result<Vec<u8>, EncodeError>),
+) {
+    map_ordered(images, threads, encode, f);
+}
+
+#[cfg(feature = "decode")]
+pub fn decode_each<D: AsRef<[u8]> + Sync>(
+    files: &[D],
+    threads: usize,
+    f: impl FnMut(usize, Result<Image<Pixel>, DecodeError>),
+) {
+    map_ordered(
+        files,
+        threads,
+        |data| Decoder::new().decode(data.as_ref()),
+        f,
+    );
+}
+
+#[cfg(feature = "encode")]
+fn encode(image: &Image<Pixel>) -> Result<Vec<u8>, EncodeError> {
+    let width = u32::try_from(image.width).map_err(|_| EncodeError::LimitsExceeded)?;
+    let height = u32::try_from(image.height).map_err(|_| EncodeError::LimitsExceeded)?;
+    Encoder::new(width, height).encode(&image.pixels)
+}
+
+struct Queue<R> {
+    // the next item to start on and the next to hand over
+    next: usize,
+    emitted: usize,
+    // finished out of order; a panic is passed on when its turn comes
+    ready: BTreeMap<usize, thread::Result<R>>,
+    // set once nothing more will be handed over, so idle workers stop waiting
+    cancelled: bool,
+}
+
+// Cancels the queue when the handing-over loop ends, including by unwinding out
+// of `emit` or a passed-on panic; workers blocked on a window that will never
+// move again would otherwise keep the scope from ever returning.
+struct Cancel<'a, R> {
+    queue: &'a Mutex<Queue<R>>,
+    changed: &'a Condvar,
+}
+
+impl<R> Drop for Cancel<'_, R> {
+    fn drop(&mut self) {
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.cancelled = true;
+        drop(queue);
+        self.changed.notify_all();
+    }
+}
+
+fn map_ordered<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    work: impl Fn(&T) -> R + Sync,
+    mut emit: impl FnMut(usize, R),
+) {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let threads = threads.min(items.len());
+    let window = threads * 2;
+    let queue = Mutex::new(Queue {
+        next: 0,
+        emitted: 0,
+        ready: BTreeMap::new(),
+        cancelled: false,
+    });
+    let changed = Condvar::new();
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                loop {
+                    let i = {
+                        let mut queue = queue.lock().unwrap();
+                        while !queue.cancelled
+                            && queue.next < items.len()
+                            && queue.next >= queue.emitted + window
+                        {
+                            queue = changed.wait(queue).unwrap();
+                        }
+                        if queue.cancelled || queue.next == items.len() {
+                            return;
+                        }
+                        queue.next += 1;
+                        queue.next - 1
+                    };
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| work(&items[i])));
+                    queue.lock().unwrap().ready.insert(i, result);
+                    changed.notify_all();
+                }
+            });
+        }
+
+        let _cancel = Cancel {
+            queue: &queue,
+            changed: &changed,
+        };
+        for i in 0..items.len() {
+            let result = {
+                let mut queue = queue.lock().unwrap();
+                loop {
+                    if let Some(result) = queue.ready.remove(&i) {
+                        queue.emitted = i + 1;
+                        break result;
+                    }
+                    queue = changed.wait(queue).unwrap();
+                }
+            };
+            changed.notify_all();
+            match result {
+                Ok(result) => emit(i, result),
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        }
+    });
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_order() {
+        let images = (0..25)
+            .map(|i| {
+                Image::from_fn(i % 7 + 1, i / 3 + 1, |x, y| {
+                    Pixel::new(x as u8, y as u8, i as u8, 255)
+                })
+            })
+            .collect::<Vec<_>>();
+        let expected = images
+            .iter()
+            .map(|image| encode(image).unwrap())
+            .collect::<Vec<_>>();
+
+        for threads in [0, 1, 3, 64] {
+            let encoded = encode_all(&images, threads)
+                .into_iter()
+                .map(Result::unwrap)
+                .collect::<Vec<_>>();
+            assert_eq!(encoded, expected);
+
+            let decoded = decode_all(&encoded, threads);
+            for (image, decoded) in images.iter().zip(decoded) {
+                assert!(decoded.unwrap().pixels == image.pixels);
+            }
+        }
+
+        let mut files = expected.clone();
+        files[3].truncate(20);
+        let mut seen = vec![];
+        decode_each(&files, 4, |i, result| seen.push((i, result.is_ok())));
+        assert_eq!(seen.len(), 25);
+        assert!(
+            seen.iter()
+                .enumerate()
+                .all(|(i, &(index, ok))| i == index && ok == (i != 3))
+        );
+
+        assert!(decode_all::<Vec<u8>>(&[], 4).is_empty());
+        let empty = Image::from_fn(0, 0, |_, _| Pixel::new(0, 0, 0, 0));
+        assert_eq!(encode_all(&[empty], 2)[0], Err(EncodeError::ZeroDimensions));
+    }
+
+    #[test]
+    fn panics_propagate() {
+        let images = (0..20)
+            .map(|_| Image::from_fn(4, 4, |x, y| Pixel::new(x as u8, y as u8, 0, 255)))
+            .collect::<Vec<_>>();
+        let callback = panic::catch_unwind(AssertUnwindSafe(|| {
+            encode_each(&images, 2, |i, _| assert_ne!(i, 0, "callback"));
+        }));
+        assert!(callback.is_err());
+
+        let items = (0..20).collect::<Vec<_>>();
+        let work = panic::catch_unwind(|| {
+            map_ordered(&items, 2, |&i| assert_ne!(i, 0, "work"), |_, _| {});
+        });
+        assert!(work.is_err());
+    }
+}
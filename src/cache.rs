@@ -0,0 +1,181 @@
+use crate::Pixel;
+
+// Where the 64 INDEX slots come from. Encoder and decoder must use the same policy,
+// and both call `insert` exactly once for the pixel of every non-RUN op (including
+// INDEX ops), so stateful policies stay in sync.
+pub trait CachePolicy: Default {
+    fn find(&self, pixel: Pixel) -> Option<u8>;
+    fn get(&self, idx: u8) -> Pixel;
+    fn insert(&mut self, pixel: Pixel);
+
+    // Called by the decoder only, with the pixel repeated by a RUN op.
+    fn insert_run(&mut self, _pixel: Pixel) {}
+}
+
+// The index cache mandated by the spec, required for interchange.
+pub struct SpecCache([Pixel; 64]);
+
+// zero-initialized, as in the spec
+impl Default for SpecCache {
+    fn default() -> Self {
+        Self([Pixel::new(0, 0, 0, 0); 64])
+    }
+}
+
+#[cfg(any(feature = "encode", feature = "decode"))]
+impl SpecCache {
+    // the 64 slots, for saving and restoring the state of a decode
+    pub(crate) fn slots(&self) -> [Pixel; 64] {
+        self.0
+    }
+
+    pub(crate) fn from_slots(slots: [Pixel; 64]) -> Self {
+        Self(slots)
+    }
+}
+
+impl CachePolicy for SpecCache {
+    fn find(&self, pixel: Pixel) -> Option<u8> {
+        let h = pixel.hash();
+        self.0[h as usize].eq(&pixel).then_some(h)
+    }
+
+    fn get(&self, idx: u8) -> Pixel {
+        self.0[idx as usize]
+    }
+
+    fn insert(&mut self, pixel: Pixel) {
+        self.0[pixel.hash() as usize] = pixel;
+    }
+
+    // qoi.h records every decoded pixel, runs included. That only differs from
+    // the encoder for a run at the very start, whose (0, 0, 0, 255) is not cached.
+    fn insert_run(&mut self, pixel: Pixel) {
+        self.insert(pixel);
+    }
+}
+
+// Same layout as the spec cache with a multiplicative hash over all 32 bits,
+// which spreads similar colors better than the 3/5/7/11 sum.
+#[cfg(feature = "nonstandard")]
+pub struct MixHashCache([Pixel; 64]);
+
+#[cfg(feature = "nonstandard")]
+impl MixHashCache {
+    fn hash(pixel: Pixel) -> u8 {
+        let packed = u32::from_be_bytes(pixel.to_bytes());
+        (packed.wrapping_mul(0x9e37_79b1) >> 26) as u8
+    }
+}
+
+#[cfg(feature = "nonstandard")]
+impl Default for MixHashCache {
+    fn default() -> Self {
+        Self([Pixel::new(0, 0, 0, 255); 64])
+    }
+}
+
+#[cfg(feature = "nonstandard")]
+impl CachePolicy for MixHashCache {
+    fn find(&self, pixel: Pixel) -> Option<u8> {
+        let h = Self::hash(pixel);
+        self.0[h as usize].eq(&pixel).then_some(h)
+    }
+
+    fn get(&self, idx: u8) -> Pixel {
+        self.0[idx as usize]
+    }
+
+    fn insert(&mut self, pixel: Pixel) {
+        self.0[Self::hash(pixel) as usize] = pixel;
+    }
+}
+
+// 32 sets of 2 ways selected by the spec hash, evicting the least recently used way.
+// Slot `idx` is way `idx & 1` of set `idx >> 1`.
+#[cfg(feature = "nonstandard")]
+pub struct TwoWayCache {
+    slots: [Pixel; 64],
+    // per set, the way to evict next
+    lru: [u8; 32],
+}
+
+#[cfg(feature = "nonstandard")]
+impl Default for TwoWayCache {
+    fn default() -> Self {
+        Self {
+            slots: [Pixel::new(0, 0, 0, 255); 64],
+            lru: [0; 32],
+        }
+    }
+}
+
+#[cfg(feature = "nonstandard")]
+impl CachePolicy for TwoWayCache {
+    fn find(&self, pixel: Pixel) -> Option<u8> {
+        let set = pixel.hash() % 32;
+        (0..2)
+            .map(|way| set * 2 + way)
+            .find(|&idx| self.slots[idx as usize] == pixel)
+    }
+
+    fn get(&self, idx: u8) -> Pixel {
+        self.slots[idx as usize]
+    }
+
+    fn insert(&mut self, pixel: Pixel) {
+        let set = pixel.hash() % 32;
+        let way = match self.find(pixel) {
+            Some(idx) => idx & 1,
+            None => {
+                let way = self.lru[set as usize];
+                self.slots[(set * 2 + way) as usize] = pixel;
+                way
+            }
+        };
+        self.lru[set as usize] = way ^ 1;
+    }
+}
+
+#[cfg(all(test, feature = "nonstandard"))]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+    use crate::{Decoder, Encoder};
+
+    fn round_trip<C: CachePolicy>(img: &[Pixel], width: u32, height: u32) -> f64 {
+        let mut encoder = Encoder::<C>::with_cache(width, height);
+        let data = encoder.encode(img).unwrap();
+        let decoded = Decoder::<C>::with_cache().decode(&data).unwrap();
+        assert!(decoded.pixels.eq(img));
+        encoder.stats().cache_hit_rate()
+    }
+
+    #[test]
+    fn policies_round_trip() {
+        let img = image::ImageReader::open("assets/suz.png")
+            .unwrap()
+            .decode()
+            .unwrap();
+        let (width, height) = (img.width(), img.height());
+        let pixels = img
+            .as_rgba8()
+            .unwrap()
+            .pixels()
+            .map(|&Rgba::<u8>([r, g, b, a])| Pixel::new(r, g, b, a))
+            .collect::<Vec<_>>();
+
+        let spec = round_trip::<SpecCache>(&pixels, width, height);
+        let mix_hash = round_trip::<MixHashCache>(&pixels, width, height);
+        let two_way = round_trip::<TwoWayCache>(&pixels, width, height);
+        for rate in [spec, mix_hash, two_way] {
+            assert!((0.0..=1.0).contains(&rate), "hit rate {rate}");
+        }
+        // two-way trades half of the hash buckets for a second way per set, so it
+        // is not a superset of the spec cache, but should stay within a point of it
+        assert!(spec > 0.5, "spec {spec}");
+        assert!(mix_hash > spec - 0.01, "mix hash {mix_hash} vs spec {spec}");
+        assert!(two_way > spec - 0.01, "two-way {two_way} vs spec {spec}");
+    }
+}
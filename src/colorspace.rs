@@ -0,0 +1,110 @@
+//! Conversions between the two colorspaces a QOI header can declare: 0, sRGB
+//! with linear alpha, and 1, all channels linear.
+//!
+//! Both directions go through 256-entry lookup tables on 8-bit values, so a
+//! round trip loses precision in the darks, where linear values are bunched
+//! together. Alpha is linear in both and left alone.
+
+use std::sync::LazyLock;
+
+use crate::{Header, Image, Pixel};
+
+pub const SRGB: u8 = 0;
+pub const LINEAR: u8 = 1;
+
+static TO_LINEAR: LazyLock<[u8; 256]> = LazyLock::new(|| {
+    table(|c| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    })
+});
+
+static TO_SRGB: LazyLock<[u8; 256]> = LazyLock::new(|| {
+    table(|c| {
+        if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    })
+});
+
+fn table(f: impl Fn(f32) -> f32) -> [u8; 256] {
+    std::array::from_fn(|i| (f(i as f32 / 255.0) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+fn apply(pixels: &mut [Pixel], table: &[u8; 256]) {
+    for pixel in pixels {
+        pixel.r = table[pixel.r as usize];
+        pixel.g = table[pixel.g as usize];
+        pixel.b = table[pixel.b as usize];
+    }
+}
+
+pub fn srgb_to_linear(pixels: &mut [Pixel]) {
+    apply(pixels, &TO_LINEAR);
+}
+
+pub fn linear_to_srgb(pixels: &mut [Pixel]) {
+    apply(pixels, &TO_SRGB);
+}
+
+// Converts `image`, described by `header`, to `colorspace` and updates the
+// header to match. Images already in that colorspace are left as they are.
+pub fn normalize(image: &mut Image<Pixel>, header: &mut Header, colorspace: u8) {
+    match (header.colorspace, colorspace) {
+        (SRGB, LINEAR) => srgb_to_linear(&mut image.pixels),
+        (LINEAR, SRGB) => linear_to_srgb(&mut image.pixels),
+        _ => return,
+    }
+    header.colorspace = colorspace;
+}
+
+impl Image<Pixel> {
+    pub fn to_linear(&self) -> Image<Pixel> {
+        let mut image = self.map_pixels(|pixel| pixel);
+        srgb_to_linear(&mut image.pixels);
+        image
+    }
+
+    pub fn to_srgb(&self) -> Image<Pixel> {
+        let mut image = self.map_pixels(|pixel| pixel);
+        linear_to_srgb(&mut image.pixels);
+        image
+    }
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use super::*;
+    use crate::{Decoder, Encoder};
+
+    #[test]
+    fn conversions() {
+        let image = Image::from_fn(256, 1, |x, _| Pixel::new(x as u8, 128, 255, 77));
+        let linear = image.to_linear();
+        assert_eq!(linear[(0, 0)], Pixel::new(0, 55, 255, 77));
+        assert_eq!(linear[(10, 0)].r, 1);
+        // the lighter half survives a round trip
+        let back = linear.to_srgb();
+        assert!((128..256).all(|x| back[(x, 0)].r.abs_diff(x as u8) <= 1));
+        assert!(back.pixels.iter().all(|pixel| pixel.a == 77));
+
+        let data = Encoder::new(256, 1)
+            .colorspace(LINEAR)
+            .encode(&linear.pixels)
+            .unwrap();
+        let mut decoder = Decoder::new();
+        let mut decoded = decoder.decode(&data).unwrap();
+        let mut header = decoder.header().unwrap();
+        normalize(&mut decoded, &mut header, SRGB);
+        assert_eq!(header.colorspace, SRGB);
+        assert_eq!(decoded.pixels, back.pixels);
+        // already sRGB
+        normalize(&mut decoded, &mut header, SRGB);
+        assert_eq!(decoded.pixels, back.pixels);
+    }
+}
@@ -0,0 +1,499 @@
+//! What the encoder and decoder share: the header, pixel and image types,
+//! limits on dimensions and the hooks for cancelling and progress. Everything
+//! here is built with either half of the codec, or with neither.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::num::Wrapping;
+use std::ops::{Index, IndexMut};
+#[cfg(any(feature = "encode", feature = "decode"))]
+use std::sync::Arc;
+#[cfg(any(feature = "encode", feature = "decode"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{ParseHexError, convert};
+
+#[cfg(any(feature = "encode", feature = "decode"))]
+pub(crate) const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+// channels is 3 (RGB) or 4 (RGBA) and colorspace 0 (sRGB with linear alpha) or
+// 1 (all linear); both are informative only and do not change how pixels decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u8,
+    pub colorspace: u8,
+}
+
+impl Header {
+    #[cfg(any(feature = "encode", feature = "decode"))]
+    pub(crate) fn append_bytes(&self, magic: &[u8; 4], buf: &mut Vec<u8>) {
+        buf.extend(magic);
+        buf.extend(self.width.to_be_bytes());
+        buf.extend(self.height.to_be_bytes());
+        buf.push(self.channels);
+        buf.push(self.colorspace);
+    }
+
+    #[cfg(feature = "decode")]
+    pub(crate) fn from_bytes<'a>(magic: &[u8; 4], data: &'a [u8]) -> Option<(Self, &'a [u8])> {
+        let (file_magic, data) = data.split_at_checked(4)?;
+        if !file_magic.eq(magic) {
+            return None;
+        }
+
+        let (width_bytes, data) = data.split_first_chunk::<4>()?;
+        let width = u32::from_be_bytes(*width_bytes);
+        let (height_bytes, data) = data.split_first_chunk::<4>()?;
+        let height = u32::from_be_bytes(*height_bytes);
+
+        let (&channels, data) = data.split_first()?;
+        let (&colorspace, data) = data.split_first()?;
+
+        Some((Header { width, height, channels, colorspace }, data))
+    }
+}
+
+// `Pixel` alone is the usual RGBA pixel. `Pixel<3>` has no alpha: its `a` is
+// `()`, so it takes three bytes, a quarter less memory for opaque images, and
+// the encoder writes images of it as 3-channel files.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct Pixel<const N: usize = 4>
+where
+    Channels<N>: ChannelCount,
+{
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: <Channels<N> as ChannelCount>::Alpha,
+}
+
+// SAFETY: three or four `u8` fields with `repr(C)` (alpha is `()` in `Pixel<3>`),
+// so no padding and any bit pattern is valid
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Zeroable for Pixel {}
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Pod for Pixel {}
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Zeroable for Pixel<3> {}
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Pod for Pixel<3> {}
+
+// The channel count `N` of a `Pixel<N>`; only 3 and 4 implement `ChannelCount`.
+pub struct Channels<const N: usize>;
+
+pub trait ChannelCount {
+    // what a pixel stores for alpha
+    type Alpha: Copy + fmt::Debug + PartialEq;
+
+    fn alpha(alpha: Self::Alpha) -> u8;
+    // drops alpha when there is none to store it in
+    fn from_alpha(alpha: u8) -> Self::Alpha;
+}
+
+impl ChannelCount for Channels<3> {
+    type Alpha = ();
+
+    fn alpha((): ()) -> u8 {
+        255
+    }
+
+    fn from_alpha(_: u8) {}
+}
+
+impl ChannelCount for Channels<4> {
+    type Alpha = u8;
+
+    fn alpha(alpha: u8) -> u8 {
+        alpha
+    }
+
+    fn from_alpha(alpha: u8) -> u8 {
+        alpha
+    }
+}
+
+impl<const N: usize> Pixel<N>
+where
+    Channels<N>: ChannelCount,
+{
+    pub const CHANNELS: u8 = N as u8;
+
+    // opaque when there is no alpha
+    pub fn to_rgba(self) -> Pixel {
+        Pixel::<4>::new(self.r, self.g, self.b, Channels::<N>::alpha(self.a))
+    }
+
+    pub fn from_rgba(Pixel { r, g, b, a }: Pixel) -> Self {
+        Self { r, g, b, a: Channels::<N>::from_alpha(a) }
+    }
+}
+
+impl Pixel<3> {
+    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: () }
+    }
+}
+
+// Channel order of a pixel packed into a `u32`, most significant byte first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelOrder {
+    Rgba,
+    Argb,
+    Bgra,
+    Abgr,
+    // like `Argb` with the top byte zeroed, which is what softbuffer expects
+    Xrgb,
+}
+
+pub struct Image<T> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<T>,
+}
+
+impl<T> Image<T> {
+    // Calls `f` for every position in row-major order.
+    pub fn from_fn(width: usize, height: usize, mut f: impl FnMut(usize, usize) -> T) -> Self {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+        Image { width, height, pixels }
+    }
+
+    // Row-major offset of (x, y), or None outside the image.
+    fn offset(&self, x: usize, y: usize) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y * self.width + x)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        self.offset(x, y).and_then(|i| self.pixels.get(i))
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
+        self.offset(x, y).and_then(|i| self.pixels.get_mut(i))
+    }
+
+    // Pixels in row-major order together with their (x, y) position.
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (usize, usize, &T)> {
+        let width = self.width;
+        self.pixels.iter().enumerate().map(move |(i, pixel)| (i % width, i / width, pixel))
+    }
+
+    pub fn enumerate_pixels_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut T)> {
+        let width = self.width;
+        self.pixels.iter_mut().enumerate().map(move |(i, pixel)| (i % width, i / width, pixel))
+    }
+}
+
+impl<T> Index<(usize, usize)> for Image<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        let (width, height) = (self.width, self.height);
+        self.get(x, y)
+            .unwrap_or_else(|| panic!("pixel ({x}, {y}) is outside the {width}x{height} image"))
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Image<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        let (width, height) = (self.width, self.height);
+        self.get_mut(x, y)
+            .unwrap_or_else(|| panic!("pixel ({x}, {y}) is outside the {width}x{height} image"))
+    }
+}
+
+impl<T: Copy> Image<T> {
+    /// Nearest-neighbour downscale that fits the image into a `max_size` square,
+    /// keeping the aspect ratio. Images that already fit are copied as-is, and
+    /// images without pixels, such as 0x1000, come out 0x0.
+    pub fn thumbnail(&self, max_size: usize) -> Image<T> {
+        if self.width == 0 || self.height == 0 {
+            return Image { width: 0, height: 0, pixels: Vec::new() };
+        }
+        let largest = self.width.max(self.height);
+        if largest <= max_size {
+            return Image {
+                width: self.width,
+                height: self.height,
+                pixels: self.pixels.clone(),
+            };
+        }
+
+        let width = (self.width * max_size / largest).max(1);
+        let height = (self.height * max_size / largest).max(1);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let src_y = y * self.height / height;
+            for x in 0..width {
+                let src_x = x * self.width / width;
+                pixels.push(self.pixels[src_y * self.width + src_x]);
+            }
+        }
+
+        Image { width, height, pixels }
+    }
+
+    pub fn map_pixels<U>(&self, f: impl FnMut(T) -> U) -> Image<U> {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().copied().map(f).collect(),
+        }
+    }
+
+    pub fn map_pixels_in_place(&mut self, mut f: impl FnMut(T) -> T) {
+        for pixel in &mut self.pixels {
+            *pixel = f(*pixel);
+        }
+    }
+
+    /// The image turned a quarter turn clockwise.
+    pub fn rotate_cw(&self) -> Image<T> {
+        Image::from_fn(self.height, self.width, |x, y| {
+            self.pixels[(self.height - 1 - x) * self.width + y]
+        })
+    }
+
+    /// The image turned a quarter turn counterclockwise.
+    pub fn rotate_ccw(&self) -> Image<T> {
+        Image::from_fn(self.height, self.width, |x, y| {
+            self.pixels[x * self.width + self.width - 1 - y]
+        })
+    }
+
+    /// The image mirrored left to right.
+    pub fn flip_horizontal(&self) -> Image<T> {
+        Image::from_fn(self.width, self.height, |x, y| {
+            self.pixels[y * self.width + self.width - 1 - x]
+        })
+    }
+
+    /// The image mirrored top to bottom.
+    pub fn flip_vertical(&self) -> Image<T> {
+        Image::from_fn(self.width, self.height, |x, y| {
+            self.pixels[(self.height - 1 - y) * self.width + x]
+        })
+    }
+}
+
+impl Image<Pixel> {
+    pub fn into_rgba_bytes(self) -> Vec<u8> {
+        let mut bytes = vec![0; self.pixels.len() * 4];
+        convert::pixels_to_rgba(&self.pixels, &mut bytes);
+        bytes
+    }
+
+    // Borrows the pixel buffer when the `bytemuck` feature is enabled, copies otherwise.
+    pub fn as_rgba_bytes(&self) -> Cow<'_, [u8]> {
+        #[cfg(feature = "bytemuck")]
+        return Cow::Borrowed(bytemuck::cast_slice(&self.pixels));
+        #[cfg(not(feature = "bytemuck"))]
+        {
+            let mut bytes = vec![0; self.pixels.len() * 4];
+            convert::pixels_to_rgba(&self.pixels, &mut bytes);
+            Cow::Owned(bytes)
+        }
+    }
+
+    pub fn to_u32_pixels(&self, order: PixelOrder) -> Vec<u32> {
+        let mut packed = vec![0; self.pixels.len()];
+        convert::pixels_to_u32(&self.pixels, &mut packed, order);
+        packed
+    }
+
+    // 3 when every pixel is opaque, as an encoder with `force_opaque` would write
+    fn channels(&self) -> u8 {
+        if self.pixels.iter().all(|pixel| pixel.a == 255) { 3 } else { 4 }
+    }
+
+    // FNV-1a over the RGBA bytes, enough to tell images apart in logs
+    fn digest(&self) -> u64 {
+        self.pixels
+            .iter()
+            .flat_map(Pixel::to_bytes)
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3))
+    }
+}
+
+// Summarizes instead of listing every pixel.
+impl fmt::Debug for Image<Pixel> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Image")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("channels", &self.channels())
+            .field("digest", &format_args!("{:016x}", self.digest()))
+            .finish()
+    }
+}
+
+impl fmt::Display for Image<Pixel> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.channels() == 3 { "RGB" } else { "RGBA" };
+        write!(f, "{}x{} {kind} image ({:016x})", self.width, self.height, self.digest())
+    }
+}
+
+impl Pixel {
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    pub(crate) fn hash(&self) -> u8 {
+        let &Pixel { r, g, b, a } = self;
+        let hash = (Wrapping(r) * Wrapping(3)
+                  + Wrapping(g) * Wrapping(5)
+                  + Wrapping(b) * Wrapping(7)
+                  + Wrapping(a) * Wrapping(11)) % Wrapping(64);
+        hash.0
+    }
+
+    pub fn to_bytes(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    pub fn to_u32(&self, order: PixelOrder) -> u32 {
+        let &Pixel { r, g, b, a } = self;
+        u32::from_be_bytes(match order {
+            PixelOrder::Rgba => [r, g, b, a],
+            PixelOrder::Argb => [a, r, g, b],
+            PixelOrder::Bgra => [b, g, r, a],
+            PixelOrder::Abgr => [a, b, g, r],
+            PixelOrder::Xrgb => [0, r, g, b],
+        })
+    }
+
+    // The inverse of `to_u32`; `Xrgb` pixels come out opaque.
+    pub fn from_u32(value: u32, order: PixelOrder) -> Self {
+        let [x, y, z, w] = value.to_be_bytes();
+        match order {
+            PixelOrder::Rgba => Pixel::new(x, y, z, w),
+            PixelOrder::Argb => Pixel::new(y, z, w, x),
+            PixelOrder::Bgra => Pixel::new(z, y, x, w),
+            PixelOrder::Abgr => Pixel::new(w, z, y, x),
+            PixelOrder::Xrgb => Pixel::new(y, z, w, 255),
+        }
+    }
+
+    // "#rrggbb" or "#rrggbbaa", case-insensitive; the "#" is optional and alpha
+    // defaults to 255.
+    pub fn from_hex(hex: &str) -> Result<Self, ParseHexError> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex).as_bytes();
+        if !matches!(digits.len(), 6 | 8) {
+            return Err(ParseHexError::InvalidLength);
+        }
+        let digit = |c: u8| (c as char).to_digit(16).ok_or(ParseHexError::InvalidDigit);
+        let mut channels = [255; 4];
+        for (channel, pair) in channels.iter_mut().zip(digits.chunks_exact(2)) {
+            *channel = (digit(pair[0])? * 16 + digit(pair[1])?) as u8;
+        }
+        let [r, g, b, a] = channels;
+        Ok(Pixel::new(r, g, b, a))
+    }
+
+    // The shorter "#rrggbb" form for opaque pixels.
+    pub fn to_hex(&self) -> String {
+        let Pixel { r, g, b, a } = *self;
+        match a {
+            255 => format!("#{r:02x}{g:02x}{b:02x}"),
+            _ => format!("#{r:02x}{g:02x}{b:02x}{a:02x}"),
+        }
+    }
+}
+
+impl std::str::FromStr for Pixel {
+    type Err = ParseHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pixel::from_hex(s)
+    }
+}
+
+// How many pixels are encoded or decoded between checks of a cancel flag.
+#[cfg(any(feature = "encode", feature = "decode"))]
+pub(crate) const CANCEL_INTERVAL: usize = 1 << 16;
+
+#[cfg(any(feature = "encode", feature = "decode"))]
+type Progress = Box<dyn FnMut(usize, usize) + Send>;
+
+// The cancel flag and progress callback of an encoder or decoder, both looked
+// at only every so many pixels.
+#[cfg(any(feature = "encode", feature = "decode"))]
+#[derive(Default)]
+pub(crate) struct Hooks {
+    pub(crate) cancel: Option<Arc<AtomicBool>>,
+    pub(crate) progress: Option<(usize, Progress)>,
+}
+
+#[cfg(any(feature = "encode", feature = "decode"))]
+impl Hooks {
+    // the pixel index to call `check` at first, never without a hook
+    pub(crate) fn first_check(&self) -> usize {
+        match (&self.cancel, &self.progress) {
+            (None, None) => usize::MAX,
+            _ => 0,
+        }
+    }
+
+    // Reports progress and returns where to check next, or None once cancelled.
+    pub(crate) fn check(&mut self, done: usize, total: usize) -> Option<usize> {
+        if self.cancel.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+            return None;
+        }
+        let mut interval = if self.cancel.is_some() { CANCEL_INTERVAL } else { usize::MAX };
+        if let Some((every, progress)) = &mut self.progress {
+            progress(done, total);
+            interval = interval.min(*every);
+        }
+        Some(done.saturating_add(interval))
+    }
+
+    pub(crate) fn finish(&mut self, done: usize, total: usize) {
+        if let Some((_, progress)) = &mut self.progress {
+            progress(done, total);
+        }
+    }
+}
+
+// Bounds on the dimensions of an image, checked on the header of a file before
+// anything is allocated for it, and before encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: usize,
+}
+
+impl Default for Limits {
+    // the same pixel cap as the reference qoi.h
+    fn default() -> Self {
+        Self {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixels: 400_000_000,
+        }
+    }
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixels: usize::MAX,
+        }
+    }
+
+    #[cfg(any(feature = "encode", feature = "decode"))]
+    pub(crate) fn allows(&self, width: u32, height: u32) -> bool {
+        width <= self.max_width
+            && height <= self.max_height
+            && (width as usize).checked_mul(height as usize).is_some_and(|n| n <= self.max_pixels)
+    }
+}
+
@@ -0,0 +1,356 @@
+//! Bulk pixel format conversions.
+//!
+//! Each function converts a whole slice, with SIMD kernels on x86_64 (SSSE3 is
+//! detected at runtime, SSE2 is always there) and scalar loops elsewhere. Both
+//! give identical results; the scalar versions are what the tests check against.
+//!
+//! Premultiplied pixels have their color channels scaled by alpha / 255, rounded
+//! to nearest; fully transparent pixels unpremultiply to transparent black.
+
+use crate::{Pixel, PixelOrder};
+
+// Panics unless `rgba` holds exactly 4 bytes for every pixel in `dst`.
+pub fn rgba_to_pixels(rgba: &[u8], dst: &mut [Pixel]) {
+    assert_eq!(rgba.len(), dst.len() * 4, "buffer lengths differ");
+    // SAFETY: `Pixel` is `repr(C)` with four `u8` fields, so it has the layout of
+    // `[u8; 4]` in RGBA order and any bytes are a valid pixel
+    unsafe { std::ptr::copy_nonoverlapping(rgba.as_ptr(), dst.as_mut_ptr().cast(), rgba.len()) }
+}
+
+// Panics unless `rgba` holds exactly 4 bytes for every pixel in `src`.
+pub fn pixels_to_rgba(src: &[Pixel], rgba: &mut [u8]) {
+    assert_eq!(rgba.len(), src.len() * 4, "buffer lengths differ");
+    // SAFETY: as in `rgba_to_pixels`
+    unsafe { std::ptr::copy_nonoverlapping(src.as_ptr().cast(), rgba.as_mut_ptr(), rgba.len()) }
+}
+
+// Same as `Pixel::to_u32` on every pixel. Panics unless the slices are the same length.
+pub fn pixels_to_u32(src: &[Pixel], dst: &mut [u32], order: PixelOrder) {
+    assert_eq!(src.len(), dst.len(), "buffer lengths differ");
+    #[cfg(target_arch = "x86_64")]
+    if std::arch::is_x86_feature_detected!("ssse3") {
+        let (table, fill) = match order {
+            PixelOrder::Rgba => ([3, 2, 1, 0], 0),
+            PixelOrder::Argb => ([2, 1, 0, 3], 0),
+            PixelOrder::Bgra => ([3, 0, 1, 2], 0),
+            PixelOrder::Abgr => ([0, 1, 2, 3], 0),
+            PixelOrder::Xrgb => ([2, 1, 0, x86::ZERO], 0),
+        };
+        // SAFETY: checked for SSSE3 above, and both slices hold `src.len()` 4-byte items
+        return unsafe {
+            x86::shuffle(
+                src.as_ptr().cast(),
+                dst.as_mut_ptr().cast(),
+                src.len(),
+                table,
+                fill,
+            )
+        };
+    }
+    pixels_to_u32_scalar(src, dst, order)
+}
+
+// Same as `Pixel::from_u32` on every value. Panics unless the slices are the same length.
+pub fn u32_to_pixels(src: &[u32], dst: &mut [Pixel], order: PixelOrder) {
+    assert_eq!(src.len(), dst.len(), "buffer lengths differ");
+    #[cfg(target_arch = "x86_64")]
+    if std::arch::is_x86_feature_detected!("ssse3") {
+        let (table, fill) = match order {
+            PixelOrder::Rgba => ([3, 2, 1, 0], 0),
+            PixelOrder::Argb => ([2, 1, 0, 3], 0),
+            PixelOrder::Bgra => ([1, 2, 3, 0], 0),
+            PixelOrder::Abgr => ([0, 1, 2, 3], 0),
+            PixelOrder::Xrgb => ([2, 1, 0, x86::ZERO], 0xff00_0000),
+        };
+        // SAFETY: as in `pixels_to_u32`
+        return unsafe {
+            x86::shuffle(
+                src.as_ptr().cast(),
+                dst.as_mut_ptr().cast(),
+                src.len(),
+                table,
+                fill,
+            )
+        };
+    }
+    u32_to_pixels_scalar(src, dst, order)
+}
+
+pub fn premultiply(pixels: &mut [Pixel]) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: SSE2 is part of the x86_64 baseline
+    let pixels = unsafe { x86::premultiply(pixels) };
+    premultiply_scalar(pixels)
+}
+
+pub fn unpremultiply(pixels: &mut [Pixel]) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: SSE2 is part of the x86_64 baseline
+    let pixels = unsafe { x86::unpremultiply(pixels) };
+    unpremultiply_scalar(pixels)
+}
+
+fn pixels_to_u32_scalar(src: &[Pixel], dst: &mut [u32], order: PixelOrder) {
+    for (pixel, out) in src.iter().zip(dst) {
+        *out = pixel.to_u32(order);
+    }
+}
+
+fn u32_to_pixels_scalar(src: &[u32], dst: &mut [Pixel], order: PixelOrder) {
+    for (&value, out) in src.iter().zip(dst) {
+        *out = Pixel::from_u32(value, order);
+    }
+}
+
+fn premultiply_scalar(pixels: &mut [Pixel]) {
+    // c * a / 255 rounded, without a division
+    let scale = |c: u8, a: u8| {
+        let t = c as u16 * a as u16 + 128;
+        ((t + (t >> 8)) >> 8) as u8
+    };
+    for pixel in pixels {
+        let Pixel { r, g, b, a } = *pixel;
+        *pixel = Pixel::new(scale(r, a), scale(g, a), scale(b, a), a);
+    }
+}
+
+fn unpremultiply_scalar(pixels: &mut [Pixel]) {
+    // in f32 like the SIMD kernel; the quotient is exact enough to round correctly
+    let scale = |c: u8, a: u8| (c as f32 * 255.0 / a as f32).min(255.0).round_ties_even() as u8;
+    for pixel in pixels {
+        let Pixel { r, g, b, a } = *pixel;
+        *pixel = match a {
+            0 => Pixel::new(0, 0, 0, 0),
+            _ => Pixel::new(scale(r, a), scale(g, a), scale(b, a), a),
+        };
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use crate::Pixel;
+
+    // a shuffle index that produces a zero byte
+    pub(super) const ZERO: u8 = 0x80;
+
+    // Rearranges the bytes of every 4-byte item by `table` (output byte i is input
+    // byte table[i]) and ORs in `fill`, read as a little-endian u32.
+    //
+    // SAFETY: SSSE3 must be available, `src` readable and `dst` writable for
+    // `len` 4-byte items.
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn shuffle(src: *const u8, dst: *mut u8, len: usize, table: [u8; 4], fill: u32) {
+        let mut mask = [0; 16];
+        for (i, index) in mask.iter_mut().enumerate() {
+            let entry = table[i % 4];
+            *index = if entry == ZERO {
+                ZERO
+            } else {
+                entry + (i / 4 * 4) as u8
+            };
+        }
+        let mask = unsafe { _mm_loadu_si128(mask.as_ptr().cast()) };
+        let fill_vec = _mm_set1_epi32(fill as i32);
+
+        let mut i = 0;
+        while i + 4 <= len {
+            unsafe {
+                let v = _mm_loadu_si128(src.add(i * 4).cast());
+                let v = _mm_or_si128(_mm_shuffle_epi8(v, mask), fill_vec);
+                _mm_storeu_si128(dst.add(i * 4).cast(), v);
+            }
+            i += 4;
+        }
+        for i in i..len {
+            let item = unsafe { src.add(i * 4).cast::<[u8; 4]>().read() };
+            let out = table.map(|entry| {
+                if entry == ZERO {
+                    0
+                } else {
+                    item[entry as usize]
+                }
+            });
+            let out = u32::from_le_bytes(out) | fill;
+            unsafe { dst.add(i * 4).cast::<[u8; 4]>().write(out.to_le_bytes()) };
+        }
+    }
+
+    // Premultiplies groups of 4 pixels and returns the pixels left over.
+    #[target_feature(enable = "sse2")]
+    pub fn premultiply(pixels: &mut [Pixel]) -> &mut [Pixel] {
+        let zero = _mm_setzero_si128();
+        // the alpha lane is multiplied by 255 instead of itself, which keeps it as is
+        let color = _mm_set_epi16(0, -1, -1, -1, 0, -1, -1, -1);
+        let opaque = _mm_set_epi16(255, 0, 0, 0, 255, 0, 0, 0);
+        let half = _mm_set1_epi16(128);
+        let scale = |v: __m128i| {
+            let alpha = _mm_shufflehi_epi16::<0xff>(_mm_shufflelo_epi16::<0xff>(v));
+            let alpha = _mm_or_si128(_mm_and_si128(alpha, color), opaque);
+            let t = _mm_add_epi16(_mm_mullo_epi16(v, alpha), half);
+            _mm_srli_epi16::<8>(_mm_add_epi16(t, _mm_srli_epi16::<8>(t)))
+        };
+
+        let mut chunks = pixels.chunks_exact_mut(4);
+        for chunk in &mut chunks {
+            let ptr = chunk.as_mut_ptr().cast::<__m128i>();
+            // SAFETY: a chunk is 16 bytes
+            let v = unsafe { _mm_loadu_si128(ptr) };
+            let lo = scale(_mm_unpacklo_epi8(v, zero));
+            let hi = scale(_mm_unpackhi_epi8(v, zero));
+            unsafe { _mm_storeu_si128(ptr, _mm_packus_epi16(lo, hi)) };
+        }
+        chunks.into_remainder()
+    }
+
+    // Unpremultiplies groups of 4 pixels and returns the pixels left over.
+    #[target_feature(enable = "sse2")]
+    pub fn unpremultiply(pixels: &mut [Pixel]) -> &mut [Pixel] {
+        let zero = _mm_setzero_si128();
+        let alpha_mask = _mm_set1_epi32(0xff00_0000_u32 as i32);
+        let max = _mm_set1_ps(255.0);
+        let one = _mm_set1_ps(1.0);
+        let scale = |v: __m128i| {
+            let v = _mm_cvtepi32_ps(v);
+            // transparent pixels divide by 1 here and are cleared below
+            let alpha = _mm_max_ps(_mm_shuffle_ps::<0xff>(v, v), one);
+            let v = _mm_min_ps(_mm_div_ps(_mm_mul_ps(v, max), alpha), max);
+            _mm_cvtps_epi32(v)
+        };
+
+        let mut chunks = pixels.chunks_exact_mut(4);
+        for chunk in &mut chunks {
+            let ptr = chunk.as_mut_ptr().cast::<__m128i>();
+            // SAFETY: a chunk is 16 bytes
+            let v = unsafe { _mm_loadu_si128(ptr) };
+            let lo = _mm_unpacklo_epi8(v, zero);
+            let hi = _mm_unpackhi_epi8(v, zero);
+            let lo = _mm_packs_epi32(
+                scale(_mm_unpacklo_epi16(lo, zero)),
+                scale(_mm_unpackhi_epi16(lo, zero)),
+            );
+            let hi = _mm_packs_epi32(
+                scale(_mm_unpacklo_epi16(hi, zero)),
+                scale(_mm_unpackhi_epi16(hi, zero)),
+            );
+            let alpha = _mm_and_si128(v, alpha_mask);
+            let out = _mm_or_si128(
+                _mm_andnot_si128(alpha_mask, _mm_packus_epi16(lo, hi)),
+                alpha,
+            );
+            let out = _mm_andnot_si128(_mm_cmpeq_epi32(alpha, zero), out);
+            unsafe { _mm_storeu_si128(ptr, out) };
+        }
+        chunks.into_remainder()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDERS: [PixelOrder; 5] = [
+        PixelOrder::Rgba,
+        PixelOrder::Argb,
+        PixelOrder::Bgra,
+        PixelOrder::Abgr,
+        PixelOrder::Xrgb,
+    ];
+
+    fn pixels(len: usize) -> Vec<Pixel> {
+        let mut state = 7u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let [r, g, b, a] = state.to_le_bytes();
+                Pixel::new(r, g, b, a)
+            })
+            .collect()
+    }
+
+    // every color value against every alpha
+    fn all_pairs() -> Vec<Pixel> {
+        (0..=255)
+            .flat_map(|a| (0..=255).map(move |c| Pixel::new(c, 255 - c, c / 2, a)))
+            .collect()
+    }
+
+    #[test]
+    fn packed() {
+        for len in (0..20).chain([1001]) {
+            let src = pixels(len);
+            for order in ORDERS {
+                let mut packed = vec![0; len];
+                pixels_to_u32(&src, &mut packed, order);
+                let mut expected = vec![0; len];
+                pixels_to_u32_scalar(&src, &mut expected, order);
+                assert_eq!(packed, expected, "{order:?}");
+
+                let mut back = vec![Pixel::new(0, 0, 0, 0); len];
+                u32_to_pixels(&packed, &mut back, order);
+                let mut expected = vec![Pixel::new(0, 0, 0, 0); len];
+                u32_to_pixels_scalar(&packed, &mut expected, order);
+                assert_eq!(back, expected, "{order:?}");
+                if order != PixelOrder::Xrgb {
+                    assert_eq!(back, src, "{order:?}");
+                }
+            }
+
+            let mut rgba = vec![0; len * 4];
+            pixels_to_rgba(&src, &mut rgba);
+            assert!(
+                rgba.iter()
+                    .copied()
+                    .eq(src.iter().flat_map(Pixel::to_bytes))
+            );
+            let mut back = vec![Pixel::new(0, 0, 0, 0); len];
+            rgba_to_pixels(&rgba, &mut back);
+            assert_eq!(back, src);
+        }
+        assert_eq!(
+            Pixel::from_u32(0x00010203, PixelOrder::Xrgb),
+            Pixel::new(1, 2, 3, 255)
+        );
+    }
+
+    #[test]
+    fn premultiplied() {
+        let src = all_pairs();
+        for len in (0..20).chain([src.len()]) {
+            let mut simd = src[..len].to_vec();
+            let mut scalar = simd.clone();
+            premultiply(&mut simd);
+            premultiply_scalar(&mut scalar);
+            assert_eq!(simd, scalar);
+
+            unpremultiply(&mut simd);
+            unpremultiply_scalar(&mut scalar);
+            assert_eq!(simd, scalar);
+
+            // pixels that were already premultiplied come back unchanged
+            premultiply(&mut simd);
+            let mut again = simd.clone();
+            unpremultiply(&mut again);
+            premultiply(&mut again);
+            assert_eq!(again, simd);
+        }
+
+        let mut pixels = [
+            Pixel::new(200, 100, 255, 128),
+            Pixel::new(10, 20, 30, 0),
+            Pixel::new(1, 2, 3, 255),
+        ];
+        premultiply(&mut pixels);
+        assert_eq!(
+            pixels,
+            [
+                Pixel::new(100, 50, 128, 128),
+                Pixel::new(0, 0, 0, 0),
+                Pixel::new(1, 2, 3, 255)
+            ]
+        );
+        unpremultiply(&mut pixels);
+        assert_eq!(pixels[0], Pixel::new(199, 100, 255, 128));
+    }
+}
@@ -0,0 +1,113 @@
+//! Drawing decoded images onto `embedded-graphics` targets.
+//!
+//! Images draw as `Rgb888`; displays with another color type (e.g. `Rgb565`
+//! on an SSD1351 or ILI9341) can wrap their target with
+//! `DrawTargetExt::color_converted`. Fully transparent pixels are skipped.
+
+use embedded_graphics_core::Pixel as EgPixel;
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::{Dimensions, OriginDimensions, Point, Size};
+use embedded_graphics_core::image::{GetPixel, ImageDrawable};
+use embedded_graphics_core::pixelcolor::Rgb888;
+use embedded_graphics_core::primitives::{PointsIter, Rectangle};
+
+use crate::{Image, Pixel};
+
+impl OriginDimensions for Image<Pixel> {
+    fn size(&self) -> Size {
+        Size::new(self.width as u32, self.height as u32)
+    }
+}
+
+impl GetPixel for Image<Pixel> {
+    type Color = Rgb888;
+
+    fn pixel(&self, p: Point) -> Option<Rgb888> {
+        let (x, y) = (usize::try_from(p.x).ok()?, usize::try_from(p.y).ok()?);
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let px = self.pixels[y * self.width + x];
+        Some(Rgb888::new(px.r, px.g, px.b))
+    }
+}
+
+impl ImageDrawable for Image<Pixel> {
+    type Color = Rgb888;
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb888>,
+    {
+        self.draw_sub_image(target, &self.bounding_box())
+    }
+
+    // pixels of `area` are drawn relative to its top left corner
+    fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb888>,
+    {
+        let area = area.intersection(&self.bounding_box());
+        let offset = area.top_left;
+        target.draw_iter(area.points().filter_map(|p| {
+            let px = self.pixels[p.y as usize * self.width + p.x as usize];
+            (px.a != 0).then(|| EgPixel(p - offset, Rgb888::new(px.r, px.g, px.b)))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    struct Target(Vec<(Point, Rgb888)>);
+
+    impl OriginDimensions for Target {
+        fn size(&self) -> Size {
+            Size::new(16, 16)
+        }
+    }
+
+    impl DrawTarget for Target {
+        type Color = Rgb888;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+        where
+            I: IntoIterator<Item = EgPixel<Rgb888>>,
+        {
+            self.0
+                .extend(pixels.into_iter().map(|EgPixel(p, c)| (p, c)));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn draw() {
+        let image = Image {
+            width: 2,
+            height: 2,
+            pixels: vec![
+                Pixel::new(1, 2, 3, 255),
+                Pixel::new(4, 5, 6, 0),
+                Pixel::new(7, 8, 9, 255),
+                Pixel::new(10, 11, 12, 255),
+            ],
+        };
+
+        let mut target = Target(vec![]);
+        image.draw(&mut target).unwrap();
+        assert_eq!(target.0.len(), 3);
+        assert_eq!(target.0[2], (Point::new(1, 1), Rgb888::new(10, 11, 12)));
+
+        let mut target = Target(vec![]);
+        let area = Rectangle::new(Point::new(1, 1), Size::new(4, 4));
+        image.draw_sub_image(&mut target, &area).unwrap();
+        assert_eq!(target.0, [(Point::zero(), Rgb888::new(10, 11, 12))]);
+
+        assert_eq!(image.pixel(Point::new(0, 1)), Some(Rgb888::new(7, 8, 9)));
+        assert_eq!(image.pixel(Point::new(2, 0)), None);
+    }
+}
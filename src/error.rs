@@ -0,0 +1,94 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    // the data does not start with a complete header with the expected magic
+    InvalidHeader,
+    // the header declares a channel count other than 3 or 4
+    InvalidChannels,
+    // the header declares a colorspace other than 0 or 1
+    InvalidColorspace,
+    // the header declares dimensions beyond the decoder's `Limits`
+    LimitsExceeded,
+    // the pixel buffer for the declared dimensions could not be allocated
+    OutOfMemory,
+    // decoding took more ops or produced more bytes than the decoder's `Budget`
+    BudgetExceeded,
+    // the data ends before every pixel has been decoded
+    UnexpectedEof,
+    // a run continues past the last pixel of the declared dimensions
+    RunOvershoot,
+    // the pixels are not followed by exactly the end marker
+    InvalidEndMarker,
+    // the requested region or subsampling factor does not fit the image
+    InvalidArgument,
+    // the caller's output buffer cannot hold the image
+    BufferTooSmall,
+    // the flag given to `Decoder::cancel_on` was set
+    Cancelled,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DecodeError::InvalidHeader => "invalid QOI header",
+            DecodeError::InvalidChannels => "invalid channel count in header",
+            DecodeError::InvalidColorspace => "invalid colorspace in header",
+            DecodeError::LimitsExceeded => "image dimensions exceed the decoder limits",
+            DecodeError::OutOfMemory => "not enough memory for the image",
+            DecodeError::BudgetExceeded => "decoding exceeded the decoder budget",
+            DecodeError::UnexpectedEof => "unexpected end of data",
+            DecodeError::RunOvershoot => "run extends past the end of the image",
+            DecodeError::InvalidEndMarker => "missing or invalid end marker",
+            DecodeError::InvalidArgument => "invalid region or subsampling factor",
+            DecodeError::BufferTooSmall => "output buffer too small",
+            DecodeError::Cancelled => "decoding was cancelled",
+        })
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeError {
+    // the width or height is zero, which the spec does not allow
+    ZeroDimensions,
+    // the dimensions exceed the encoder's `Limits`
+    LimitsExceeded,
+    // the number of pixels passed in is not width * height
+    PixelCountMismatch,
+    // the flag given to `Encoder::cancel_on` was set
+    Cancelled,
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EncodeError::ZeroDimensions => "image width and height must not be zero",
+            EncodeError::LimitsExceeded => "image dimensions exceed the encoder limits",
+            EncodeError::PixelCountMismatch => "pixel count does not match the image dimensions",
+            EncodeError::Cancelled => "encoding was cancelled",
+        })
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseHexError {
+    // not 6 or 8 digits after the optional "#"
+    InvalidLength,
+    // a character other than 0-9, a-f or A-F
+    InvalidDigit,
+}
+
+impl fmt::Display for ParseHexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseHexError::InvalidLength => "expected #rrggbb or #rrggbbaa",
+            ParseHexError::InvalidDigit => "invalid hex digit in color",
+        })
+    }
+}
+
+impl std::error::Error for ParseHexError {}
@@ -1,59 +1,369 @@
+//! C API mirroring the reference qoi.h, so C and C++ code can link against this
+//! crate instead. The header is generated into `include/qoi.h` with cbindgen.
+//!
+//! Returned buffers come from `malloc` and can be released with `free` as with
+//! the reference implementation, or with `qoi_free` (required on wasm).
+
+#![allow(non_camel_case_types)]
+
+use std::ffi::{CStr, c_char, c_int, c_void};
 use std::slice;
 
-use crate::Decoder;
+use crate::{Decoder, Encoder, Pixel};
+
+pub const QOI_SRGB: u8 = 0;
+pub const QOI_LINEAR: u8 = 1;
+
+// status codes of `qoi_decode_to_rgba`
+pub const QOI_OK: c_int = 0;
+pub const QOI_ERROR_NULL_POINTER: c_int = -1;
+pub const QOI_ERROR_INVALID_DATA: c_int = -2;
+pub const QOI_ERROR_BUFFER_TOO_SMALL: c_int = -3;
+
+// the reference implementation refuses images larger than this
+const QOI_PIXELS_MAX: usize = 400_000_000;
+
+#[repr(C)]
+pub struct qoi_desc {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u8,
+    pub colorspace: u8,
+}
+
+/// Encodes `desc.width * desc.height` pixels of `desc.channels` bytes each. Returns
+/// the encoded bytes and stores their length in `out_len`, or NULL on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn qoi_encode(
+    data: *const c_void,
+    desc: *const qoi_desc,
+    out_len: *mut c_int,
+) -> *mut c_void {
+    if data.is_null() || desc.is_null() || out_len.is_null() {
+        return std::ptr::null_mut();
+    }
+    let desc = unsafe { &*desc };
+    let Some(encoded) = encode(data, desc) else {
+        return std::ptr::null_mut();
+    };
+    let Ok(len) = c_int::try_from(encoded.len()) else {
+        return std::ptr::null_mut();
+    };
+
+    unsafe { *out_len = len };
+    alloc_bytes(&encoded)
+}
 
+/// Decodes `size` bytes of QOI data into pixels of `channels` bytes each (0 keeps the
+/// file's channel count), filling in `desc` from the header. Returns NULL on failure.
 #[unsafe(no_mangle)]
 pub extern "C" fn qoi_decode(
-    data: *const u8,
-    len: u32,
-    out_width: *mut u32,
-    out_height: *mut u32,
-) -> *const u8 {
-    if data.is_null() {
-        return std::ptr::null();
+    data: *const c_void,
+    size: c_int,
+    desc: *mut qoi_desc,
+    channels: c_int,
+) -> *mut c_void {
+    if data.is_null() || desc.is_null() || size < 0 || !matches!(channels, 0 | 3 | 4) {
+        return std::ptr::null_mut();
     }
+    let data = unsafe { slice::from_raw_parts(data.cast::<u8>(), size as usize) };
+    let Some((header, pixels)) = decode(data, channels as usize) else {
+        return std::ptr::null_mut();
+    };
 
-    let mut decoder = Decoder::new();
-    let decoded = decoder.decode(unsafe { slice::from_raw_parts(data, len as usize) });
+    unsafe { *desc = header };
+    alloc_bytes(&pixels)
+}
 
-    let Some(image) = decoded else {
-        return std::ptr::null();
+/// Decodes `in_len` bytes of QOI data as RGBA into the caller's `out` buffer of
+/// `out_cap` bytes, without allocating. `out_desc` is filled in from the header
+/// whenever the header is valid, so calling with a NULL `out` and 0 capacity
+/// returns `QOI_ERROR_BUFFER_TOO_SMALL` along with the size to allocate.
+#[unsafe(no_mangle)]
+pub extern "C" fn qoi_decode_to_rgba(
+    data: *const c_void,
+    in_len: usize,
+    out: *mut c_void,
+    out_cap: usize,
+    out_desc: *mut qoi_desc,
+) -> c_int {
+    if data.is_null() || out_desc.is_null() {
+        return QOI_ERROR_NULL_POINTER;
+    }
+    let data = unsafe { slice::from_raw_parts(data.cast::<u8>(), in_len) };
+    let Some((header, _)) = crate::Header::from_bytes(b"qoif", data) else {
+        return QOI_ERROR_INVALID_DATA;
+    };
+    unsafe {
+        *out_desc = qoi_desc {
+            width: header.width,
+            height: header.height,
+            channels: header.channels,
+            colorspace: header.colorspace,
+        }
     };
 
-    if !out_width.is_null() {
-        unsafe { *out_width = image.width as u32 };
+    let needed = (header.width as usize)
+        .checked_mul(header.height as usize)
+        .and_then(|n| n.checked_mul(4));
+    match needed {
+        None => return QOI_ERROR_INVALID_DATA,
+        Some(needed) if out.is_null() || out_cap < needed => return QOI_ERROR_BUFFER_TOO_SMALL,
+        Some(_) => {}
     }
-    if !out_height.is_null() {
-        unsafe { *out_height = image.height as u32 };
+
+    let out = unsafe { slice::from_raw_parts_mut(out.cast::<u8>(), out_cap) };
+    match Decoder::new().decode_to_rgba(data, out) {
+        Ok(_) => QOI_OK,
+        Err(_) => QOI_ERROR_INVALID_DATA,
     }
+}
+
+/// Encodes the pixels and writes them to `filename`. Returns the number of bytes
+/// written, or 0 on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn qoi_write(
+    filename: *const c_char,
+    data: *const c_void,
+    desc: *const qoi_desc,
+) -> c_int {
+    if filename.is_null() || data.is_null() || desc.is_null() {
+        return 0;
+    }
+    let Ok(filename) = unsafe { CStr::from_ptr(filename) }.to_str() else {
+        return 0;
+    };
+    let Some(encoded) = encode(data, unsafe { &*desc }) else {
+        return 0;
+    };
 
-    let bytes = transmute_vec::<_, u8>(image.pixels);
-    let boxed = Box::new(bytes).into_boxed_slice();
+    match std::fs::write(filename, &encoded) {
+        Ok(()) => c_int::try_from(encoded.len()).unwrap_or(0),
+        Err(_) => 0,
+    }
+}
+
+/// Reads and decodes `filename` like `qoi_decode`. Returns NULL on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn qoi_read(
+    filename: *const c_char,
+    desc: *mut qoi_desc,
+    channels: c_int,
+) -> *mut c_void {
+    if filename.is_null() {
+        return std::ptr::null_mut();
+    }
+    let Ok(filename) = unsafe { CStr::from_ptr(filename) }.to_str() else {
+        return std::ptr::null_mut();
+    };
+    let Ok(data) = std::fs::read(filename) else {
+        return std::ptr::null_mut();
+    };
+    let Ok(size) = c_int::try_from(data.len()) else {
+        return std::ptr::null_mut();
+    };
 
-    Box::into_raw(boxed).cast()
+    qoi_decode(data.as_ptr().cast(), size, desc, channels)
 }
 
+/// Releases a buffer returned by any of the functions above.
 #[unsafe(no_mangle)]
-pub extern "C" fn qoi_free(data: *mut u8) {
+pub extern "C" fn qoi_free(data: *mut c_void) {
     if !data.is_null() {
-        drop(unsafe { Box::from_raw(data) });
+        unsafe { free_bytes(data) };
     }
 }
 
-fn transmute_vec<Src, Dst>(input: Vec<Src>) -> Vec<Dst> {
-    const fn get_factor(src_size: usize, dst_size: usize) -> usize {
-        if src_size == 0 && dst_size == 0 {
-            1
-        } else {
-            assert!(dst_size != 0 && src_size % dst_size == 0);
-            src_size / dst_size
+fn encode(data: *const c_void, desc: &qoi_desc) -> Option<Vec<u8>> {
+    let channels = desc.channels as usize;
+    let total = (desc.width as usize).checked_mul(desc.height as usize)?;
+    if total == 0
+        || total > QOI_PIXELS_MAX
+        || !matches!(channels, 3 | 4)
+        || !matches!(desc.colorspace, QOI_SRGB | QOI_LINEAR)
+    {
+        return None;
+    }
+
+    let data = unsafe { slice::from_raw_parts(data.cast::<u8>(), total * channels) };
+    let pixels = data
+        .chunks_exact(channels)
+        .map(|px| Pixel::new(px[0], px[1], px[2], px.get(3).copied().unwrap_or(255)))
+        .collect::<Vec<_>>();
+
+    Encoder::new(desc.width, desc.height)
+        .force_opaque(channels == 3)
+        .colorspace(desc.colorspace)
+        .encode(&pixels)
+        .ok()
+}
+
+fn decode(data: &[u8], channels: usize) -> Option<(qoi_desc, Vec<u8>)> {
+    let (header, _) = crate::Header::from_bytes(b"qoif", data)?;
+    let image = Decoder::new().decode(data).ok()?;
+
+    let channels = match channels {
+        0 => header.channels as usize,
+        n => n,
+    };
+    if !matches!(channels, 3 | 4) {
+        return None;
+    }
+    let pixels = image
+        .pixels
+        .iter()
+        .flat_map(|px| px.to_bytes().into_iter().take(channels))
+        .collect();
+
+    let desc = qoi_desc {
+        width: header.width,
+        height: header.height,
+        channels: header.channels,
+        colorspace: header.colorspace,
+    };
+    Some((desc, pixels))
+}
+
+#[cfg(not(target_family = "wasm"))]
+unsafe extern "C" {
+    fn malloc(size: usize) -> *mut c_void;
+    fn free(ptr: *mut c_void);
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn alloc_bytes(bytes: &[u8]) -> *mut c_void {
+    let ptr = unsafe { malloc(bytes.len().max(1)) };
+    if !ptr.is_null() {
+        unsafe {
+            ptr.cast::<u8>()
+                .copy_from_nonoverlapping(bytes.as_ptr(), bytes.len())
+        };
+    }
+    ptr
+}
+
+#[cfg(not(target_family = "wasm"))]
+unsafe fn free_bytes(ptr: *mut c_void) {
+    unsafe { free(ptr) };
+}
+
+// There is no libc on wasm, so the allocation size is stored in front of the
+// buffer for `qoi_free` to rebuild the layout.
+#[cfg(target_family = "wasm")]
+const PREFIX: usize = 8;
+
+#[cfg(target_family = "wasm")]
+fn alloc_bytes(bytes: &[u8]) -> *mut c_void {
+    use std::alloc::{Layout, alloc};
+
+    let Ok(layout) = Layout::from_size_align(PREFIX + bytes.len(), PREFIX) else {
+        return std::ptr::null_mut();
+    };
+    unsafe {
+        let ptr = alloc(layout);
+        if ptr.is_null() {
+            return std::ptr::null_mut();
         }
+        ptr.cast::<usize>().write(layout.size());
+        let data = ptr.add(PREFIX);
+        data.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+        data.cast()
     }
+}
+
+#[cfg(target_family = "wasm")]
+unsafe fn free_bytes(ptr: *mut c_void) {
+    use std::alloc::{Layout, dealloc};
+
+    unsafe {
+        let ptr = ptr.cast::<u8>().sub(PREFIX);
+        let size = ptr.cast::<usize>().read();
+        dealloc(ptr, Layout::from_size_align_unchecked(size, PREFIX));
+    }
+}
 
-    let factor = const { get_factor(size_of::<Src>(), size_of::<Dst>()) };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let len = input.len() * factor;
-    let cap = input.capacity() * factor;
+    #[test]
+    fn round_trip() {
+        let rgb = [255u8, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9];
+        let desc = qoi_desc {
+            width: 2,
+            height: 2,
+            channels: 3,
+            colorspace: QOI_LINEAR,
+        };
+        let mut len = 0;
+        let encoded = qoi_encode(rgb.as_ptr().cast(), &desc, &mut len);
+        assert!(!encoded.is_null());
 
-    unsafe { Vec::from_raw_parts(input.leak().as_mut_ptr().cast(), len, cap) }
+        let mut out = qoi_desc {
+            width: 0,
+            height: 0,
+            channels: 0,
+            colorspace: 0,
+        };
+        let decoded = qoi_decode(encoded, len, &mut out, 4);
+        assert!(!decoded.is_null());
+        assert_eq!(
+            (out.width, out.height, out.channels, out.colorspace),
+            (2, 2, 3, 1)
+        );
+        let pixels = unsafe { slice::from_raw_parts(decoded.cast::<u8>(), 16) };
+        assert_eq!(pixels[12..], [9, 9, 9, 255]);
+
+        qoi_free(decoded);
+        qoi_free(encoded);
+
+        let bad = qoi_desc {
+            width: 0,
+            height: 2,
+            channels: 3,
+            colorspace: QOI_SRGB,
+        };
+        assert!(qoi_encode(rgb.as_ptr().cast(), &bad, &mut len).is_null());
+        assert!(qoi_decode(rgb.as_ptr().cast(), 12, &mut out, 0).is_null());
+    }
+
+    #[test]
+    fn decode_to_rgba() {
+        let rgba = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        let desc = qoi_desc {
+            width: 2,
+            height: 1,
+            channels: 4,
+            colorspace: QOI_SRGB,
+        };
+        let mut len = 0;
+        let encoded = qoi_encode(rgba.as_ptr().cast(), &desc, &mut len);
+        let encoded_len = len as usize;
+
+        let mut out = qoi_desc {
+            width: 0,
+            height: 0,
+            channels: 0,
+            colorspace: 0,
+        };
+        let status = qoi_decode_to_rgba(encoded, encoded_len, std::ptr::null_mut(), 0, &mut out);
+        assert_eq!(
+            (status, out.width, out.height),
+            (QOI_ERROR_BUFFER_TOO_SMALL, 2, 1)
+        );
+
+        let mut buf = [0u8; 8];
+        let status = qoi_decode_to_rgba(encoded, encoded_len, buf.as_mut_ptr().cast(), 8, &mut out);
+        assert_eq!((status, buf), (QOI_OK, rgba));
+
+        let status = qoi_decode_to_rgba(
+            encoded,
+            encoded_len - 1,
+            buf.as_mut_ptr().cast(),
+            8,
+            &mut out,
+        );
+        assert_eq!(status, QOI_ERROR_INVALID_DATA);
+        qoi_free(encoded);
+    }
 }
@@ -0,0 +1,148 @@
+//! Minimal framing for streaming QOI images over a byte stream (TCP, pipes, ...).
+//!
+//! Every frame is the `qfrm` magic, a big-endian u32 payload length and the
+//! encoded QOI payload.
+
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &[u8; 4] = b"qfrm";
+
+// frames above this size are rejected by `recv_frame` before allocating
+pub const DEFAULT_MAX_FRAME_LEN: usize = 256 * 1024 * 1024;
+
+pub fn send_frame<W: Write>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&frame_header(payload.len())?)?;
+    writer.write_all(payload)
+}
+
+// Returns `Ok(None)` when the stream ends cleanly between frames.
+pub fn recv_frame<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    recv_frame_with_limit(reader, DEFAULT_MAX_FRAME_LEN)
+}
+
+pub fn recv_frame_with_limit<R: Read>(
+    reader: &mut R,
+    max_len: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0u8; 8];
+    let mut filled = 0;
+    while filled < header.len() {
+        match reader.read(&mut header[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    let len = parse_header(&header, max_len)?;
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+fn frame_header(len: usize) -> io::Result<[u8; 8]> {
+    let len = u32::try_from(len).map_err(|_| invalid("frame too large"))?;
+    let mut header = [0u8; 8];
+    header[..4].copy_from_slice(MAGIC);
+    header[4..].copy_from_slice(&len.to_be_bytes());
+    Ok(header)
+}
+
+fn parse_header(header: &[u8; 8], max_len: usize) -> io::Result<usize> {
+    if header[..4].ne(MAGIC) {
+        return Err(invalid("bad frame magic"));
+    }
+    let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    if len > max_len {
+        return Err(invalid("frame exceeds the size limit"));
+    }
+    Ok(len)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(feature = "async")]
+pub mod nonblocking {
+    use std::io;
+
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    use super::{DEFAULT_MAX_FRAME_LEN, frame_header, parse_header};
+
+    pub async fn send_frame<W: AsyncWrite + Unpin>(
+        writer: &mut W,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        writer.write_all(&frame_header(payload.len())?).await?;
+        writer.write_all(payload).await
+    }
+
+    pub async fn recv_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+        recv_frame_with_limit(reader, DEFAULT_MAX_FRAME_LEN).await
+    }
+
+    pub async fn recv_frame_with_limit<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        max_len: usize,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let mut header = [0u8; 8];
+        let mut filled = 0;
+        while filled < header.len() {
+            match reader.read(&mut header[filled..]).await? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => filled += n,
+            }
+        }
+
+        let len = parse_header(&header, max_len)?;
+        let mut payload = vec![0; len];
+        reader.read_exact(&mut payload).await?;
+        Ok(Some(payload))
+    }
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use super::*;
+    use crate::{Decoder, Encoder, Pixel};
+
+    #[test]
+    fn round_trip() {
+        let frames = (0..3u8)
+            .map(|i| {
+                Encoder::new(2, 2)
+                    .encode(&[Pixel::new(i, 0, 0, 255); 4])
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let mut stream = vec![];
+        for frame in &frames {
+            send_frame(&mut stream, frame).unwrap();
+        }
+
+        let mut reader = stream.as_slice();
+        for (i, frame) in frames.iter().enumerate() {
+            let received = recv_frame(&mut reader).unwrap().unwrap();
+            assert_eq!(&received, frame);
+            assert!(
+                Decoder::new().decode(&received).unwrap().pixels[0]
+                    == Pixel::new(i as u8, 0, 0, 255)
+            );
+        }
+        assert!(recv_frame(&mut reader).unwrap().is_none());
+
+        // a stream cut inside a frame is an error, not a clean end
+        let mut truncated = &stream[..stream.len() - 1];
+        for _ in 0..2 {
+            recv_frame(&mut truncated).unwrap();
+        }
+        assert!(recv_frame(&mut truncated).is_err());
+        assert!(recv_frame_with_limit(&mut stream.as_slice(), 4).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! `axum` integration: `Qoi` responds with encoded images and extracts decoded
+//! ones from request bodies.
+
+use axum::body::Bytes;
+use axum::extract::{FromRequest, Request};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+use crate::{Budget, DecodeError, Decoder, Encoder, Image, Limits, Pixel};
+
+pub const CONTENT_TYPE: &str = "image/qoi";
+
+// Wraps either a decoded image or already encoded QOI bytes.
+pub struct Qoi<T>(pub T);
+
+impl IntoResponse for Qoi<Vec<u8>> {
+    fn into_response(self) -> Response {
+        ([(header::CONTENT_TYPE, CONTENT_TYPE)], self.0).into_response()
+    }
+}
+
+impl IntoResponse for Qoi<Image<Pixel>> {
+    fn into_response(self) -> Response {
+        let image = self.0;
+        match Encoder::new(image.width as u32, image.height as u32).encode(&image.pixels) {
+            Ok(data) => Qoi(data).into_response(),
+            Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        }
+    }
+}
+
+// Decodes the request body with the `Limits` and `Budget` found in the request
+// extensions (e.g. added by a layer), or the defaults otherwise.
+impl<S: Send + Sync> FromRequest<S> for Qoi<Image<Pixel>> {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let limits = req
+            .extensions()
+            .get::<Limits>()
+            .copied()
+            .unwrap_or_default();
+        let budget = req
+            .extensions()
+            .get::<Budget>()
+            .copied()
+            .unwrap_or_default();
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let image = Decoder::new()
+            .with_limits(limits)
+            .with_budget(budget)
+            .decode(&body)
+            .map_err(|err| {
+                let status = match err {
+                    DecodeError::LimitsExceeded | DecodeError::BudgetExceeded => {
+                        StatusCode::PAYLOAD_TOO_LARGE
+                    }
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                (status, err.to_string()).into_response()
+            })?;
+        Ok(Qoi(image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{Body, to_bytes};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn round_trip() {
+        let image = Image {
+            width: 3,
+            height: 2,
+            pixels: vec![Pixel::new(10, 20, 30, 255); 6],
+        };
+        let response = Qoi(image).into_response();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], CONTENT_TYPE);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        let request = Request::new(Body::from(body.clone()));
+        let Qoi(decoded) = Qoi::from_request(request, &()).await.ok().unwrap();
+        assert_eq!((decoded.width, decoded.height), (3, 2));
+
+        let mut request = Request::new(Body::from(body));
+        request.extensions_mut().insert(Limits {
+            max_pixels: 5,
+            ..Limits::default()
+        });
+        let rejection = Qoi::from_request(request, &()).await.err().unwrap();
+        assert_eq!(rejection.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let request = Request::new(Body::from("not a qoi file"));
+        let rejection = Qoi::from_request(request, &()).await.err().unwrap();
+        assert_eq!(rejection.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,11 +1,79 @@
+#![allow(clippy::identity_op)]
+
+#[cfg(any(feature = "encode", feature = "decode"))]
 use std::num::Wrapping;
+#[cfg(feature = "decode")]
+use std::ops::ControlFlow;
+#[cfg(any(feature = "encode", feature = "decode"))]
+use std::sync::Arc;
+#[cfg(any(feature = "encode", feature = "decode"))]
+use std::sync::atomic::AtomicBool;
 
+#[cfg(all(feature = "encode", feature = "decode"))]
+pub mod anim;
+#[cfg(all(feature = "encode", feature = "decode"))]
+pub mod archive;
+pub mod atlas;
+#[cfg(feature = "ndarray")]
+pub mod array;
+#[cfg(any(feature = "encode", feature = "decode"))]
+pub mod batch;
+mod cache;
+pub mod colorspace;
+mod common;
+pub mod convert;
+#[cfg(feature = "embedded-graphics")]
+pub mod embedded;
+mod error;
+#[cfg(all(feature = "encode", feature = "decode"))]
 mod ffi;
+pub mod framing;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(all(feature = "encode", feature = "decode"))]
+pub mod mask;
+#[cfg(feature = "decode")]
+pub mod ops;
+#[cfg(feature = "qoi-plus")]
+pub mod pipeline;
+pub mod planar;
+#[cfg(feature = "qoi-plus")]
+pub mod plus;
+pub mod prelude;
+#[cfg(feature = "image")]
+mod rgba;
+#[cfg(feature = "decode")]
+pub mod seek;
+pub mod shared;
+pub mod stats;
+#[cfg(feature = "encode")]
+pub mod stream;
+#[cfg(feature = "wgpu")]
+pub mod texture;
+#[cfg(any(feature = "encode", feature = "decode"))]
+mod trace;
+#[cfg(all(feature = "encode", feature = "decode"))]
+mod verify;
+pub mod ycbcr;
+
+pub use cache::{CachePolicy, SpecCache};
+pub use common::{ChannelCount, Channels, Header, Image, Limits, Pixel, PixelOrder};
+#[cfg(any(feature = "encode", feature = "decode"))]
+use common::{END_MARKER, Hooks};
+pub use error::{DecodeError, EncodeError, ParseHexError};
+#[cfg(all(feature = "encode", feature = "decode"))]
+pub use verify::{Verification, verify};
+#[cfg(feature = "nonstandard")]
+pub use cache::{MixHashCache, TwoWayCache};
 
 #[cfg(target_family = "wasm")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+#[cfg(any(feature = "encode", feature = "decode"))]
+#[allow(clippy::upper_case_acronyms)]
+// the encoder writes most ops straight from their fields, only the decoder builds them
+#[cfg_attr(not(feature = "decode"), allow(dead_code))]
 #[derive(Debug, Clone, Copy)]
 enum QoiOp {
     RGB { r: u8, g: u8, b: u8 },
@@ -16,33 +84,36 @@ enum QoiOp {
     Run { len: u8 },                       // 6-bit, in [1..62] with bias of -1
 }
 
+#[cfg(any(feature = "encode", feature = "decode"))]
 impl QoiOp {
+    #[cfg(feature = "encode")]
     fn append_bytes(&self, buf: &mut Vec<u8>) {
-        match self {
-            &QoiOp::RGB { r, g, b } => buf.extend([0b11111110, r, g, b]),
-            &QoiOp::RGBA { r, g, b, a } => buf.extend([0b11111111, r, g, b, a]),
-            &QoiOp::Index { idx } => {
-                assert!(idx <= 62);
+        match *self {
+            QoiOp::RGB { r, g, b } => buf.extend([0b11111110, r, g, b]),
+            QoiOp::RGBA { r, g, b, a } => buf.extend([0b11111111, r, g, b, a]),
+            QoiOp::Index { idx } => {
+                assert!(idx < 64);
                 buf.push((0b00 << 6) | idx)
             }
-            &QoiOp::Diff { dr, dg, db } => {
+            QoiOp::Diff { dr, dg, db } => {
                 assert!(dr <= 3 && dg <= 3 && db <= 3);
                 buf.push((0b01 << 6) | (dr << 4) | (dg << 2) | (db << 0))
             }
-            &QoiOp::Luma { dg, dr_dg, db_dg } => {
+            QoiOp::Luma { dg, dr_dg, db_dg } => {
                 assert!(dg < 64 && dr_dg < 16 && db_dg < 16);
                 buf.push((0b10 << 6) | dg);
                 buf.push((dr_dg << 4) | db_dg);
             }
-            &QoiOp::Run { len } => {
+            QoiOp::Run { len } => {
                 assert!(len <= 62);
                 buf.push((0b11 << 6) | (len - 1))
             }
         }
     }
 
+    #[cfg(feature = "decode")]
     fn from_bytes(buf: &[u8]) -> Option<(Self, &[u8])> {
-        let (head, rest) = buf.split_first()?;
+        let (&head, rest) = buf.split_first()?;
         match (head >> 6, head & 0b00111111) {
             (0b11, 0b111110) => {
                 let (&r, rest) = rest.split_first()?;
@@ -72,97 +143,595 @@ impl QoiOp {
                 let db_dg = (next >> 0) & 0b1111;
                 Some((QoiOp::Luma { dg, dr_dg, db_dg }, rest))
             }
-            (0b11, len) => {
+            // the only tag left, `head >> 6` has no other values
+            (_, len) => {
                 let len = len + 1;
                 Some((QoiOp::Run { len }, rest))
             }
-            (4..=u8::MAX, _) => unreachable!("(u8) >> 6 cannot be 4 or greater")
         }
     }
+
+    #[cfg(feature = "encode")]
+    // Picks the smallest non-run op for `pixel`, records the pixel in the cache and
+    // appends the op to `buf`. Noisy images make the choice between DIFF, LUMA, RGB
+    // and RGBA unpredictable, so all four are built and one is selected with masks
+    // instead of a chain of range checks.
+    fn append_for_pixel(
+        cache: &mut impl CachePolicy,
+        prev: Pixel,
+        pixel: Pixel,
+        buf: &mut Vec<u8>,
+        stats: &mut EncodeStats,
+    ) {
+        let Pixel { r, g, b, a } = pixel;
+        let Pixel { r: pr, g: pg, b: pb, a: pa } = prev;
+
+        let found = cache.find(pixel);
+        cache.insert(pixel);
+        if let Some(idx) = found {
+            stats.index += 1;
+            return QoiOp::Index { idx }.append_bytes(buf);
+        }
+
+        let Wrapping(dr) = Wrapping(r) - Wrapping(pr);
+        let Wrapping(dg) = Wrapping(g) - Wrapping(pg);
+        let Wrapping(db) = Wrapping(b) - Wrapping(pb);
+        let same_alpha = a == pa;
+
+        let [diff_r, diff_g, diff_b] = [dr, dg, db].map(|d| d.wrapping_add(2));
+        let Wrapping(dr_dg) = Wrapping(8u8) + Wrapping(dr) - Wrapping(dg);
+        let Wrapping(db_dg) = Wrapping(8u8) + Wrapping(db) - Wrapping(dg);
+        let Wrapping(luma_dg) = Wrapping(32u8) + Wrapping(dg);
+
+        // exactly one of these is set
+        let is_diff = same_alpha & ((diff_r | diff_g | diff_b) < 4);
+        let is_luma = !is_diff & same_alpha & (luma_dg < 64) & ((dr_dg | db_dg) < 16);
+        let is_rgb = !is_diff & !is_luma & same_alpha;
+        let is_rgba = !same_alpha;
+
+        let diff_byte = (0b01 << 6) | (diff_r << 4) | (diff_g << 2) | (diff_b << 0);
+        let diff = [diff_byte, 0, 0, 0, 0, 0, 0, 0];
+        let luma = [(0b10 << 6) | luma_dg, (dr_dg << 4) | db_dg, 0, 0, 0, 0, 0, 0];
+        let rgb = [0b11111110, r, g, b, 0, 0, 0, 0];
+        let rgba = [0b11111111, r, g, b, a, 0, 0, 0];
+        let mask = |set: bool| (set as u64).wrapping_neg();
+        let bytes = u64::from_le_bytes(diff) & mask(is_diff)
+            | u64::from_le_bytes(luma) & mask(is_luma)
+            | u64::from_le_bytes(rgb) & mask(is_rgb)
+            | u64::from_le_bytes(rgba) & mask(is_rgba);
+        let len = is_diff as usize
+            + 2 * is_luma as usize
+            + 4 * is_rgb as usize
+            + 5 * is_rgba as usize;
+
+        // a fixed-size copy and a truncate are cheaper than a copy of `len` bytes
+        buf.extend_from_slice(&bytes.to_le_bytes());
+        buf.truncate(buf.len() - 8 + len);
+
+        stats.diff += is_diff as usize;
+        stats.luma += is_luma as usize;
+        stats.rgb += is_rgb as usize;
+        stats.rgba += is_rgba as usize;
+    }
+
+    #[cfg(feature = "decode")]
+    // reconstructs the pixel produced by this op, runs repeat `prev`
+    fn apply(self, cache: &impl CachePolicy, prev: Pixel) -> Pixel {
+        match self {
+            QoiOp::RGB { r, g, b } => {
+                let a = prev.a;
+                Pixel::new(r, g, b, a)
+            }
+            QoiOp::RGBA { r, g, b, a } => {
+                Pixel::new(r, g, b, a)
+            }
+            QoiOp::Index { idx } => {
+                cache.get(idx)
+            }
+            QoiOp::Diff { dr, dg, db } => {
+                let Pixel { r: pr, g: pg, b: pb, a } = prev;
+                let Wrapping(r) = Wrapping(pr) + Wrapping(dr) - Wrapping(2);
+                let Wrapping(g) = Wrapping(pg) + Wrapping(dg) - Wrapping(2);
+                let Wrapping(b) = Wrapping(pb) + Wrapping(db) - Wrapping(2);
+                Pixel::new(r, g, b, a)
+            }
+            QoiOp::Luma { dg, dr_dg, db_dg } => {
+                let Wrapping(dg) = Wrapping(dg) - Wrapping(32);
+                let Wrapping(dr) = Wrapping(dr_dg) + Wrapping(dg) - Wrapping(8);
+                let Wrapping(db) = Wrapping(db_dg) + Wrapping(dg) - Wrapping(8);
+                let Pixel { r: pr, g: pg, b: pb, a } = prev;
+                let Wrapping(r) = Wrapping(pr) + Wrapping(dr);
+                let Wrapping(g) = Wrapping(pg) + Wrapping(dg);
+                let Wrapping(b) = Wrapping(pb) + Wrapping(db);
+                Pixel::new(r, g, b, a)
+            }
+            QoiOp::Run { .. } => prev,
+        }
+    }
+
+    #[cfg(feature = "decode")]
+    // Decodes the op at the start of `chunk`, which is read as a whole so that the
+    // operands need no bounds checks (no op is longer than 8 bytes). Each arm builds
+    // a `QoiOp` with a known variant, so the second match in `apply` is left for the
+    // optimizer to fold, keeping the decoder at one hard-to-predict branch per op.
+    fn decode(chunk: &[u8; 8], cache: &impl CachePolicy, prev: Pixel) -> DecodedOp {
+        let [head, b1, b2, b3, b4, ..] = *chunk;
+        let data = head & 0b00111111;
+        let single = |op: QoiOp, len| DecodedOp {
+            pixel: op.apply(cache, prev),
+            count: 1,
+            len,
+            run: false,
+        };
+        match OP_KINDS[head as usize] {
+            OpKind::Rgb => single(QoiOp::RGB { r: b1, g: b2, b: b3 }, 4),
+            OpKind::Rgba => single(QoiOp::RGBA { r: b1, g: b2, b: b3, a: b4 }, 5),
+            OpKind::Index => single(QoiOp::Index { idx: data }, 1),
+            OpKind::Diff => {
+                let dr = (data >> 4) & 0b11;
+                let dg = (data >> 2) & 0b11;
+                let db = (data >> 0) & 0b11;
+                single(QoiOp::Diff { dr, dg, db }, 1)
+            }
+            OpKind::Luma => {
+                let dr_dg = (b1 >> 4) & 0b1111;
+                let db_dg = (b1 >> 0) & 0b1111;
+                single(QoiOp::Luma { dg: data, dr_dg, db_dg }, 2)
+            }
+            OpKind::Run => DecodedOp {
+                pixel: prev,
+                count: data as usize + 1,
+                len: 1,
+                run: true,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "decode")]
+// The pixel of an op, how many times it repeats, the op's length in bytes and
+// whether it was a RUN.
+struct DecodedOp {
+    pixel: Pixel,
+    count: usize,
+    len: usize,
+    run: bool,
+}
+
+#[cfg(feature = "decode")]
+#[derive(Clone, Copy)]
+enum OpKind {
+    Rgb,
+    Rgba,
+    Index,
+    Diff,
+    Luma,
+    Run,
+}
+
+#[cfg(feature = "decode")]
+// The op kind for every tag byte, so telling ops apart is a single lookup instead
+// of checking for the 8-bit tags before the 2-bit ones.
+const OP_KINDS: [OpKind; 256] = {
+    let mut kinds = [OpKind::Run; 256];
+    let mut head = 0;
+    while head < 0b11111110 {
+        kinds[head] = match head >> 6 {
+            0b00 => OpKind::Index,
+            0b01 => OpKind::Diff,
+            0b10 => OpKind::Luma,
+            _ => OpKind::Run,
+        };
+        head += 1;
+    }
+    kinds[0b11111110] = OpKind::Rgb;
+    kinds[0b11111111] = OpKind::Rgba;
+    kinds
+};
+
+// Decodes with a default `Decoder`, including its default `Limits`.
+#[cfg(feature = "decode")]
+impl TryFrom<&[u8]> for Image<Pixel> {
+    type Error = DecodeError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        Decoder::new().decode(data)
+    }
 }
 
-#[derive(Copy, Clone, PartialEq)]
-pub struct Pixel {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-    pub a: u8,
+// Common interface of the available encoders, so callers can pick one at runtime.
+#[cfg(feature = "encode")]
+pub trait QoiEncode {
+    fn name(&self) -> &'static str;
+    fn encode(&mut self, img: &[Pixel]) -> Result<Vec<u8>, EncodeError>;
+}
+
+#[cfg(feature = "encode")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EncoderKind {
+    #[default]
+    Standard,
+    #[cfg(feature = "qoi-plus")]
+    Plus,
+}
+
+#[cfg(feature = "encode")]
+impl EncoderKind {
+    pub const ALL: &[EncoderKind] = &[
+        EncoderKind::Standard,
+        #[cfg(feature = "qoi-plus")]
+        EncoderKind::Plus,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EncoderKind::Standard => "standard",
+            #[cfg(feature = "qoi-plus")]
+            EncoderKind::Plus => "plus",
+        }
+    }
+
+    pub fn build(self, width: u32, height: u32) -> Box<dyn QoiEncode> {
+        match self {
+            EncoderKind::Standard => Box::new(Encoder::new(width, height)),
+            #[cfg(feature = "qoi-plus")]
+            EncoderKind::Plus => Box::new(plus::EncoderExt::new(width, height)),
+        }
+    }
 }
-pub struct Image<T> {
-    pub width: usize,
-    pub height: usize,
-    pub pixels: Vec<T>,
+
+// Op counts of the last encode, mostly useful to compare encoder settings.
+#[cfg(feature = "encode")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EncodeStats {
+    pub pixels: usize,
+    pub run: usize,
+    pub run_pixels: usize,
+    pub index: usize,
+    pub diff: usize,
+    pub luma: usize,
+    pub rgb: usize,
+    pub rgba: usize,
+    // with `Encoder::near_lossless`, how far the stored pixels are from the input:
+    // the pixels that changed, the largest change of a channel, and the sum of
+    // the squared changes over all color channels
+    pub changed_pixels: usize,
+    pub max_error: u8,
+    pub squared_error: u64,
 }
 
-impl Pixel {
-    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
-        Self { r, g, b, a }
+#[cfg(feature = "encode")]
+impl EncodeStats {
+    // counts `op` and appends it to `buf`
+    fn append(&mut self, op: QoiOp, buf: &mut Vec<u8>) {
+        self.record(&op);
+        op.append_bytes(buf);
+    }
+
+    fn record(&mut self, op: &QoiOp) {
+        match *op {
+            QoiOp::Run { len } => {
+                self.run += 1;
+                self.run_pixels += len as usize;
+            }
+            QoiOp::Index { .. } => self.index += 1,
+            QoiOp::Diff { .. } => self.diff += 1,
+            QoiOp::Luma { .. } => self.luma += 1,
+            QoiOp::RGB { .. } => self.rgb += 1,
+            QoiOp::RGBA { .. } => self.rgba += 1,
+        }
+    }
+
+    // for `count` pixels stored the same way
+    fn record_error(&mut self, original: Pixel, stored: Pixel, count: usize) {
+        if original == stored {
+            return;
+        }
+        self.changed_pixels += count;
+        for (a, b) in [(original.r, stored.r), (original.g, stored.g), (original.b, stored.b)] {
+            let error = a.abs_diff(b);
+            self.max_error = self.max_error.max(error);
+            self.squared_error += error as u64 * error as u64 * count as u64;
+        }
+    }
+
+    // per color channel, over every pixel
+    pub fn mean_squared_error(&self) -> f64 {
+        match self.pixels {
+            0 => 0.0,
+            pixels => self.squared_error as f64 / (3 * pixels) as f64,
+        }
     }
 
-    fn hash(&self) -> u8 {
-        let &Pixel { r, g, b, a } = self;
-        let hash = (Wrapping(r) * Wrapping(3)
-                  + Wrapping(g) * Wrapping(5)
-                  + Wrapping(b) * Wrapping(7)
-                  + Wrapping(a) * Wrapping(11)) % Wrapping(64);
-        hash.0
+    // every pixel that does not continue a run is looked up in the index cache
+    pub fn cache_lookups(&self) -> usize {
+        self.index + self.diff + self.luma + self.rgb + self.rgba
     }
 
-    pub fn to_bytes(&self) -> [u8; 4] {
-        [self.r, self.g, self.b, self.a]
+    pub fn cache_hit_rate(&self) -> f64 {
+        match self.cache_lookups() {
+            0 => 0.0,
+            lookups => self.index as f64 / lookups as f64,
+        }
     }
 }
 
-pub struct Encoder {
+#[cfg(feature = "encode")]
+pub struct Encoder<C: CachePolicy = SpecCache> {
     width: u32,
     height: u32,
     channels: u8,
     colorspace: u8,
-    cache: [Pixel; 64],
+    cache: C,
     prev: Pixel,
+    stats: EncodeStats,
+    force_opaque: bool,
+    color_key: Option<Pixel>,
+    tolerance: u8,
+    limits: Limits,
+    hooks: Hooks,
 }
 
+#[cfg(feature = "encode")]
 impl Encoder {
     pub fn new(width: u32, height: u32) -> Self {
+        Self::with_cache(width, height)
+    }
+
+    // The size of the largest file an image of these dimensions can encode to,
+    // with every pixel an RGBA op.
+    pub fn max_encoded_len(width: u32, height: u32) -> usize {
+        let pixels = (width as usize).saturating_mul(height as usize);
+        pixels.saturating_mul(5).saturating_add(14 + END_MARKER.len())
+    }
+
+    // Takes the dimensions, channels and colorspace from an existing header, e.g.
+    // one read by `Decoder::header` when re-encoding a modified image.
+    pub fn with_header(header: Header) -> Self {
+        Self {
+            channels: header.channels,
+            colorspace: header.colorspace,
+            ..Self::with_cache(header.width, header.height)
+        }
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<C: CachePolicy> Encoder<C> {
+    pub fn with_cache(width: u32, height: u32) -> Self {
         Self {
             width,
             height,
             channels: 4,
             colorspace: 0,
-            cache: [Pixel::new(0, 0, 0, 255); 64],
+            cache: C::default(),
             prev: Pixel::new(0, 0, 0, 255),
+            stats: EncodeStats::default(),
+            force_opaque: false,
+            color_key: None,
+            tolerance: 0,
+            limits: Limits::default(),
+            hooks: Hooks::default(),
+        }
+    }
+
+    // The default caps images at the 400M pixels that qoi.h accepts.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // Gives up with `EncodeError::Cancelled` soon after `flag` is set, e.g. from
+    // a UI thread. Whatever was appended to the output is removed again.
+    pub fn cancel_on(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.hooks.cancel = Some(flag);
+        self
+    }
+
+    // Calls `progress` with the pixels encoded so far and the total about every
+    // `interval` pixels, and once more when done.
+    pub fn on_progress(
+        mut self,
+        interval: usize,
+        progress: impl FnMut(usize, usize) + Send + 'static,
+    ) -> Self {
+        self.hooks.progress = Some((interval.max(1), Box::new(progress)));
+        self
+    }
+
+    // Treat every pixel as fully opaque and write a 3-channel header, so alpha
+    // jitter in RGBA sources does not force every pixel into an RGBA op.
+    pub fn force_opaque(mut self, force_opaque: bool) -> Self {
+        self.force_opaque = force_opaque;
+        self
+    }
+
+    // Pixels matching the key's color (alpha is ignored) are written fully
+    // transparent, for assets that used a color such as magenta as transparency.
+    // Applied after `force_opaque`, which then no longer drops the alpha channel.
+    pub fn color_key(mut self, key: Option<Pixel>) -> Self {
+        self.color_key = key;
+        self
+    }
+
+    // Lets the encoder change each color channel by up to `tolerance` when that
+    // makes the pixel a repeat of the one before, a cached one, or a small
+    // difference from the one before, which have shorter ops. Alpha is kept
+    // exact. 0, the default, is lossless.
+    pub fn near_lossless(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    // 0 for sRGB with linear alpha, 1 for all channels linear; only stored in the header
+    pub fn colorspace(mut self, colorspace: u8) -> Self {
+        self.colorspace = colorspace;
+        self
+    }
+
+    fn preprocess(&self, pixel: Pixel) -> Pixel {
+        match self.color_key {
+            Some(key) if (pixel.r, pixel.g, pixel.b) == (key.r, key.g, key.b) => {
+                Pixel { a: 0, ..key }
+            }
+            _ if self.force_opaque => Pixel { a: 255, ..pixel },
+            _ => pixel,
+        }
+    }
+
+    // `pixel` after the pixel options, and what is stored for it after the
+    // previous pixel, which differs only with a tolerance.
+    fn stored(&self, pixel: Pixel) -> (Pixel, Pixel) {
+        let pixel = self.preprocess(pixel);
+        match self.tolerance {
+            0 => (pixel, pixel),
+            _ => (pixel, self.approximate(self.prev, pixel)),
+        }
+    }
+
+    // The pixel to store for `pixel` following `prev` within the tolerance,
+    // trying the ops from the shortest: a run, an index into the cache, DIFF, an
+    // index to a near pixel, and LUMA. Falls back to `pixel` itself.
+    fn approximate(&self, prev: Pixel, pixel: Pixel) -> Pixel {
+        let tolerance = self.tolerance;
+        let near = |other: Pixel| {
+            other.a == pixel.a
+                && other.r.abs_diff(pixel.r) <= tolerance
+                && other.g.abs_diff(pixel.g) <= tolerance
+                && other.b.abs_diff(pixel.b) <= tolerance
+        };
+        if near(prev) {
+            return prev;
+        }
+        if self.cache.find(pixel).is_some() {
+            return pixel;
+        }
+
+        // `prev` moved by as much of the difference to `pixel` as `range` allows
+        let towards = |from: u8, to: u8, offset: i16, range: (i16, i16)| {
+            let wanted = to as i16 - from as i16 - offset;
+            from.wrapping_add((offset + wanted.clamp(range.0, range.1)) as u8)
+        };
+        let diff = Pixel {
+            r: towards(prev.r, pixel.r, 0, (-2, 1)),
+            g: towards(prev.g, pixel.g, 0, (-2, 1)),
+            b: towards(prev.b, pixel.b, 0, (-2, 1)),
+            a: pixel.a,
+        };
+        if near(diff) {
+            return diff;
+        }
+        if let Some(cached) = (0..64).map(|idx| self.cache.get(idx)).find(|&cached| near(cached)) {
+            return cached;
         }
+        let g = towards(prev.g, pixel.g, 0, (-32, 31));
+        let dg = g.wrapping_sub(prev.g) as i8 as i16;
+        let luma = Pixel {
+            r: towards(prev.r, pixel.r, dg, (-8, 7)),
+            g,
+            b: towards(prev.b, pixel.b, dg, (-8, 7)),
+            a: pixel.a,
+        };
+        if near(luma) {
+            return luma;
+        }
+        pixel
+    }
+
+    pub fn stats(&self) -> &EncodeStats {
+        &self.stats
     }
 
     fn append_header(&self, buf: &mut Vec<u8>) {
-        buf.extend(b"qoif");
-        buf.extend(self.width.to_be_bytes());
-        buf.extend(self.height.to_be_bytes());
-        buf.push(self.channels);
-        buf.push(self.colorspace);
+        self.append_header_for(self.channels, buf);
+    }
+
+    // `channels` is what the pixel type holds, 4 unless the image has no alpha
+    fn append_header_for(&self, channels: u8, buf: &mut Vec<u8>) {
+        let opaque = self.force_opaque && self.color_key.is_none();
+        let header = Header {
+            width: self.width,
+            height: self.height,
+            channels: if opaque { 3 } else { self.channels.min(channels) },
+            colorspace: self.colorspace,
+        };
+        header.append_bytes(b"qoif", buf);
+    }
+
+    pub fn encode(&mut self, img: &[Pixel]) -> Result<Vec<u8>, EncodeError> {
+        let mut buf = vec![];
+        self.encode_into(img, &mut buf)?;
+        Ok(buf)
     }
 
-    pub fn encode(&mut self, img: &[Pixel]) -> Vec<u8> {
+    // Appends the encoded image to `buf`. Nothing is allocated when `buf` has
+    // `Encoder::max_encoded_len` bytes of spare capacity, so callers that manage
+    // their own memory can reuse one buffer for every image.
+    pub fn encode_into(&mut self, img: &[Pixel], buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        self.encode_pixels(img, buf)
+    }
+
+    // `encode` for either channel count; `Pixel<3>` images become 3-channel files.
+    pub fn encode_as<const N: usize>(&mut self, img: &[Pixel<N>]) -> Result<Vec<u8>, EncodeError>
+    where
+        Channels<N>: ChannelCount,
+    {
         let mut buf = vec![];
+        self.encode_pixels(img, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn encode_pixels<const N: usize>(
+        &mut self,
+        img: &[Pixel<N>],
+        buf: &mut Vec<u8>,
+    ) -> Result<(), EncodeError>
+    where
+        Channels<N>: ChannelCount,
+    {
+        check_dimensions(self.width, self.height, &self.limits, img.len())
+            .inspect_err(|err| trace::rejected(self.width, self.height, err))?;
+        let trace = trace::encode(self.width, self.height);
+        let start = buf.len();
 
         // header
-        self.append_header(&mut buf);
+        self.append_header_for(Pixel::<N>::CHANNELS, buf);
 
+        self.cache = C::default();
+        self.prev = Pixel::<4>::new(0, 0, 0, 255);
+        self.stats = EncodeStats { pixels: img.len(), ..Default::default() };
+
+        // No peephole pass is needed over this output: the decoder's prev pixel and
+        // cache depend only on the pixels so far, never on the ops chosen for them, so
+        // the smallest op for every pixel and ceil(n / 62) RUN ops for every run of
+        // n repeats already give the smallest possible stream.
         let mut is_running = false;
         let mut run_length = 0;
-        let mut ops = Vec::<QoiOp>::new();
 
         // body
-        for pixel in img {
+        let mut next_check = self.hooks.first_check();
+        for (i, pixel) in img.iter().enumerate() {
+            if i >= next_check {
+                let Some(next) = self.hooks.check(i, img.len()) else {
+                    buf.truncate(start);
+                    return Err(EncodeError::Cancelled);
+                };
+                next_check = next;
+            }
+            let (original, pixel) = self.stored(pixel.to_rgba());
+            self.stats.record_error(original, pixel, 1);
             let prev = self.prev;
+            let pixel = &pixel;
             self.prev = *pixel;
-            let &Pixel { r, g, b, a } = pixel;
-            let &Pixel { r: pr, g: pg, b: pb, a: pa } = &prev;
 
             if is_running {
                 if prev.eq(pixel) {
                     if run_length >= 62 {
-                        ops.push(QoiOp::Run { len: 62 });
+                        self.stats.append(QoiOp::Run { len: 62 }, buf);
                         run_length -= 62;
                     }
                     run_length += 1;
@@ -170,7 +739,7 @@ impl Encoder {
                 } else {
                     is_running = false;
                     if run_length > 0 {
-                        ops.push(QoiOp::Run { len: run_length });
+                        self.stats.append(QoiOp::Run { len: run_length }, buf);
                     }
                 }
             }
@@ -182,156 +751,1402 @@ impl Encoder {
                 continue;
             }
 
-            let h = pixel.hash();
+            QoiOp::append_for_pixel(&mut self.cache, prev, *pixel, buf, &mut self.stats);
+        }
 
-            if self.cache[h as usize].eq(pixel) {
-                ops.push(QoiOp::Index { idx: h });
-                continue;
-            }
+        if is_running {
+            self.stats.append(QoiOp::Run { len: run_length }, buf);
+        }
 
-            let Wrapping(dr) = Wrapping(r) - Wrapping(pr) + Wrapping(2);
-            let Wrapping(dg) = Wrapping(g) - Wrapping(pg) + Wrapping(2);
-            let Wrapping(db) = Wrapping(b) - Wrapping(pb) + Wrapping(2);
-            let Wrapping(da) = Wrapping(a) - Wrapping(pa);
+        // footer
+        buf.extend_from_slice(&END_MARKER);
 
-            if da == 0 && 0 <= dr && dr <= 3 && 0 <= dg && dg <= 3 && 0 <= db && db <= 3 {
-                ops.push(QoiOp::Diff { dr, dg, db });
-                continue;
-            }
+        self.hooks.finish(img.len(), img.len());
+        trace.finish(img.len(), buf.len() - start);
+        Ok(())
+    }
+}
 
-            let Wrapping(dg) = Wrapping(g) - Wrapping(pg);
-            let Wrapping(dr) = Wrapping(r) - Wrapping(pr);
-            let Wrapping(db) = Wrapping(b) - Wrapping(pb);
-            let Wrapping(dr_dg) = Wrapping(8u8) + Wrapping(dr) - Wrapping(dg);
-            let Wrapping(db_dg) = Wrapping(8u8) + Wrapping(db) - Wrapping(dg);
-            let Wrapping(dg) = Wrapping(32u8) + Wrapping(dg);
+#[cfg(feature = "encode")]
+impl<C: CachePolicy> QoiEncode for Encoder<C> {
+    fn name(&self) -> &'static str {
+        EncoderKind::Standard.name()
+    }
 
-            if da == 0 && 0 <= dg && dg < 64 && 0 <= dr_dg && dr_dg < 16 && 0 <= db_dg && db_dg < 16
-            {
-                ops.push(QoiOp::Luma { dg, dr_dg, db_dg, });
-                continue;
-            }
+    fn encode(&mut self, img: &[Pixel]) -> Result<Vec<u8>, EncodeError> {
+        Encoder::encode(self, img)
+    }
+}
 
-            if da == 0 {
-                ops.push(QoiOp::RGB { r, g, b });
-            } else {
-                ops.push(QoiOp::RGBA { r, g, b, a });
-            }
-        }
+// Rejects what would otherwise become a file other decoders refuse, or one
+// whose header does not match its pixels.
+#[cfg(feature = "encode")]
+fn check_dimensions(width: u32, height: u32, limits: &Limits, len: usize) -> Result<(), EncodeError> {
+    if width == 0 || height == 0 {
+        return Err(EncodeError::ZeroDimensions);
+    }
+    if !limits.allows(width, height) {
+        return Err(EncodeError::LimitsExceeded);
+    }
+    if len != width as usize * height as usize {
+        return Err(EncodeError::PixelCountMismatch);
+    }
+    Ok(())
+}
 
-        if is_running {
-            ops.push(QoiOp::Run { len: run_length });
-        }
+// Caps on the work spent on a single image, checked while the ops are decoded,
+// so a large but well-formed file cannot keep a worker busy indefinitely.
+#[cfg(feature = "decode")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    // RGBA bytes produced, counting every decoded pixel whether or not it is kept
+    pub max_output_bytes: usize,
+    pub max_ops: usize,
+}
 
-        for op in ops {
-            op.append_bytes(&mut buf);
+#[cfg(feature = "decode")]
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            max_output_bytes: usize::MAX,
+            max_ops: usize::MAX,
         }
-
-        // footer
-        buf.extend_from_slice(&[0u8, 0, 0, 0, 0, 0, 0, 1]);
-
-        buf
     }
 }
 
-pub struct Decoder {
-    cache: [Pixel; 64],
+// What `Decoder::decode_partial` recovered from a possibly damaged file.
+#[cfg(feature = "decode")]
+pub struct PartialDecode {
+    // always the full size declared in the header
+    pub image: Image<Pixel>,
+    // the leading pixels that came from the op stream; the rest repeat the last of
+    // them, or are the pixel given to `Decoder::pad_with`
+    pub decoded: usize,
+    // what stopped decoding, and the input offset of the op it stopped at
+    pub error: Option<DecodeError>,
+    pub offset: Option<usize>,
+}
+
+#[cfg(feature = "decode")]
+pub struct Decoder<C: CachePolicy = SpecCache, O: ops::Observer = ()> {
+    cache: C,
     prev: Pixel,
+    color_key: Option<Pixel>,
+    limits: Limits,
+    budget: Budget,
+    lenient: bool,
+    header: Option<Header>,
+    trailing: usize,
+    input_len: usize,
+    error_offset: Option<usize>,
+    padding: Option<Pixel>,
+    hooks: Hooks,
+    observer: O,
 }
 
+#[cfg(feature = "decode")]
 impl Decoder {
     pub fn new() -> Self {
+        Self::with_cache()
+    }
+
+    pub fn dimensions(data: &[u8]) -> Result<(usize, usize), DecodeError> {
+        let (Header { width, height, .. }, _) =
+            Header::from_bytes(b"qoif", data).ok_or(DecodeError::InvalidHeader)?;
+        Ok((width as usize, height as usize))
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<C: CachePolicy> Default for Decoder<C> {
+    fn default() -> Self {
+        Self::with_cache()
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<C: CachePolicy> Decoder<C> {
+    pub fn with_cache() -> Self {
         Self {
-            cache: [Pixel::new(0, 0, 0, 255); 64],
+            cache: C::default(),
             prev: Pixel::new(0, 0, 0, 255),
+            color_key: None,
+            limits: Limits::default(),
+            budget: Budget::default(),
+            lenient: false,
+            header: None,
+            trailing: 0,
+            input_len: 0,
+            error_offset: None,
+            padding: None,
+            hooks: Hooks::default(),
+            observer: (),
         }
     }
 
-    pub fn decode(&mut self, data: &[u8]) -> Option<Image<Pixel>> {
-        // header
-        let (magic, data) = data.split_at_checked(4)?;
-        if !magic.eq(b"qoif") {
-            return None;
+    // Calls `observer` with every op read by the decode methods, e.g. to gather
+    // statistics without a second pass over the file.
+    pub fn with_observer<O: ops::Observer>(self, observer: O) -> Decoder<C, O> {
+        Decoder {
+            cache: self.cache,
+            prev: self.prev,
+            color_key: self.color_key,
+            limits: self.limits,
+            budget: self.budget,
+            lenient: self.lenient,
+            header: self.header,
+            trailing: self.trailing,
+            input_len: self.input_len,
+            error_offset: self.error_offset,
+            padding: self.padding,
+            hooks: self.hooks,
+            observer,
         }
+    }
+}
 
-        let (width_bytes, data) = data.split_first_chunk::<4>()?;
-        let width = u32::from_be_bytes(*width_bytes);
-        let (height_bytes, data) = data.split_first_chunk::<4>()?;
-        let height = u32::from_be_bytes(*height_bytes);
+#[cfg(feature = "decode")]
+impl<C: CachePolicy, O: ops::Observer> Decoder<C, O> {
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn into_observer(self) -> O {
+        self.observer
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    // Stops with `DecodeError::Cancelled` soon after `flag` is set, leaving the
+    // output as for any other error.
+    pub fn cancel_on(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.hooks.cancel = Some(flag);
+        self
+    }
+
+    // Calls `progress` with the pixels decoded so far and the total about every
+    // `interval` pixels, and once more when the ops end.
+    pub fn on_progress(
+        mut self,
+        interval: usize,
+        progress: impl FnMut(usize, usize) + Send + 'static,
+    ) -> Self {
+        self.hooks.progress = Some((interval.max(1), Box::new(progress)));
+        self
+    }
+
+    // The inverse of `Encoder::color_key`: fully transparent pixels come out as `key`.
+    pub fn color_key(mut self, key: Option<Pixel>) -> Self {
+        self.color_key = key;
+        self
+    }
+
+    // Has `decode_partial` fill what it could not decode with `pixel`, e.g. a
+    // color absent from the image to make the damage stand out.
+    pub fn pad_with(mut self, pixel: Option<Pixel>) -> Self {
+        self.padding = pixel;
+        self
+    }
+
+    // Tolerates bytes after the end marker, as appended by some writers, and
+    // channels or colorspace values outside the spec. The number of ignored
+    // bytes is available from `trailing_len` after decoding.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    // The header of the last image read, including its channels and colorspace.
+    pub fn header(&self) -> Option<Header> {
+        self.header
+    }
+
+    pub fn trailing_len(&self) -> usize {
+        self.trailing
+    }
+
+    // Where in the input the last decode failed: the byte offset of the op that
+    // could not be decoded, or of the missing end marker. `None` after a success
+    // and for errors found before the op stream, such as a bad header.
+    pub fn error_offset(&self) -> Option<usize> {
+        self.error_offset
+    }
+
+    pub fn decode(&mut self, data: &[u8]) -> Result<Image<Pixel>, DecodeError> {
+        let mut image = Image {
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+        };
+        self.decode_into(data, &mut image)?;
+        Ok(image)
+    }
+
+    // `decode` for either channel count; `Pixel<3>` drops alpha while decoding.
+    pub fn decode_as<const N: usize>(&mut self, data: &[u8]) -> Result<Image<Pixel<N>>, DecodeError>
+    where
+        Channels<N>: ChannelCount,
+    {
+        let mut image = Image { width: 0, height: 0, pixels: Vec::new() };
+        self.decode_into(data, &mut image)?;
+        Ok(image)
+    }
+
+    // Decodes into `image`, reusing its pixel storage when it is large enough.
+    // On error `image` is left empty.
+    pub fn decode_into<const N: usize>(
+        &mut self,
+        data: &[u8],
+        image: &mut Image<Pixel<N>>,
+    ) -> Result<(), DecodeError>
+    where
+        Channels<N>: ChannelCount,
+    {
+        image.width = 0;
+        image.height = 0;
+        image.pixels.clear();
 
-        let (_channels, data) = data.split_first()?;
-        let (_colorspace, data) = data.split_first()?;
+        // header
+        let (Header { width, height, .. }, data) = self.read_header(data)?;
+        let total = width as usize * height as usize;
+        check_input_len(data, total)?;
 
         // body
-        let mut data = data;
-        let mut pixels = Vec::<Pixel>::with_capacity((width * height) as usize);
-        while pixels.len() < (width * height) as usize {
-            let (op, rest) = QoiOp::from_bytes(data)?;
-            let mut count: u8 = 1;
-            let pixel = match op {
-                QoiOp::RGB { r, g, b } => {
-                    let a = self.prev.a;
-                    Pixel::new(r, g, b, a)
-                }
-                QoiOp::RGBA { r, g, b, a } => {
-                    Pixel::new(r, g, b, a)
-                }
-                QoiOp::Index { idx } => {
-                    *self.cache.get(idx as usize)?
-                }
-                QoiOp::Diff { dr, dg, db } => {
-                    let Pixel { r: pr, g: pg, b: pb, a } = self.prev;
-                    let Wrapping(r) = Wrapping(pr) + Wrapping(dr) - Wrapping(2);
-                    let Wrapping(g) = Wrapping(pg) + Wrapping(dg) - Wrapping(2);
-                    let Wrapping(b) = Wrapping(pb) + Wrapping(db) - Wrapping(2);
-                    Pixel::new(r, g, b, a)
-                }
-                QoiOp::Luma { dg, dr_dg, db_dg } => {
-                    let Wrapping(dg) = Wrapping(dg) - Wrapping(32);
-                    let Wrapping(dr) = Wrapping(dr_dg) + Wrapping(dg) - Wrapping(8);
-                    let Wrapping(db) = Wrapping(db_dg) + Wrapping(dg) - Wrapping(8);
-                    let Pixel { r: pr, g: pg, b: pb, a } = self.prev;
-                    let Wrapping(r) = Wrapping(pr) + Wrapping(dr);
-                    let Wrapping(g) = Wrapping(pg) + Wrapping(dg);
-                    let Wrapping(b) = Wrapping(pb) + Wrapping(db);
-                    Pixel::new(r, g, b, a)
+        let pixels = &mut image.pixels;
+        pixels.try_reserve_exact(total).map_err(|_| DecodeError::OutOfMemory)?;
+        let result = self.decode_ops(data, total, |_, pixel, count| {
+            pixels.extend(std::iter::repeat_n(Pixel::from_rgba(pixel), count));
+            ControlFlow::Continue(())
+        });
+
+        // footer
+        if let Err(err) = result.and_then(|data| self.read_footer(data)) {
+            image.pixels.clear();
+            return Err(err);
+        }
+
+        image.width = width as usize;
+        image.height = height as usize;
+        Ok(())
+    }
+
+    // Salvages what it can from truncated or corrupted files: decodes ops until the
+    // first one that fails and pads the image from there. Only an unusable header
+    // is an error. Bytes after the end marker are skipped as in lenient mode.
+    pub fn decode_partial(&mut self, data: &[u8]) -> Result<PartialDecode, DecodeError> {
+        let (Header { width, height, .. }, data) = self.read_header(data)?;
+        let total = width as usize * height as usize;
+        // the padding is bounded too: input that could not hold the whole image
+        // intact is refused as by `decode`, before anything is allocated
+        check_input_len(data, total)?;
+        if total.saturating_mul(4) > self.budget.max_output_bytes {
+            return Err(DecodeError::BudgetExceeded);
+        }
+
+        let mut pixels = alloc_pixels(total)?;
+        let result = self.decode_ops(data, total, |_, pixel, count| {
+            pixels.extend(std::iter::repeat_n(pixel, count));
+            ControlFlow::Continue(())
+        });
+        let error = match result {
+            Ok(rest) => match rest.strip_prefix(&END_MARKER) {
+                Some(trailing) => {
+                    self.trailing = trailing.len();
+                    None
                 }
-                QoiOp::Run { len } => {
-                    count = len;
-                    self.prev
+                None => Some(self.fail(rest, DecodeError::InvalidEndMarker)),
+            },
+            Err(err) => Some(err),
+        };
+
+        let decoded = pixels.len();
+        let last = pixels.last().copied().unwrap_or(Pixel::new(0, 0, 0, 255));
+        pixels.resize(total, self.padding.unwrap_or(last));
+        let image = Image { width: width as usize, height: height as usize, pixels };
+        Ok(PartialDecode { image, decoded, error, offset: self.error_offset })
+    }
+
+    // Writes RGBA bytes straight into `out`, which must hold at least
+    // `width * height * 4` bytes, and returns the dimensions.
+    pub fn decode_to_rgba(
+        &mut self,
+        data: &[u8],
+        out: &mut [u8],
+    ) -> Result<(usize, usize), DecodeError> {
+        self.decode_to_bytes::<4>(data, out)
+    }
+
+    // Like `decode_to_rgba` with 3 bytes per pixel, for opaque images such as
+    // screenshots and photos. Alpha is dropped, as by qoi.h when asked for 3 channels.
+    pub fn decode_to_rgb(
+        &mut self,
+        data: &[u8],
+        out: &mut [u8],
+    ) -> Result<(usize, usize), DecodeError> {
+        self.decode_to_bytes::<3>(data, out)
+    }
+
+    // `N` is 3 or 4, so the per-pixel copy has a fixed size.
+    fn decode_to_bytes<const N: usize>(
+        &mut self,
+        data: &[u8],
+        out: &mut [u8],
+    ) -> Result<(usize, usize), DecodeError> {
+        // header
+        let (Header { width, height, .. }, data) = self.read_header(data)?;
+        let total = width as usize * height as usize;
+        let out = total
+            .checked_mul(N)
+            .and_then(|len| out.get_mut(..len))
+            .ok_or(DecodeError::BufferTooSmall)?;
+        check_input_len(data, total)?;
+
+        // body
+        let data = self.decode_ops(data, total, |index, pixel, count| {
+            let bytes = pixel.to_bytes();
+            let (bytes, _) = bytes.split_first_chunk::<N>().unwrap();
+            if count == 1 {
+                out[N * index..N * index + N].copy_from_slice(bytes);
+            } else {
+                for chunk in out[N * index..N * (index + count)].chunks_exact_mut(N) {
+                    chunk.copy_from_slice(bytes);
                 }
-            };
-            self.prev = pixel;
-            let h = pixel.hash();
-            self.cache[h as usize] = pixel;
-            data = rest;
+            }
+            ControlFlow::Continue(())
+        })?;
+
+        // footer
+        self.read_footer(data)?;
+
+        Ok((width as usize, height as usize))
+    }
+
+    // Only the pixels inside the rectangle are stored, and decoding stops once the
+    // rectangle is complete, so the end marker of the file is not checked.
+    pub fn decode_region(
+        &mut self,
+        data: &[u8],
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+    ) -> Result<Image<Pixel>, DecodeError> {
+        // header
+        let (Header { width, height, .. }, data) = self.read_header(data)?;
+        let (width, height) = (width as usize, height as usize);
+        let fits = |start: usize, len, max| start.checked_add(len).is_some_and(|end| end <= max);
+        if !fits(x, w, width) || !fits(y, h, height) {
+            return Err(DecodeError::InvalidArgument);
+        }
+        let total = width * height;
+        let end = (y + h) * width;
+        check_input_len(data, end)?;
 
-            for _ in 0..count {
-                pixels.push(pixel);
+        // body
+        let mut pixels = alloc_pixels(w * h)?;
+        self.decode_ops(data, total, |index, pixel, count| {
+            for i in index..index + count {
+                let (px, py) = (i % width, i / width);
+                if py >= y && py < y + h && px >= x && px < x + w {
+                    pixels.push(pixel);
+                }
             }
+            if index + count >= end {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })?;
+
+        if pixels.len() != w * h {
+            return Err(DecodeError::UnexpectedEof);
         }
 
-        if pixels.len() > (width * height) as usize {
-            return None;
+        Ok(Image {
+            width: w,
+            height: h,
+            pixels,
+        })
+    }
+
+    // Keeps every `factor`-th pixel of every `factor`-th row, which is much cheaper
+    // than a full decode followed by a resize when only a preview is needed.
+    pub fn decode_subsampled(
+        &mut self,
+        data: &[u8],
+        factor: usize,
+    ) -> Result<Image<Pixel>, DecodeError> {
+        if factor == 0 {
+            return Err(DecodeError::InvalidArgument);
         }
 
+        // header
+        let (Header { width, height, .. }, data) = self.read_header(data)?;
+        let (width, height) = (width as usize, height as usize);
+        let total = width * height;
+        let (out_width, out_height) = (width.div_ceil(factor), height.div_ceil(factor));
+        check_input_len(data, total)?;
+
+        // body
+        let mut pixels = alloc_pixels(out_width * out_height)?;
+        let data = self.decode_ops(data, total, |index, pixel, count| {
+            for i in index..index + count {
+                if (i / width).is_multiple_of(factor) && (i % width).is_multiple_of(factor) {
+                    pixels.push(pixel);
+                }
+            }
+            ControlFlow::Continue(())
+        })?;
+
         // footer
-        if [0u8, 0, 0, 0, 0, 0, 0, 1].ne(data) {
-            return None;
-        }
+        self.read_footer(data)?;
 
-        Some(Image {
-            width: width as usize,
-            height: height as usize,
+        Ok(Image {
+            width: out_width,
+            height: out_height,
             pixels,
         })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use image::{Rgba, RgbaImage};
-    use std::time::Instant;
+    // Also guarantees that `width * height` fits in a `usize`.
+    fn read_header<'a>(&mut self, data: &'a [u8]) -> Result<(Header, &'a [u8]), DecodeError> {
+        self.header = None;
+        self.input_len = data.len();
+        self.error_offset = None;
+        let (header, data) = Header::from_bytes(b"qoif", data).ok_or(DecodeError::InvalidHeader)?;
+        if !self.lenient && !matches!(header.channels, 3 | 4) {
+            return Err(DecodeError::InvalidChannels);
+        }
+        if !self.lenient && !matches!(header.colorspace, 0 | 1) {
+            return Err(DecodeError::InvalidColorspace);
+        }
+        if !self.limits.allows(header.width, header.height) {
+            trace::rejected(header.width, header.height, &DecodeError::LimitsExceeded);
+            return Err(DecodeError::LimitsExceeded);
+        }
+        self.header = Some(header);
+        Ok((header, data))
+    }
+
+    fn read_footer(&mut self, data: &[u8]) -> Result<(), DecodeError> {
+        match data.strip_prefix(&END_MARKER) {
+            Some(rest) if self.lenient || rest.is_empty() => {
+                self.trailing = rest.len();
+                Ok(())
+            }
+            _ => Err(self.fail(data, DecodeError::InvalidEndMarker)),
+        }
+    }
+
+    // records that decoding stopped with `rest` left of the input
+    fn fail(&mut self, rest: &[u8], err: DecodeError) -> DecodeError {
+        let offset = self.input_len - rest.len();
+        trace::failed(offset, &err);
+        self.error_offset = Some(offset);
+        err
+    }
+
+    // Runs the op stream for an image of `total` pixels, handing every op's pixel to
+    // `emit` together with its position and repeat count. Returns the bytes after the
+    // last op that was read.
+    fn decode_ops<'a>(
+        &mut self,
+        data: &'a [u8],
+        total: usize,
+        emit: impl FnMut(usize, Pixel, usize) -> ControlFlow<()>,
+    ) -> Result<&'a [u8], DecodeError> {
+        self.cache = C::default();
+        self.prev = Pixel::new(0, 0, 0, 255);
+        self.resume_ops(data, 0, total, emit)
+    }
+
+    // Like `decode_ops` from the op for pixel `index`, with the cache and previous
+    // pixel already set to what they were before it.
+    fn resume_ops<'a>(
+        &mut self,
+        mut data: &'a [u8],
+        mut index: usize,
+        total: usize,
+        mut emit: impl FnMut(usize, Pixel, usize) -> ControlFlow<()>,
+    ) -> Result<&'a [u8], DecodeError> {
+        self.trailing = 0;
+        let header = self.header.expect("read before the ops");
+        let trace = trace::decode(header.width, header.height, self.input_len);
+        let start = data.len();
+
+        // copied out of `self` so they can stay in registers for the whole loop
+        let (budget, color_key) = (self.budget, self.color_key);
+        let (cache, mut prev) = (&mut self.cache, self.prev);
+        let mut next_check = self.hooks.first_check();
+        let mut ops = 0;
+        while index < total {
+            if index >= next_check {
+                let Some(next) = self.hooks.check(index, total) else {
+                    return Err(self.fail(data, DecodeError::Cancelled));
+                };
+                next_check = next;
+            }
+            ops += 1;
+            if ops > budget.max_ops {
+                return Err(self.fail(data, DecodeError::BudgetExceeded));
+            }
+            let mut padded = [0; 8];
+            let chunk = match data.first_chunk() {
+                Some(chunk) => chunk,
+                // only the last few bytes of a truncated file, anything valid ends
+                // with the 8-byte end marker
+                None => {
+                    padded[..data.len()].copy_from_slice(data);
+                    &padded
+                }
+            };
+            let DecodedOp { pixel, count, len, run } = QoiOp::decode(chunk, cache, prev);
+            let Some(rest) = data.get(len..) else {
+                return Err(self.fail(data, DecodeError::UnexpectedEof));
+            };
+            // rejected before anything is emitted, so no pixel past `total` is ever stored
+            if index + count > total {
+                return Err(self.fail(data, DecodeError::RunOvershoot));
+            }
+            if (index + count).saturating_mul(4) > budget.max_output_bytes {
+                return Err(self.fail(data, DecodeError::BudgetExceeded));
+            }
+            if O::ENABLED {
+                let (op, _) = QoiOp::from_bytes(chunk).expect("a whole op");
+                let offset = self.input_len - data.len();
+                self.observer.op(offset, ops::Op::from_qoi(op), index);
+            }
+            prev = pixel;
+            if run {
+                cache.insert_run(pixel);
+            } else {
+                cache.insert(pixel);
+            }
+            data = rest;
+
+            let pixel = match color_key {
+                Some(key) if pixel.a == 0 => key,
+                _ => pixel,
+            };
+            if emit(index, pixel, count).is_break() {
+                break;
+            }
+            index += count;
+        }
+        self.prev = prev;
+
+        self.hooks.finish(index, total);
+        trace.finish(index, start - data.len());
+        Ok(data)
+    }
+}
+
+// Decodes a stream of frames into one buffer that is handed out by reference.
+// Once a frame of the largest size in the stream has been decoded, later frames
+// reuse its storage and decoding no longer allocates.
+#[cfg(feature = "decode")]
+pub struct FrameDecoder<C: CachePolicy = SpecCache> {
+    decoder: Decoder<C>,
+    frame: Image<Pixel>,
+}
+
+#[cfg(feature = "decode")]
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::with_decoder(Decoder::new())
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<C: CachePolicy> Default for FrameDecoder<C> {
+    fn default() -> Self {
+        Self::with_decoder(Decoder::default())
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<C: CachePolicy> FrameDecoder<C> {
+    pub fn with_decoder(decoder: Decoder<C>) -> Self {
+        Self {
+            decoder,
+            frame: Image {
+                width: 0,
+                height: 0,
+                pixels: Vec::new(),
+            },
+        }
+    }
+
+    // For the header and trailing length of the last frame.
+    pub fn decoder(&self) -> &Decoder<C> {
+        &self.decoder
+    }
+
+    pub fn decode_next(&mut self, data: &[u8]) -> Result<&Image<Pixel>, DecodeError> {
+        self.decoder.decode_into(data, &mut self.frame)?;
+        Ok(&self.frame)
+    }
+}
+
+// Every op byte yields at most 62 pixels (a full run), so shorter input cannot
+// hold `pixels` pixels and is rejected before anything is allocated for them.
+#[cfg(feature = "decode")]
+fn check_input_len(data: &[u8], pixels: usize) -> Result<(), DecodeError> {
+    if pixels > data.len().saturating_mul(62) {
+        return Err(DecodeError::UnexpectedEof);
+    }
+    Ok(())
+}
+
+// Reserves the output up front without aborting the process when the allocation fails.
+#[cfg(feature = "decode")]
+fn alloc_pixels(len: usize) -> Result<Vec<Pixel>, DecodeError> {
+    let mut pixels = Vec::new();
+    pixels.try_reserve_exact(len).map_err(|_| DecodeError::OutOfMemory)?;
+    Ok(pixels)
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use std::time::Instant;
+
+    #[test]
+    fn thumbnail() {
+        use super::*;
+
+        let img = Image {
+            width: 400,
+            height: 100,
+            pixels: (0..400 * 100).map(|i| (i % 400) as u16).collect::<Vec<_>>(),
+        };
+
+        let thumb = img.thumbnail(100);
+        assert_eq!((thumb.width, thumb.height), (100, 25));
+        assert_eq!(thumb.pixels[..4], [0, 4, 8, 12]);
+
+        let same = img.thumbnail(1000);
+        assert_eq!((same.width, same.height), (400, 100));
+
+        let empty = Image { width: 0, height: 1000, pixels: Vec::<u16>::new() };
+        let thumb = empty.thumbnail(256);
+        assert_eq!((thumb.width, thumb.height, thumb.pixels.len()), (0, 0, 0));
+    }
+
+    #[test]
+    fn encoded_sizes() {
+        use super::*;
+
+        // the first pixel equals the initial prev pixel, so the whole image is runs
+        for len in [1, 61, 62, 63, 124, 125, 1000] {
+            let data = Encoder::new(len, 1).encode(&vec![Pixel::new(0, 0, 0, 255); len as usize]).unwrap();
+            assert_eq!(data.len(), 14 + (len as usize).div_ceil(62) + 8, "run of {len}");
+        }
+
+        // one byte for a DIFF or INDEX pixel, then the rest of the row as runs
+        let img = [[Pixel::new(1, 1, 1, 255); 70], [Pixel::new(0, 0, 0, 255); 70]].concat();
+        let data = Encoder::new(140, 1).encode(&img).unwrap();
+        assert_eq!(data.len(), 14 + (1 + 2) + (1 + 2) + 8);
+    }
+
+    #[test]
+    fn encode_into() {
+        use super::*;
+
+        // alpha changes on every pixel, so both are RGBA ops, the worst case
+        let img = [Pixel::new(1, 2, 3, 4), Pixel::new(9, 8, 7, 6)];
+        let mut buf = Vec::with_capacity(2 * Encoder::max_encoded_len(2, 1));
+        let storage = buf.as_ptr();
+        Encoder::new(2, 1).encode_into(&img, &mut buf).unwrap();
+        assert_eq!(buf.len(), Encoder::max_encoded_len(2, 1));
+        Encoder::new(2, 1).encode_into(&img, &mut buf).unwrap();
+        assert_eq!(buf.as_ptr(), storage);
+        assert!(buf[..buf.len() / 2] == buf[buf.len() / 2..]);
+    }
+
+    #[test]
+    fn encoder_kinds() {
+        use super::*;
+
+        let img = [Pixel::new(1, 2, 3, 255), Pixel::new(1, 2, 3, 255), Pixel::new(9, 9, 9, 9)];
+        for &kind in EncoderKind::ALL {
+            assert_eq!(EncoderKind::from_name(kind.name()), Some(kind));
+            let mut encoder = kind.build(3, 1);
+            assert_eq!(encoder.name(), kind.name());
+            assert!(!encoder.encode(&img).unwrap().is_empty());
+        }
+
+        // encoders are reusable, every call starts from a fresh state
+        let mut standard = EncoderKind::default().build(3, 1);
+        let first = standard.encode(&img).unwrap();
+        assert_eq!(standard.encode(&img).unwrap(), first);
+        assert_eq!(first, Encoder::new(3, 1).encode(&img).unwrap());
+    }
+
+    #[test]
+    fn indexing() {
+        use super::*;
+
+        let mut img = Image { width: 3, height: 2, pixels: (0..6).collect::<Vec<u8>>() };
+        assert_eq!(img[(2, 0)], 2);
+        assert_eq!(img[(0, 1)], 3);
+        img[(1, 1)] = 40;
+        assert_eq!(img.pixels[4], 40);
+        assert_eq!(img.get(2, 1), Some(&5));
+        assert_eq!(img.get(3, 0), None);
+        assert_eq!(img.get_mut(0, 2), None);
+
+        let grid = Image::from_fn(3, 2, |x, y| (x, y));
+        assert_eq!(grid.pixels, [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]);
+        assert!(Image::from_fn(0, 5, |_, _| 0u8).pixels.is_empty());
+
+        let result = std::panic::catch_unwind(|| img[(3, 1)]);
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(message, "pixel (3, 1) is outside the 3x2 image");
+    }
+
+    #[test]
+    fn formatting() {
+        use super::*;
+
+        let mut img = Image { width: 2, height: 1, pixels: vec![Pixel::new(1, 2, 3, 255); 2] };
+        assert_eq!(img.to_string(), "2x1 RGB image (77096ba2893f9e11)");
+        img.pixels[1].a = 0;
+        let debug = format!("{img:?}");
+        assert!(debug.starts_with("Image { width: 2, height: 1, channels: 4, digest: "));
+        assert!(!debug.contains("77096ba2893f9e11"));
+    }
+
+    #[test]
+    fn enumerate_pixels() {
+        use super::*;
+
+        let mut img = Image::from_fn(3, 2, |x, y| 10 * y + x);
+        assert!(img.enumerate_pixels().all(|(x, y, &value)| value == 10 * y + x));
+        assert_eq!(img.enumerate_pixels().last(), Some((2, 1, &12)));
+
+        for (x, y, value) in img.enumerate_pixels_mut() {
+            if x == y {
+                *value = 0;
+            }
+        }
+        assert_eq!(img.pixels, [0, 1, 2, 10, 0, 12]);
+    }
+
+    #[test]
+    fn rotate_and_flip() {
+        use super::*;
+
+        // 0 1 2
+        // 3 4 5
+        let img = Image::from_fn(3, 2, |x, y| y * 3 + x);
+        let cw = img.rotate_cw();
+        assert_eq!((cw.width, cw.height), (2, 3));
+        assert_eq!(cw.pixels, [3, 0, 4, 1, 5, 2]);
+        assert_eq!(img.rotate_ccw().pixels, [2, 5, 1, 4, 0, 3]);
+        assert_eq!(cw.rotate_ccw().pixels, img.pixels);
+        assert_eq!(cw.rotate_cw().rotate_cw().rotate_cw().pixels, img.pixels);
+        assert_eq!(img.flip_horizontal().pixels, [2, 1, 0, 5, 4, 3]);
+        assert_eq!(img.flip_vertical().pixels, [3, 4, 5, 0, 1, 2]);
+    }
+
+    #[test]
+    fn map_pixels() {
+        use super::*;
+
+        let mut img = Image::from_fn(2, 2, |x, y| Pixel::new(x as u8 * 100, y as u8 * 100, 7, 128));
+        let swapped = img.map_pixels(|Pixel { r, g, b, a }| Pixel::new(b, g, r, a));
+        assert_eq!(swapped[(1, 0)], Pixel::new(7, 0, 100, 128));
+        let alpha = img.map_pixels(|p| p.a);
+        assert_eq!(alpha.pixels, [128; 4]);
+
+        let premultiply = |c: u8, a: u8| (c as u16 * a as u16 / 255) as u8;
+        img.map_pixels_in_place(|Pixel { r, g, b, a }| {
+            Pixel::new(premultiply(r, a), premultiply(g, a), premultiply(b, a), a)
+        });
+        assert_eq!(img[(1, 1)], Pixel::new(50, 50, 3, 128));
+    }
+
+    #[test]
+    fn try_from_bytes() {
+        use super::*;
+
+        let img = [Pixel::new(1, 2, 3, 255), Pixel::new(4, 5, 6, 7)];
+        let data = Encoder::new(1, 2).encode(&img).unwrap();
+        let decoded: Image<Pixel> = data.as_slice().try_into().unwrap();
+        assert_eq!(decoded.pixels, img);
+        assert_eq!(Image::try_from(&data[..20]).err(), Some(DecodeError::UnexpectedEof));
+    }
+
+    #[test]
+    fn hex_colors() {
+        use super::*;
+
+        assert_eq!(Pixel::from_hex("#ff8000"), Ok(Pixel::new(255, 128, 0, 255)));
+        assert_eq!("0a0B0c80".parse(), Ok(Pixel::new(10, 11, 12, 128)));
+        assert_eq!(Pixel::new(255, 128, 0, 255).to_hex(), "#ff8000");
+        assert_eq!(Pixel::new(10, 11, 12, 128).to_hex(), "#0a0b0c80");
+
+        assert_eq!(Pixel::from_hex("#fff"), Err(ParseHexError::InvalidLength));
+        assert_eq!(Pixel::from_hex("#ff80zz"), Err(ParseHexError::InvalidDigit));
+        assert_eq!(Pixel::from_hex("#+f8000"), Err(ParseHexError::InvalidDigit));
+        assert_eq!(Pixel::from_hex("#ff80\u{e9}"), Err(ParseHexError::InvalidDigit));
+    }
+
+    #[test]
+    fn raw_export() {
+        use super::*;
+
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![Pixel::new(1, 2, 3, 4), Pixel::new(5, 6, 7, 8)],
+        };
+        assert_eq!(*image.as_rgba_bytes(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(image.to_u32_pixels(PixelOrder::Argb), [0x04010203, 0x08050607]);
+        assert_eq!(image.to_u32_pixels(PixelOrder::Xrgb), [0x00010203, 0x00050607]);
+        assert_eq!(image.pixels[0].to_u32(PixelOrder::Bgra), 0x03020104);
+        assert_eq!(image.into_rgba_bytes(), [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn force_opaque() {
+        use super::*;
+
+        let img = (0..64u8)
+            .map(|i| Pixel::new(i, i, i, 254 + i % 2))
+            .collect::<Vec<_>>();
+        let plain = Encoder::new(8, 8).encode(&img).unwrap();
+        let mut encoder = Encoder::new(8, 8).force_opaque(true);
+        let opaque = encoder.encode(&img).unwrap();
+        assert_eq!(opaque[12], 3);
+        assert_eq!(encoder.stats().rgba, 0);
+        assert!(opaque.len() < plain.len());
+
+        let decoded = Decoder::new().decode(&opaque).unwrap();
+        assert!(decoded.pixels.iter().zip(&img).all(|(d, p)| *d == Pixel { a: 255, ..*p }));
+    }
+
+    #[test]
+    fn color_key() {
+        use super::*;
+
+        let magenta = Pixel::new(255, 0, 255, 255);
+        let img = [Pixel::new(1, 2, 3, 255), magenta, Pixel::new(255, 0, 255, 128)];
+        let data = Encoder::new(3, 1).color_key(Some(magenta)).encode(&img).unwrap();
+
+        let decoded = Decoder::new().decode(&data).unwrap();
+        assert!(decoded.pixels[0] == img[0]);
+        assert!(decoded.pixels[1..].iter().all(|p| p.a == 0));
+
+        let decoded = Decoder::new().color_key(Some(magenta)).decode(&data).unwrap();
+        assert!(decoded.pixels.eq(&[img[0], magenta, magenta]));
+    }
+
+    #[test]
+    fn near_lossless() {
+        use super::*;
+
+        let mut seed = 7u32;
+        let img = (0..64 * 64)
+            .map(|i| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                let noise = (seed >> 16) as u8 % 5;
+                let value = (i % 64) as u8 * 3 + noise;
+                Pixel::new(value, value / 2 + noise, 200 - noise, 255 - (i / 64 % 2) as u8)
+            })
+            .collect::<Vec<_>>();
+        let lossless = Encoder::new(64, 64).encode(&img).unwrap();
+        assert_eq!(Encoder::new(64, 64).near_lossless(0).encode(&img).unwrap(), lossless);
+
+        let mut encoder = Encoder::new(64, 64).near_lossless(2);
+        let data = encoder.encode(&img).unwrap();
+        assert!(data.len() < lossless.len());
+        let stats = *encoder.stats();
+        assert!(stats.changed_pixels > 0 && stats.max_error <= 2);
+        assert!(stats.mean_squared_error() > 0.0 && stats.mean_squared_error() <= 4.0);
+
+        let decoded = Decoder::new().decode(&data).unwrap();
+        let mut max_error = 0;
+        for (d, p) in decoded.pixels.iter().zip(&img) {
+            assert_eq!(d.a, p.a);
+            max_error = max_error.max(d.r.abs_diff(p.r).max(d.g.abs_diff(p.g)).max(d.b.abs_diff(p.b)));
+        }
+        assert_eq!(max_error, stats.max_error);
+    }
+
+    #[test]
+    fn cancellation() {
+        use super::*;
+        use common::CANCEL_INTERVAL;
+        use std::sync::atomic::Ordering;
+
+        let img = (0..512 * 512).map(|i| Pixel::new(i as u8, (i >> 8) as u8, 7, 255)).collect::<Vec<_>>();
+        let flag = Arc::new(AtomicBool::new(false));
+        let data = Encoder::new(512, 512).cancel_on(flag.clone()).encode(&img).unwrap();
+        let decoded = Decoder::new().cancel_on(flag.clone()).decode(&data).unwrap();
+        assert!(decoded.pixels == img);
+
+        flag.store(true, Ordering::Relaxed);
+        let mut buf = vec![1, 2, 3];
+        let result = Encoder::new(512, 512).cancel_on(flag.clone()).encode_into(&img, &mut buf);
+        assert_eq!(result, Err(EncodeError::Cancelled));
+        assert_eq!(buf, [1, 2, 3]);
+        let mut decoder = Decoder::new().cancel_on(flag.clone());
+        assert_eq!(decoder.decode(&data).err(), Some(DecodeError::Cancelled));
+        assert_eq!(decoder.error_offset(), Some(14));
+
+        // set part way through, decoding stops within the next check interval
+        flag.store(false, Ordering::Relaxed);
+        let mut last = 0;
+        let setter = flag.clone();
+        let mut decoder = Decoder::new().cancel_on(flag.clone()).with_observer(|_, _, index| {
+            if index == 100_000 {
+                setter.store(true, Ordering::Relaxed);
+            }
+            last = index;
+        });
+        assert_eq!(decoder.decode(&data).err(), Some(DecodeError::Cancelled));
+        assert!(last < 100_000 + CANCEL_INTERVAL);
+    }
+
+    #[test]
+    fn progress() {
+        use super::*;
+        use std::sync::Mutex;
+
+        let img = (0..100 * 30).map(|i| Pixel::new(i as u8, 0, 0, 255)).collect::<Vec<_>>();
+        let calls = Arc::new(Mutex::new(vec![]));
+        let record = |calls: &Arc<Mutex<Vec<(usize, usize)>>>| {
+            let calls = calls.clone();
+            move |done, total| calls.lock().unwrap().push((done, total))
+        };
+        let data = Encoder::new(100, 30).on_progress(1000, record(&calls)).encode(&img).unwrap();
+        assert_eq!(*calls.lock().unwrap(), [(0, 3000), (1000, 3000), (2000, 3000), (3000, 3000)]);
+
+        calls.lock().unwrap().clear();
+        let mut decoder = Decoder::new().on_progress(1000, record(&calls));
+        assert!(decoder.decode(&data).unwrap().pixels == img);
+        assert_eq!(*calls.lock().unwrap(), [(0, 3000), (1000, 3000), (2000, 3000), (3000, 3000)]);
+    }
+
+    #[test]
+    fn trailing_data() {
+        use super::*;
+
+        let img = [Pixel::new(1, 2, 3, 255), Pixel::new(4, 5, 6, 255)];
+        let mut data = Encoder::new(2, 1).encode(&img).unwrap();
+        data.extend([0; 5]);
+        assert_eq!(Decoder::new().decode(&data).err(), Some(DecodeError::InvalidEndMarker));
+
+        let mut decoder = Decoder::new().lenient(true);
+        assert!(decoder.decode(&data).unwrap().pixels.eq(&img));
+        assert_eq!(decoder.trailing_len(), 5);
+
+        // the marker itself still has to follow the ops
+        let len = data.len();
+        data[len - 6] = 2;
+        assert!(decoder.decode(&data).is_err());
+    }
+
+    #[test]
+    fn header_values() {
+        use super::*;
+
+        let mut data = Encoder::new(1, 1).colorspace(1).encode(&[Pixel::new(9, 9, 9, 255)]).unwrap();
+        let mut decoder = Decoder::new();
+        decoder.decode(&data).unwrap();
+        let header = decoder.header().unwrap();
+        assert_eq!((header.width, header.height, header.channels, header.colorspace), (1, 1, 4, 1));
+
+        data[12] = 17;
+        assert_eq!(decoder.decode(&data).err(), Some(DecodeError::InvalidChannels));
+        assert_eq!(decoder.header(), None);
+        data[12] = 3;
+        data[13] = 2;
+        assert_eq!(decoder.decode(&data).err(), Some(DecodeError::InvalidColorspace));
+
+        let mut decoder = Decoder::new().lenient(true);
+        assert!(decoder.decode(&data).is_ok());
+        assert_eq!(decoder.header().map(|header| header.colorspace), Some(2));
+
+        let header = Header { width: 1, height: 1, channels: 3, colorspace: 1 };
+        let data = Encoder::with_header(header).encode(&[Pixel::new(9, 9, 9, 255)]).unwrap();
+        let mut decoder: Decoder = Decoder::default();
+        decoder.decode(&data).unwrap();
+        assert_eq!(decoder.header(), Some(header));
+    }
+
+    #[test]
+    fn limits() {
+        use super::*;
+
+        let data = Encoder::new(20, 10).encode(&[Pixel::new(0, 0, 0, 255); 200]).unwrap();
+        assert!(Decoder::new().decode(&data).is_ok());
+
+        let limits = Limits { max_width: 19, ..Limits::default() };
+        assert!(Decoder::new().with_limits(limits).decode(&data).is_err());
+        let limits = Limits { max_pixels: 199, ..Limits::default() };
+        assert!(Decoder::new().with_limits(limits).decode_subsampled(&data, 2).is_err());
+
+        // the 200 black pixels take four ops: three full runs and one of 14
+        let budget = Budget { max_ops: 4, ..Budget::default() };
+        assert!(Decoder::new().with_budget(budget).decode(&data).is_ok());
+        let budget = Budget { max_ops: 3, ..Budget::default() };
+        assert_eq!(Decoder::new().with_budget(budget).decode(&data).err(), Some(DecodeError::BudgetExceeded));
+        let budget = Budget { max_output_bytes: 799, ..Budget::default() };
+        assert_eq!(Decoder::new().with_budget(budget).decode(&data).err(), Some(DecodeError::BudgetExceeded));
+        // a region only has to decode up to its last row
+        let region = Decoder::new().with_budget(budget).decode_region(&data, 0, 0, 20, 9);
+        assert!(region.is_ok());
+    }
+
+    #[test]
+    fn encoder_validation() {
+        use super::*;
+
+        assert_eq!(Encoder::new(0, 0).encode(&[]), Err(EncodeError::ZeroDimensions));
+        assert_eq!(Encoder::new(0, 5).encode(&[]), Err(EncodeError::ZeroDimensions));
+        let img = [Pixel::new(0, 0, 0, 255); 6];
+        assert_eq!(Encoder::new(2, 2).encode(&img), Err(EncodeError::PixelCountMismatch));
+        assert_eq!(Encoder::new(u32::MAX, u32::MAX).encode(&img), Err(EncodeError::LimitsExceeded));
+        let limits = Limits { max_pixels: 5, ..Limits::default() };
+        assert_eq!(Encoder::new(3, 2).with_limits(limits).encode(&img), Err(EncodeError::LimitsExceeded));
+        assert!(Encoder::new(3, 2).encode(&img).is_ok());
+    }
+
+    #[test]
+    fn frame_decoder() {
+        use super::*;
+
+        let small = Encoder::new(2, 2).encode(&[Pixel::new(1, 2, 3, 255); 4]).unwrap();
+        let large = Encoder::new(4, 4).encode(&[Pixel::new(4, 5, 6, 7); 16]).unwrap();
+
+        let mut frames = FrameDecoder::new();
+        assert_eq!(frames.decode_next(&large).unwrap().pixels, [Pixel::new(4, 5, 6, 7); 16]);
+        let storage = frames.decode_next(&large).unwrap().pixels.as_ptr();
+        let frame = frames.decode_next(&small).unwrap();
+        assert_eq!((frame.width, frame.height), (2, 2));
+        assert_eq!(frame.pixels, [Pixel::new(1, 2, 3, 255); 4]);
+        assert_eq!(frame.pixels.as_ptr(), storage);
+
+        // a failed frame leaves an empty image behind, not a mix of two frames
+        let mut image = Decoder::new().decode(&large).unwrap();
+        assert!(Decoder::new().decode_into(&small[..small.len() - 1], &mut image).is_err());
+        assert_eq!((image.width, image.height, image.pixels.len()), (0, 0, 0));
+    }
+
+    #[test]
+    fn partial_decode() {
+        use super::*;
+
+        let img = image::ImageReader::open("assets/suz.png").unwrap().decode().unwrap();
+        let img_buf = img.as_rgba8().unwrap()
+            .pixels()
+            .map(|&Rgba::<u8>([r, g, b, a])| Pixel::new(r, g, b, a))
+            .collect::<Vec<_>>();
+        let data = Encoder::new(img.width(), img.height()).encode(&img_buf).unwrap();
+
+        let mut decoder = Decoder::new();
+        let full = decoder.decode(&data).unwrap();
+        let (x, y, w, h) = (17, 30, 100, 41);
+        let region = decoder.decode_region(&data, x, y, w, h).unwrap();
+        assert_eq!((region.width, region.height), (w, h));
+        for row in 0..h {
+            let start = (y + row) * full.width + x;
+            assert!(region.pixels[row * w..(row + 1) * w].eq(&full.pixels[start..start + w]));
+        }
+
+        assert!(decoder.decode_region(&data, full.width - 1, 0, 2, 1).is_err());
+
+        let preview = decoder.decode_subsampled(&data, 3).unwrap();
+        assert_eq!((preview.width, preview.height), (full.width.div_ceil(3), full.height.div_ceil(3)));
+        assert!(preview.pixels[preview.width + 2] == full.pixels[3 * full.width + 6]);
+    }
+
+    #[test]
+    fn exhaustive_small_images() {
+        use super::*;
+
+        // offsets from black that sit on either side of the DIFF and LUMA ranges
+        let offsets = [(0, 0, 0), (-2, -2, -2), (1, 1, 1), (-3, 0, 0), (2, 0, 0), (-32, -32, -32),
+                       (31, 31, 31), (-33, -33, -33), (32, 32, 32), (-24, -32, -40), (-25, -32, -39),
+                       (23, 31, 38), (24, 31, 39)];
+        let mut palette = offsets
+            .iter()
+            .map(|&(r, g, b): &(i32, i32, i32)| Pixel::new(r as u8, g as u8, b as u8, 255))
+            .collect::<Vec<_>>();
+        // alpha changes, the zeroed pixel and a pixel sharing black's cache slot
+        palette.extend([Pixel::new(0, 0, 0, 0), Pixel::new(0, 0, 0, 128), Pixel::new(1, 1, 1, 254),
+                        Pixel::new(255, 255, 255, 255), Pixel::new(64, 0, 0, 255)]);
+        assert_eq!(palette[0].hash(), palette[palette.len() - 1].hash());
+
+        let round_trip = |img: &[Pixel]| {
+            let data = Encoder::new(img.len() as u32, 1).encode(img).unwrap();
+            let decoded = Decoder::new().decode(&data).unwrap();
+            assert!(decoded.pixels.eq(img), "{:?}", img.iter().map(Pixel::to_bytes).collect::<Vec<_>>());
+        };
+
+        let n = palette.len();
+        for len in 1..=4u32 {
+            for mut code in 0..n.pow(len) {
+                let img = (0..len)
+                    .map(|_| {
+                        let pixel = palette[code % n];
+                        code /= n;
+                        pixel
+                    })
+                    .collect::<Vec<_>>();
+                round_trip(&img);
+            }
+        }
+
+        // runs ending on and around the 62 pixel limit of a single RUN op
+        for len in [61, 62, 63, 64, 123, 124, 125, 186] {
+            for &last in &palette {
+                let mut img = vec![palette[5]; len];
+                img.push(last);
+                round_trip(&img);
+                img.insert(0, last);
+                round_trip(&img);
+            }
+        }
+    }
+
+    // Raw RGBA inputs and their encodings, which match qoi.h byte for byte. Any
+    // change to the output has to be deliberate, since callers cache by content hash.
+    #[test]
+    fn test_vectors() {
+        use super::*;
+
+        macro_rules! vector {
+            ($name:literal, $width:literal, $height:literal) => {
+                (
+                    $name,
+                    $width,
+                    $height,
+                    &include_bytes!(concat!("../assets/vectors/", $name, ".rgba"))[..],
+                    &include_bytes!(concat!("../assets/vectors/", $name, ".qoi"))[..],
+                )
+            };
+        }
+        let vectors = [
+            vector!("gradient", 16, 16),
+            vector!("alpha", 8, 8),
+            vector!("runs", 192, 2),
+            vector!("noise", 12, 12),
+            vector!("palette", 32, 4),
+            vector!("smooth", 20, 10),
+        ];
+
+        for (name, width, height, rgba, expected) in vectors {
+            let pixels = rgba.chunks_exact(4).map(|p| Pixel::new(p[0], p[1], p[2], p[3])).collect::<Vec<_>>();
+            let data = Encoder::new(width, height).encode(&pixels).unwrap();
+            assert!(data == expected, "{name}: encoding changed");
+            let decoded = Decoder::new().decode(expected).unwrap();
+            assert!(decoded.into_rgba_bytes() == rgba, "{name}: decoding changed");
+        }
+    }
+
+    #[test]
+    fn reference_implementation() {
+        use super::*;
+
+        let img = image::ImageReader::open("assets/suz.png").unwrap().decode().unwrap();
+        let (width, height) = (img.width(), img.height());
+        let rgba = img.to_rgba8().into_raw();
+        let pixels = rgba.chunks_exact(4).map(|p| Pixel::new(p[0], p[1], p[2], p[3])).collect::<Vec<_>>();
+
+        let desc = qoi_sys::Desc { width, height, channels: 4, colorspace: 0 };
+        let data = Encoder::new(width, height).encode(&pixels).unwrap();
+        assert!(data == qoi_sys::encode(&rgba, desc).unwrap());
+        assert!(qoi_sys::decode(&data, 4).unwrap().1 == rgba);
+
+        let rgb = img.to_rgb8().into_raw();
+        let desc = qoi_sys::Desc { channels: 3, ..desc };
+        let data = Encoder::new(width, height).force_opaque(true).encode(&pixels).unwrap();
+        assert!(data == qoi_sys::encode(&rgb, desc).unwrap());
+        let mut out = vec![0; rgb.len()];
+        assert!(Decoder::new().decode_to_rgb(&data, &mut out).is_ok());
+        assert!(out == rgb);
+        let data = Encoder::new(width, height).encode(&pixels).unwrap();
+        assert!(Decoder::new().decode_to_rgb(&data, &mut out).is_ok());
+        assert!(out == qoi_sys::decode(&data, 3).unwrap().1);
+
+        // qoi.h caches the pixel of a leading run, which INDEX ops can then refer to
+        let mut data = vec![];
+        Header { width: 2, height: 1, channels: 4, colorspace: 0 }.append_bytes(b"qoif", &mut data);
+        data.extend([0b11000000, 53]);
+        data.extend(END_MARKER);
+        let decoded = Decoder::new().decode(&data).unwrap();
+        assert!(decoded.into_rgba_bytes() == qoi_sys::decode(&data, 4).unwrap().1);
+
+        // arbitrary streams: whatever this decoder accepts, qoi.h has to decode the same way
+        let mut state = 0x9e37_79b9_u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        for _ in 0..20_000 {
+            let mut data = vec![];
+            Header { width: 4, height: 4, channels: 4, colorspace: 0 }.append_bytes(b"qoif", &mut data);
+            data.extend((0..next() % 24).map(|_| next() as u8));
+            data.extend(END_MARKER);
+            if let Ok(decoded) = Decoder::new().decode(&data) {
+                let (_, reference) = qoi_sys::decode(&data, 4).unwrap();
+                assert!(decoded.into_rgba_bytes() == reference, "{data:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn salvage_damaged() {
+        use super::*;
+
+        let pixels = (0..64u32).map(|i| Pixel::new(i as u8 * 4, 0, 255 - i as u8, 255)).collect::<Vec<_>>();
+        let valid = Encoder::new(8, 8).encode(&pixels).unwrap();
+
+        let mut decoder = Decoder::new();
+        let partial = decoder.decode_partial(&valid).unwrap();
+        assert!(partial.image.pixels == pixels);
+        assert_eq!((partial.decoded, partial.error, decoder.error_offset()), (64, None, None));
+
+        // a DIFF for the first pixel of this gradient, then a 2-byte LUMA for every other
+        let truncated = &valid[..14 + 1 + 2 * 20 + 1];
+        assert_eq!(decoder.decode(truncated).err(), Some(DecodeError::UnexpectedEof));
+        assert_eq!(decoder.error_offset(), Some(truncated.len() - 1));
+        let partial = decoder.decode_partial(truncated).unwrap();
+        assert_eq!((partial.decoded, partial.error), (21, Some(DecodeError::UnexpectedEof)));
+        assert_eq!(decoder.error_offset(), Some(truncated.len() - 1));
+        assert_eq!(partial.image.pixels[..21], pixels[..21]);
+        assert!(partial.image.pixels[21..].iter().all(|&pixel| pixel == pixels[20]));
+        assert_eq!(partial.offset, Some(truncated.len() - 1));
+        let magenta = Pixel::new(255, 0, 255, 255);
+        let partial = Decoder::new().pad_with(Some(magenta)).decode_partial(truncated).unwrap();
+        assert_eq!(partial.image.pixels[..21], pixels[..21]);
+        assert!(partial.image.pixels[21..].iter().all(|&pixel| pixel == magenta));
+
+        // a missing end marker, and garbage after it
+        let partial = decoder.decode_partial(&valid[..valid.len() - 8]).unwrap();
+        assert_eq!((partial.decoded, partial.error), (64, Some(DecodeError::InvalidEndMarker)));
+        assert_eq!(decoder.error_offset(), Some(valid.len() - 8));
+        let mut padded = valid.clone();
+        padded.extend(b"junk");
+        assert_eq!(decoder.decode(&padded).err(), Some(DecodeError::InvalidEndMarker));
+        assert_eq!(decoder.error_offset(), Some(valid.len() - 8));
+        let partial = decoder.decode_partial(&padded).unwrap();
+        assert_eq!((partial.decoded, partial.error, decoder.trailing_len()), (64, None, 4));
+
+        assert_eq!(decoder.decode_partial(&valid[..10]).err(), Some(DecodeError::InvalidHeader));
+
+        // a tiny file claiming a huge image is not padded out to its size
+        let mut huge = valid[..14].to_vec();
+        huge[4..12].copy_from_slice(&[0, 0, 0x27, 0x10, 0, 0, 0x27, 0x10]);
+        huge.extend([0; 8]);
+        assert_eq!(decoder.decode_partial(&huge).err(), Some(DecodeError::UnexpectedEof));
+        let budget = Budget { max_output_bytes: 255, ..Budget::default() };
+        let partial = Decoder::new().with_budget(budget).decode_partial(truncated);
+        assert_eq!(partial.err(), Some(DecodeError::BudgetExceeded));
+    }
+
+    #[test]
+    fn malformed_input() {
+        use super::*;
+
+        let pixels = (0..64u32)
+            .map(|i| Pixel::new((i * 7) as u8, (i * 13) as u8, (i / 3) as u8, if i % 5 == 0 { 128 } else { 255 }))
+            .collect::<Vec<_>>();
+        let valid = Encoder::new(8, 8).encode(&pixels).unwrap();
+
+        // xorshift, so every run exercises the same inputs
+        let mut state = 0x2545_f491_u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        let mut out = vec![0; 8 * 8 * 4];
+        for round in 0..2000 {
+            let mut data = valid.clone();
+            match round % 3 {
+                0 => data.truncate(next() as usize % valid.len()),
+                1 => {
+                    for _ in 0..1 + next() % 4 {
+                        let i = next() as usize % data.len();
+                        data[i] = next() as u8;
+                    }
+                }
+                _ => data = (0..next() % 64).map(|_| next() as u8).collect(),
+            }
+
+            let limits = Limits { max_pixels: 1 << 20, ..Limits::default() };
+            let mut decoder = Decoder::new().with_limits(limits);
+            let _ = decoder.decode(&data);
+            let _ = decoder.decode_region(&data, 2, 3, 4, 5);
+            let _ = decoder.decode_subsampled(&data, 3);
+            let _ = decoder.decode_to_rgba(&data, &mut out);
+            let _ = decoder.decode_to_rgb(&data, &mut out);
+            if let Ok(partial) = decoder.decode_partial(&data) {
+                let image = &partial.image;
+                assert_eq!(image.pixels.len(), image.width * image.height);
+                assert_eq!(partial.error.is_some(), decoder.error_offset().is_some());
+            }
+        }
+
+        assert_eq!(Decoder::new().decode(&valid[..20]).err(), Some(DecodeError::UnexpectedEof));
+        assert_eq!(Decoder::new().decode(&valid[..10]).err(), Some(DecodeError::InvalidHeader));
+        assert_eq!(Decoder::new().decode_to_rgba(&valid, &mut out[1..]), Err(DecodeError::BufferTooSmall));
+        let (width, height) = Decoder::dimensions(&valid).unwrap();
+        let out = &mut out[..width * height * 3 - 1];
+        assert_eq!(Decoder::new().decode_to_rgb(&valid, out), Err(DecodeError::BufferTooSmall));
+
+        // 62 identical pixels encode as a single run, which overshoots a 61 pixel image
+        let mut overshoot = Encoder::new(62, 1).encode(&[Pixel::new(0, 0, 0, 255); 62]).unwrap();
+        overshoot[4..8].copy_from_slice(&61u32.to_be_bytes());
+        assert_eq!(Decoder::new().decode(&overshoot).err(), Some(DecodeError::RunOvershoot));
+
+        // 100M declared pixels behind a few bytes of ops fail before allocating
+        let mut oversized = vec![];
+        Header { width: 10_000, height: 10_000, channels: 4, colorspace: 0 }.append_bytes(b"qoif", &mut oversized);
+        oversized.extend(END_MARKER);
+        assert_eq!(Decoder::new().decode(&oversized).err(), Some(DecodeError::UnexpectedEof));
+        oversized[4..12].copy_from_slice(&[0xff; 8]);
+        assert_eq!(
+            Decoder::new().with_limits(Limits::unlimited()).decode(&oversized).err(),
+            Some(DecodeError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn rgb_images() {
+        use super::*;
+
+        assert_eq!(std::mem::size_of::<Pixel<3>>(), 3);
+        let rgb = Image::from_fn(9, 4, |x, y| Pixel::rgb(x as u8 * 20, y as u8, 3));
+        let data = Encoder::new(9, 4).encode_as(&rgb.pixels).unwrap();
+        let rgba = rgb.pixels.iter().map(|&pixel| pixel.to_rgba()).collect::<Vec<_>>();
+        let mut expected = Encoder::new(9, 4).encode(&rgba).unwrap();
+        expected[12] = 3;
+        assert_eq!(data, expected);
+
+        let mut decoder = Decoder::new();
+        let decoded = decoder.decode_as::<3>(&data).unwrap();
+        assert_eq!(decoder.header().unwrap().channels, 3);
+        assert_eq!(decoded.pixels, rgb.pixels);
+        assert!(decoder.decode_as::<4>(&data).unwrap().pixels == rgba);
+    }
 
     #[test]
     fn test() {
@@ -349,7 +2164,7 @@ mod tests {
             .collect::<Vec<_>>();
 
         let now = Instant::now();
-        let data = encoder.encode(&img_buf);
+        let data = encoder.encode(&img_buf).unwrap();
         std::fs::write("encoded.qoi", &data).unwrap();
         println!("QOI encoder took {} us", now.elapsed().as_micros());
 
@@ -0,0 +1,118 @@
+//! Color and coverage kept apart: an RGBA image split into its RGB and an
+//! alpha mask, each of which can be stored as a QOI file of its own.
+//!
+//! QOI has no single-channel mode, so a mask is stored as an opaque grey image
+//! with the coverage in all three channels. Runs and luma ops keep that about
+//! as small as one channel would be.
+
+use crate::{DecodeError, Decoder, EncodeError, Encoder, Header, Image, Pixel};
+
+impl Image<Pixel> {
+    // The color of every pixel, and its alpha in row-major order.
+    pub fn split_alpha(&self) -> (Image<Pixel<3>>, Vec<u8>) {
+        let color = self.map_pixels(Pixel::<3>::from_rgba);
+        let mask = self.pixels.iter().map(|pixel| pixel.a).collect();
+        (color, mask)
+    }
+
+    // The inverse of `split_alpha`, or None unless there is one mask value per
+    // pixel.
+    pub fn merge_alpha(color: &Image<Pixel<3>>, mask: &[u8]) -> Option<Image<Pixel>> {
+        if mask.len() != color.pixels.len() {
+            return None;
+        }
+        let pixels = color
+            .pixels
+            .iter()
+            .zip(mask)
+            .map(|(&Pixel { r, g, b, .. }, &a)| Pixel::new(r, g, b, a))
+            .collect();
+        Some(Image {
+            width: color.width,
+            height: color.height,
+            pixels,
+        })
+    }
+}
+
+// Encodes `image` as a 3-channel QOI file of its color and a grey one of its
+// alpha.
+pub fn encode_split(image: &Image<Pixel>) -> Result<(Vec<u8>, Vec<u8>), EncodeError> {
+    let (width, height) = (image.width as u32, image.height as u32);
+    let (_, mask) = image.split_alpha();
+    let color = Encoder::with_header(Header {
+        width,
+        height,
+        channels: 3,
+        colorspace: 0,
+    })
+    .force_opaque(true)
+    .encode(&image.pixels)?;
+    Ok((color, encode_mask(&mask, width, height)?))
+}
+
+pub fn encode_mask(mask: &[u8], width: u32, height: u32) -> Result<Vec<u8>, EncodeError> {
+    let pixels = mask
+        .iter()
+        .map(|&value| Pixel::new(value, value, value, 255))
+        .collect::<Vec<_>>();
+    Encoder::with_header(Header {
+        width,
+        height,
+        channels: 3,
+        colorspace: 0,
+    })
+    .encode(&pixels)
+}
+
+// Reads a mask written by `encode_mask`; of other images only red is kept.
+pub fn decode_mask(data: &[u8]) -> Result<Image<u8>, DecodeError> {
+    let image = Decoder::new().decode(data)?;
+    Ok(image.map_pixels(|pixel| pixel.r))
+}
+
+// Decodes the two files written by `encode_split` back into one image.
+pub fn decode_split(color: &[u8], mask: &[u8]) -> Result<Image<Pixel>, DecodeError> {
+    let color = Decoder::new().decode(color)?;
+    let mask = decode_mask(mask)?;
+    if (color.width, color.height) != (mask.width, mask.height) {
+        return Err(DecodeError::InvalidArgument);
+    }
+    let (color, _) = color.split_alpha();
+    Ok(Image::merge_alpha(&color, &mask.pixels).expect("same dimensions"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_merge() {
+        let image = Image::from_fn(5, 3, |x, y| {
+            Pixel::new(x as u8 * 50, y as u8 * 80, 30, (x * y * 20) as u8)
+        });
+        let (color, mask) = image.split_alpha();
+        let Pixel { r, g, b, .. } = color[(4, 2)];
+        assert_eq!((r, g, b), (200, 160, 30));
+        assert_eq!(mask[2 * 5 + 4], 160);
+        let merged = Image::merge_alpha(&color, &mask).unwrap();
+        assert_eq!(merged.pixels, image.pixels);
+        assert!(Image::merge_alpha(&color, &mask[1..]).is_none());
+
+        let (color, mask) = encode_split(&image).unwrap();
+        let channels = |data: &[u8]| {
+            let mut decoder = Decoder::new();
+            decoder.decode(data).unwrap();
+            decoder.header().unwrap().channels
+        };
+        assert_eq!((channels(&color), channels(&mask)), (3, 3));
+        assert_eq!(decode_split(&color, &mask).unwrap().pixels, image.pixels);
+
+        let small = encode_mask(&[0, 255], 2, 1).unwrap();
+        assert_eq!(decode_mask(&small).unwrap().pixels, [0, 255]);
+        assert_eq!(
+            decode_split(&color, &small).err(),
+            Some(DecodeError::InvalidArgument)
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! The op stream of a QOI file, read one op at a time.
+//!
+//! For tools that look inside files, e.g. to find where two encoders made
+//! different choices for the same image. Use `Decoder` to get at the pixels.
+
+use crate::{
+    CachePolicy, DecodeError, END_MARKER, Header, Image, Limits, Pixel, QoiOp, SpecCache,
+    alloc_pixels,
+};
+
+// An op and its operands, with the biases removed from the differences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Rgb { r: u8, g: u8, b: u8 },
+    Rgba { r: u8, g: u8, b: u8, a: u8 },
+    Index { index: u8 },
+    Diff { dr: i8, dg: i8, db: i8 },
+    Luma { dg: i8, dr_dg: i8, db_dg: i8 },
+    Run { len: u8 },
+}
+
+impl Op {
+    // the tag name used by the spec
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Rgb { .. } => "RGB",
+            Op::Rgba { .. } => "RGBA",
+            Op::Index { .. } => "INDEX",
+            Op::Diff { .. } => "DIFF",
+            Op::Luma { .. } => "LUMA",
+            Op::Run { .. } => "RUN",
+        }
+    }
+
+    // The color of the op's pixels in a `heatmap`: runs green, indexes blue,
+    // DIFF yellow, LUMA orange, and the full colors red.
+    pub fn heat_color(&self) -> Pixel {
+        match self {
+            Op::Run { .. } => Pixel::new(0, 200, 0, 255),
+            Op::Index { .. } => Pixel::new(0, 100, 255, 255),
+            Op::Diff { .. } => Pixel::new(255, 230, 0, 255),
+            Op::Luma { .. } => Pixel::new(255, 140, 0, 255),
+            Op::Rgb { .. } | Op::Rgba { .. } => Pixel::new(220, 0, 0, 255),
+        }
+    }
+
+    // how many pixels the op produces
+    pub fn pixels(&self) -> usize {
+        match *self {
+            Op::Run { len } => len as usize,
+            _ => 1,
+        }
+    }
+
+    pub(crate) fn from_qoi(op: QoiOp) -> Self {
+        let unbias = |value: u8, bias: i8| value as i8 - bias;
+        match op {
+            QoiOp::RGB { r, g, b } => Op::Rgb { r, g, b },
+            QoiOp::RGBA { r, g, b, a } => Op::Rgba { r, g, b, a },
+            QoiOp::Index { idx } => Op::Index { index: idx },
+            QoiOp::Diff { dr, dg, db } => Op::Diff {
+                dr: unbias(dr, 2),
+                dg: unbias(dg, 2),
+                db: unbias(db, 2),
+            },
+            QoiOp::Luma { dg, dr_dg, db_dg } => Op::Luma {
+                dg: unbias(dg, 32),
+                dr_dg: unbias(dr_dg, 8),
+                db_dg: unbias(db_dg, 8),
+            },
+            QoiOp::Run { len } => Op::Run { len },
+        }
+    }
+}
+
+// Sees every op a `Decoder` reads, given with `Decoder::with_observer`, with
+// its byte offset and the index of its first pixel. Closures taking those three
+// are observers; `()` is the default and is compiled out of the decode loop.
+pub trait Observer {
+    const ENABLED: bool = true;
+
+    fn op(&mut self, offset: usize, op: Op, index: usize);
+}
+
+impl Observer for () {
+    const ENABLED: bool = false;
+
+    fn op(&mut self, _: usize, _: Op, _: usize) {}
+}
+
+impl<F: FnMut(usize, Op, usize)> Observer for F {
+    fn op(&mut self, offset: usize, op: Op, index: usize) {
+        self(offset, op, index)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpInfo {
+    // byte offset of the op in the file
+    pub offset: usize,
+    // index of the first pixel the op produces, in row-major order
+    pub index: usize,
+    pub op: Op,
+    pub pixel: Pixel,
+}
+
+// Iterates over the ops of a file until they cover every pixel. An op that is
+// cut off or runs past the last pixel is returned as an error, which ends the
+// iteration.
+pub struct Ops<'a> {
+    header: Header,
+    data: &'a [u8],
+    offset: usize,
+    index: usize,
+    total: usize,
+    cache: SpecCache,
+    prev: Pixel,
+    failed: bool,
+}
+
+impl<'a> Ops<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, DecodeError> {
+        let (header, rest) = Header::from_bytes(b"qoif", data).ok_or(DecodeError::InvalidHeader)?;
+        let total = (header.width as usize)
+            .checked_mul(header.height as usize)
+            .ok_or(DecodeError::LimitsExceeded)?;
+        Ok(Self {
+            header,
+            data,
+            offset: data.len() - rest.len(),
+            index: 0,
+            total,
+            cache: SpecCache::default(),
+            prev: Pixel::new(0, 0, 0, 255),
+            failed: false,
+        })
+    }
+
+    pub fn header(&self) -> Header {
+        self.header
+    }
+
+    // The offset of the next op, or where the end marker should be once every
+    // pixel has been read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    // the index of the first pixel of the next op
+    pub(crate) fn next_index(&self) -> usize {
+        self.index
+    }
+
+    // the previous pixel and the cache before the next op
+    pub(crate) fn state(&self) -> (Pixel, [Pixel; 64]) {
+        (self.prev, self.cache.slots())
+    }
+
+    // Once every op has been read, checks for the end marker and returns the
+    // number of bytes after it.
+    pub fn end_marker(&self) -> Result<usize, DecodeError> {
+        if self.index < self.total {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        match self.data[self.offset..].strip_prefix(&END_MARKER) {
+            Some(rest) => Ok(rest.len()),
+            None => Err(DecodeError::InvalidEndMarker),
+        }
+    }
+}
+
+impl Iterator for Ops<'_> {
+    type Item = Result<OpInfo, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.index >= self.total {
+            return None;
+        }
+        let Some((op, rest)) = QoiOp::from_bytes(&self.data[self.offset..]) else {
+            self.failed = true;
+            return Some(Err(DecodeError::UnexpectedEof));
+        };
+        let op_info = Op::from_qoi(op);
+        if self.index + op_info.pixels() > self.total {
+            self.failed = true;
+            return Some(Err(DecodeError::RunOvershoot));
+        }
+
+        let pixel = op.apply(&self.cache, self.prev);
+        match op {
+            QoiOp::Run { .. } => self.cache.insert_run(pixel),
+            _ => self.cache.insert(pixel),
+        }
+        self.prev = pixel;
+        let info = OpInfo {
+            offset: self.offset,
+            index: self.index,
+            op: op_info,
+            pixel,
+        };
+        self.offset = self.data.len() - rest.len();
+        self.index += op_info.pixels();
+        Some(Ok(info))
+    }
+}
+
+// An image the size of the file's, with each pixel colored by the op that
+// produced it, to see which parts of an image are expensive to store.
+pub fn heatmap(data: &[u8]) -> Result<Image<Pixel>, DecodeError> {
+    let mut ops = Ops::new(data)?;
+    let header = ops.header();
+    if !Limits::default().allows(header.width, header.height) {
+        return Err(DecodeError::LimitsExceeded);
+    }
+    let mut pixels = alloc_pixels(ops.total)?;
+    for info in ops.by_ref() {
+        let op = info?.op;
+        pixels.extend(std::iter::repeat_n(op.heat_color(), op.pixels()));
+    }
+    ops.end_marker()?;
+    Ok(Image {
+        width: header.width as usize,
+        height: header.height as usize,
+        pixels,
+    })
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use super::*;
+    use crate::{Decoder, Encoder};
+
+    #[test]
+    fn disassemble() {
+        let pixels = [
+            Pixel::new(0, 0, 0, 255),
+            Pixel::new(0, 0, 0, 255),
+            Pixel::new(1, 255, 0, 255),
+            Pixel::new(11, 5, 8, 255),
+            Pixel::new(0, 0, 0, 255),
+            Pixel::new(200, 100, 50, 25),
+        ];
+        let data = Encoder::new(3, 2).encode(&pixels).unwrap();
+        let mut ops = Ops::new(&data).unwrap();
+        assert_eq!(ops.header().width, 3);
+        let all = ops.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        let expected = [
+            (14, 0, Op::Run { len: 2 }),
+            (
+                15,
+                2,
+                Op::Diff {
+                    dr: 1,
+                    dg: -1,
+                    db: 0,
+                },
+            ),
+            (
+                16,
+                3,
+                Op::Luma {
+                    dg: 6,
+                    dr_dg: 4,
+                    db_dg: 2,
+                },
+            ),
+            (
+                18,
+                4,
+                Op::Luma {
+                    dg: -5,
+                    dr_dg: -6,
+                    db_dg: -3,
+                },
+            ),
+            (
+                20,
+                5,
+                Op::Rgba {
+                    r: 200,
+                    g: 100,
+                    b: 50,
+                    a: 25,
+                },
+            ),
+        ];
+        assert_eq!(all.len(), expected.len());
+        for (info, (offset, index, op)) in all.iter().zip(expected) {
+            assert_eq!((info.offset, info.index, info.op), (offset, index, op));
+            assert_eq!(info.pixel, pixels[index]);
+        }
+        assert_eq!((ops.offset(), ops.end_marker()), (25, Ok(0)));
+
+        // agrees with the decoder on the test vectors
+        for name in ["alpha", "gradient", "noise", "palette", "runs"] {
+            let data = std::fs::read(format!("assets/vectors/{name}.qoi")).unwrap();
+            let image = Decoder::new().decode(&data).unwrap();
+            let mut ops = Ops::new(&data).unwrap();
+            for info in ops.by_ref() {
+                let info = info.unwrap();
+                let run = &image.pixels[info.index..info.index + info.op.pixels()];
+                assert!(run.iter().all(|&pixel| pixel == info.pixel), "{name}");
+            }
+            assert_eq!((ops.index, ops.end_marker()), (image.pixels.len(), Ok(0)));
+        }
+
+        // an observer on the decoder sees the same ops
+        let mut seen = vec![];
+        let mut decoder = Decoder::new().with_observer(|offset, op, index| {
+            seen.push((offset, index, op));
+        });
+        assert_eq!(decoder.decode(&data).unwrap().pixels, pixels);
+        let listed = all.iter().map(|info| (info.offset, info.index, info.op));
+        assert!(seen.into_iter().eq(listed));
+
+        let heat = heatmap(&data).unwrap();
+        let colors = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)].map(|xy| heat[xy]);
+        let (run, diff, luma) = (Op::Run { len: 1 }, all[1].op, all[2].op);
+        let expected = [run, run, diff, luma, luma, all[4].op].map(|op| op.heat_color());
+        assert_eq!(colors, expected);
+        assert_eq!(heatmap(&data[..18]).err(), Some(DecodeError::UnexpectedEof));
+
+        let mut ops = Ops::new(&data[..18]).unwrap();
+        assert!(
+            ops.by_ref()
+                .any(|info| info == Err(DecodeError::UnexpectedEof))
+        );
+        assert!(ops.next().is_none());
+        assert_eq!(ops.end_marker(), Err(DecodeError::UnexpectedEof));
+    }
+}
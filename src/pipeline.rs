@@ -0,0 +1,123 @@
+//! Frame pipelines for screen-capture style streaming.
+//!
+//! Every frame goes out either as a standard QOI file (a keyframe) or as a
+//! qoi-plus delta against the frame before it, in which unchanged pixels collapse
+//! into long runs. Keyframes let a receiver join or recover mid-stream.
+
+use crate::plus::{DecoderExt, EncoderExt, MAGIC};
+use crate::{Decoder, EncodeError, Encoder, Image, Pixel};
+
+pub struct FramePipeline {
+    width: u32,
+    height: u32,
+    keyframe_interval: usize,
+    since_keyframe: usize,
+    prev: Option<Vec<Pixel>>,
+}
+
+impl FramePipeline {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            keyframe_interval: 60,
+            since_keyframe: 0,
+            prev: None,
+        }
+    }
+
+    // Every `interval`-th frame is sent in full; 0 only sends the first one in full.
+    pub fn keyframe_interval(mut self, interval: usize) -> Self {
+        self.keyframe_interval = interval;
+        self
+    }
+
+    // Forces the next frame to be a keyframe, e.g. when a new receiver connects.
+    pub fn request_keyframe(&mut self) {
+        self.prev = None;
+    }
+
+    pub fn encode_frame(&mut self, frame: &[Pixel]) -> Result<Vec<u8>, EncodeError> {
+        let keyframe_due =
+            self.keyframe_interval != 0 && self.since_keyframe + 1 >= self.keyframe_interval;
+        let data = match &self.prev {
+            Some(prev) if !keyframe_due => {
+                self.since_keyframe += 1;
+                EncoderExt::new(self.width, self.height).encode_delta(frame, prev)?
+            }
+            _ => {
+                self.since_keyframe = 0;
+                Encoder::new(self.width, self.height).encode(frame)?
+            }
+        };
+
+        let prev = self.prev.get_or_insert_default();
+        prev.clear();
+        prev.extend_from_slice(frame);
+        Ok(data)
+    }
+}
+
+#[derive(Default)]
+pub struct FramePipelineDecoder {
+    frame: Option<Image<Pixel>>,
+}
+
+impl FramePipelineDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns `None` for invalid data and for deltas that arrive before any
+    // keyframe, which the stream cannot recover from until the next keyframe.
+    pub fn decode_frame(&mut self, data: &[u8]) -> Option<&Image<Pixel>> {
+        let frame = if data.starts_with(MAGIC) {
+            DecoderExt::new().decode_delta(data, self.frame.as_ref()?)
+        } else {
+            Decoder::new().decode(data).ok()
+        };
+        self.frame = frame;
+        self.frame.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a static background with a small square moving across it
+    fn frame(t: usize) -> Vec<Pixel> {
+        Image::from_fn(64, 48, |x, y| {
+            if (t * 4..t * 4 + 8).contains(&x) && (10..18).contains(&y) {
+                Pixel::new(255, 0, 0, 255)
+            } else {
+                Pixel::new((x * 4) as u8, (y * 5) as u8, 100, 255)
+            }
+        })
+        .pixels
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut pipeline = FramePipeline::new(64, 48).keyframe_interval(4);
+        let mut decoder = FramePipelineDecoder::new();
+        let mut sizes = vec![];
+        for t in 0..10 {
+            let data = pipeline.encode_frame(&frame(t)).unwrap();
+            assert_eq!(data.starts_with(b"qoif"), t % 4 == 0, "frame {t}");
+            sizes.push(data.len());
+            assert!(decoder.decode_frame(&data).unwrap().pixels == frame(t));
+        }
+        assert!(sizes[1] * 4 < sizes[0]);
+
+        // a delta needs the frame it was made against
+        let delta = pipeline.encode_frame(&frame(10)).unwrap();
+        assert!(FramePipelineDecoder::new().decode_frame(&delta).is_none());
+        assert!(DecoderExt::new().decode(&delta).is_none());
+
+        pipeline.request_keyframe();
+        let keyframe = pipeline.encode_frame(&frame(11)).unwrap();
+        let mut joined = FramePipelineDecoder::new();
+        assert!(joined.decode_frame(&keyframe).unwrap().pixels == frame(11));
+    }
+}
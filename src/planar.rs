@@ -0,0 +1,165 @@
+//! Images stored as one plane per channel rather than interleaved RGBA, for
+//! work that goes a channel at a time: histograms, lookup tables, isolating a
+//! channel, or handing planar frames to a video pipeline.
+//!
+//! Loops over a single plane are plain byte loops, which the compiler
+//! vectorizes far more readily than loops picking one field out of `Pixel`.
+
+use crate::{Image, Pixel};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Planes {
+    pub width: usize,
+    pub height: usize,
+    // red, green, blue and alpha, each `width * height` bytes in row-major order
+    pub planes: [Vec<u8>; 4],
+}
+
+impl Planes {
+    // an opaque black image
+    pub fn new(width: usize, height: usize) -> Self {
+        let plane = vec![0; width * height];
+        Self {
+            width,
+            height,
+            planes: [
+                plane.clone(),
+                plane.clone(),
+                plane,
+                vec![255; width * height],
+            ],
+        }
+    }
+
+    pub fn to_image(&self) -> Image<Pixel> {
+        let [r, g, b, a] = &self.planes;
+        let pixels = (0..self.width * self.height)
+            .map(|i| Pixel::new(r[i], g[i], b[i], a[i]))
+            .collect();
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+
+    // 0 to 3 for red, green, blue and alpha
+    pub fn channel(&self, channel: usize) -> &[u8] {
+        &self.planes[channel]
+    }
+
+    pub fn channel_mut(&mut self, channel: usize) -> &mut [u8] {
+        &mut self.planes[channel]
+    }
+
+    pub fn histogram(&self, channel: usize) -> [usize; 256] {
+        let mut histogram = [0; 256];
+        for &value in self.channel(channel) {
+            histogram[value as usize] += 1;
+        }
+        histogram
+    }
+
+    pub fn apply_lut(&mut self, channel: usize, lut: &[u8; 256]) {
+        for value in self.channel_mut(channel) {
+            *value = lut[*value as usize];
+        }
+    }
+
+    // Only `channel` left, shown as gray on an opaque image; alpha comes out
+    // as its coverage.
+    pub fn isolate(&self, channel: usize) -> Planes {
+        let plane = self.planes[channel].clone();
+        Planes {
+            width: self.width,
+            height: self.height,
+            planes: [
+                plane.clone(),
+                plane.clone(),
+                plane,
+                vec![255; self.width * self.height],
+            ],
+        }
+    }
+
+    // The planes one after another: all of red, then green, blue and alpha.
+    // Video tools take other orders, e.g. ffmpeg's gbrap is green, blue, red.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.planes.concat()
+    }
+
+    // None unless `data` holds exactly four planes of `width * height` bytes.
+    pub fn from_bytes(width: usize, height: usize, data: &[u8]) -> Option<Planes> {
+        let len = width.checked_mul(height)?;
+        if data.len() != len.checked_mul(4)? {
+            return None;
+        }
+        let mut chunks = data.chunks_exact(len.max(1)).map(<[u8]>::to_vec);
+        let planes = match len {
+            0 => Default::default(),
+            _ => std::array::from_fn(|_| chunks.next().unwrap()),
+        };
+        Some(Planes {
+            width,
+            height,
+            planes,
+        })
+    }
+}
+
+impl Image<Pixel> {
+    pub fn to_planes(&self) -> Planes {
+        let len = self.pixels.len();
+        let mut planes: [Vec<u8>; 4] = std::array::from_fn(|_| Vec::with_capacity(len));
+        for &Pixel { r, g, b, a } in &self.pixels {
+            for (plane, value) in planes.iter_mut().zip([r, g, b, a]) {
+                plane.push(value);
+            }
+        }
+        Planes {
+            width: self.width,
+            height: self.height,
+            planes,
+        }
+    }
+}
+
+impl From<&Image<Pixel>> for Planes {
+    fn from(image: &Image<Pixel>) -> Self {
+        image.to_planes()
+    }
+}
+
+impl From<&Planes> for Image<Pixel> {
+    fn from(planes: &Planes) -> Self {
+        planes.to_image()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let image = Image::from_fn(5, 3, |x, y| Pixel::new(x as u8, y as u8, 7, 200 + x as u8));
+        let mut planes = image.to_planes();
+        assert_eq!(planes.channel(0), [0, 1, 2, 3, 4].repeat(3));
+        assert!(planes.to_image().pixels == image.pixels);
+
+        let bytes = planes.to_bytes();
+        assert_eq!(bytes.len(), 60);
+        assert_eq!(Planes::from_bytes(5, 3, &bytes), Some(planes.clone()));
+        assert_eq!(Planes::from_bytes(5, 3, &bytes[1..]), None);
+        assert_eq!(Planes::from_bytes(0, 3, &[]), Some(Planes::new(0, 3)));
+
+        let hist = planes.histogram(2);
+        assert_eq!((hist[7], hist.iter().sum::<usize>()), (15, 15));
+        let invert = std::array::from_fn(|i| 255 - i as u8);
+        planes.apply_lut(1, &invert);
+        assert_eq!(planes.to_image()[(4, 2)], Pixel::new(4, 253, 7, 204));
+
+        let red = planes.isolate(0).to_image();
+        assert_eq!(red[(3, 1)], Pixel::new(3, 3, 3, 255));
+    }
+}
@@ -0,0 +1,590 @@
+//! Experimental "qoi-plus" format for compression research.
+//!
+//! Streams start with the `qoi+` magic instead of `qoif`, followed by the usual
+//! header fields and a flags byte announcing which extension ops may appear.
+//! Extension ops are introduced by [`ESCAPE`], a DIFF op with all deltas at zero
+//! that a standard encoder never produces (it would emit a RUN instead).
+//! Everything else is plain QOI, so files produced here are *not* spec compliant.
+//!
+//! With [`flags::INTERLACED`] the pixels are stored in Adam7 pass order, so a
+//! partially received file can already be shown as a coarse preview.
+//!
+//! With [`flags::DELTA`] the pixels are per-channel differences to a reference
+//! frame the decoder already has, so unchanged areas become long runs of zero.
+
+use crate::cache::{CachePolicy, SpecCache};
+use crate::{
+    END_MARKER, EncodeError, EncodeStats, EncoderKind, Header, Image, Limits, Pixel, QoiEncode,
+    QoiOp, alloc_pixels, check_dimensions,
+};
+
+pub const MAGIC: &[u8; 4] = b"qoi+";
+
+const ESCAPE: u8 = 0b01_10_10_10;
+
+const OP_LONG_RUN: u8 = 0x00; // u16 length with a bias of 63
+const OP_PREV_ROW: u8 = 0x01; // u8 length with a bias of 1, copies pixels from the row above
+
+const LONG_RUN_BIAS: usize = 63;
+const LONG_RUN_MAX: usize = u16::MAX as usize + LONG_RUN_BIAS;
+const PREV_ROW_MIN: usize = 2;
+const PREV_ROW_MAX: usize = 256;
+
+type Coord = (usize, usize);
+
+// (x, y) start, (x, y) step and the block each pixel covers in a preview
+const ADAM7: [(Coord, Coord, Coord); 7] = [
+    ((0, 0), (8, 8), (8, 8)),
+    ((4, 0), (8, 8), (4, 8)),
+    ((0, 4), (4, 8), (4, 4)),
+    ((2, 0), (4, 4), (2, 4)),
+    ((0, 2), (2, 4), (2, 2)),
+    ((1, 0), (2, 2), (1, 2)),
+    ((0, 1), (1, 2), (1, 1)),
+];
+
+pub mod flags {
+    pub const LONG_RUN: u8 = 1 << 0;
+    pub const PREV_ROW: u8 = 1 << 1;
+    // PREV_ROW has no meaning in pass order and is dropped for interlaced streams
+    pub const INTERLACED: u8 = 1 << 2;
+    // only set by `EncoderExt::encode_delta`
+    pub const DELTA: u8 = 1 << 3;
+    pub const ALL: u8 = LONG_RUN | PREV_ROW | INTERLACED | DELTA;
+}
+
+#[derive(Clone, Copy)]
+pub struct EncoderExt {
+    width: u32,
+    height: u32,
+    flags: u8,
+    limits: Limits,
+}
+
+impl EncoderExt {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            flags: flags::LONG_RUN | flags::PREV_ROW,
+            limits: Limits::default(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_flags(mut self, flags: u8) -> Self {
+        self.flags = flags & flags::ALL & !flags::DELTA;
+        if self.flags & flags::INTERLACED != 0 {
+            self.flags &= !flags::PREV_ROW;
+        }
+        self
+    }
+
+    pub fn interlaced(self, interlaced: bool) -> Self {
+        let flags = self.flags & !flags::INTERLACED;
+        self.with_flags(if interlaced {
+            flags | flags::INTERLACED
+        } else {
+            flags
+        })
+    }
+
+    pub fn encode(&self, img: &[Pixel]) -> Result<Vec<u8>, EncodeError> {
+        check_dimensions(self.width, self.height, &self.limits, img.len())?;
+        if self.flags & flags::INTERLACED != 0 {
+            let order = adam7_order(self.width as usize, self.height as usize);
+            let reordered = order.iter().map(|&(i, _)| img[i]).collect::<Vec<_>>();
+            return Ok(self.encode_sequence(&reordered));
+        }
+        Ok(self.encode_sequence(img))
+    }
+
+    // Encodes `img` as differences to `reference`, e.g. the previous frame of a
+    // stream, for `DecoderExt::decode_delta`.
+    pub fn encode_delta(&self, img: &[Pixel], reference: &[Pixel]) -> Result<Vec<u8>, EncodeError> {
+        if img.len() != reference.len() {
+            return Err(EncodeError::PixelCountMismatch);
+        }
+        let delta = img
+            .iter()
+            .zip(reference)
+            .map(|(&pixel, &base)| sub(pixel, base))
+            .collect::<Vec<_>>();
+        let encoder = Self {
+            flags: self.flags | flags::DELTA,
+            ..*self
+        };
+        encoder.encode(&delta)
+    }
+
+    fn encode_sequence(&self, img: &[Pixel]) -> Vec<u8> {
+        let mut buf = vec![];
+
+        // header
+        let header = Header {
+            width: self.width,
+            height: self.height,
+            channels: 4,
+            colorspace: 0,
+        };
+        header.append_bytes(MAGIC, &mut buf);
+        buf.push(self.flags);
+
+        let width = self.width as usize;
+        let mut cache = SpecCache::default();
+        let mut prev = Pixel::new(0, 0, 0, 255);
+
+        // body
+        let mut i = 0;
+        while i < img.len() {
+            let pixel = img[i];
+
+            if pixel == prev {
+                let run = img[i..].iter().take_while(|&&p| p == prev).count();
+                self.append_run(run, &mut buf);
+                i += run;
+                continue;
+            }
+
+            if self.flags & flags::PREV_ROW != 0 && i >= width {
+                let len = img[i..]
+                    .iter()
+                    .zip(&img[i - width..])
+                    .take_while(|(p, above)| p == above)
+                    .take(PREV_ROW_MAX)
+                    .count();
+
+                if len >= PREV_ROW_MIN {
+                    buf.extend([ESCAPE, OP_PREV_ROW, (len - 1) as u8]);
+                    for &p in &img[i..i + len] {
+                        cache.insert(p);
+                    }
+                    prev = img[i + len - 1];
+                    i += len;
+                    continue;
+                }
+            }
+
+            QoiOp::append_for_pixel(
+                &mut cache,
+                prev,
+                pixel,
+                &mut buf,
+                &mut EncodeStats::default(),
+            );
+            prev = pixel;
+            i += 1;
+        }
+
+        // footer
+        buf.extend_from_slice(&END_MARKER);
+
+        buf
+    }
+
+    fn append_run(&self, mut run: usize, buf: &mut Vec<u8>) {
+        if self.flags & flags::LONG_RUN != 0 {
+            while run >= LONG_RUN_BIAS {
+                let len = run.min(LONG_RUN_MAX);
+                buf.extend([ESCAPE, OP_LONG_RUN]);
+                buf.extend(((len - LONG_RUN_BIAS) as u16).to_be_bytes());
+                run -= len;
+            }
+        }
+
+        while run > 0 {
+            let len = run.min(62);
+            QoiOp::Run { len: len as u8 }.append_bytes(buf);
+            run -= len;
+        }
+    }
+}
+
+impl QoiEncode for EncoderExt {
+    fn name(&self) -> &'static str {
+        EncoderKind::Plus.name()
+    }
+
+    fn encode(&mut self, img: &[Pixel]) -> Result<Vec<u8>, EncodeError> {
+        EncoderExt::encode(self, img)
+    }
+}
+
+#[derive(Default)]
+pub struct DecoderExt {
+    limits: Limits,
+}
+
+impl DecoderExt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // checked on the header before anything is allocated for the image
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // Delta streams need their reference frame, see `decode_delta`.
+    pub fn decode(&self, data: &[u8]) -> Option<Image<Pixel>> {
+        let (image, flags) = self.decode_image(data)?;
+        (flags & flags::DELTA == 0).then_some(image)
+    }
+
+    // Decodes a stream written by `EncoderExt::encode_delta` against the same reference.
+    pub fn decode_delta(&self, data: &[u8], reference: &Image<Pixel>) -> Option<Image<Pixel>> {
+        let (mut image, flags) = self.decode_image(data)?;
+        if flags & flags::DELTA == 0
+            || (image.width, image.height) != (reference.width, reference.height)
+        {
+            return None;
+        }
+        for (pixel, &base) in image.pixels.iter_mut().zip(&reference.pixels) {
+            *pixel = add(*pixel, base);
+        }
+        Some(image)
+    }
+
+    fn decode_image(&self, data: &[u8]) -> Option<(Image<Pixel>, u8)> {
+        let (width, height, flags, data) = self.decode_header(data)?;
+        let total = width.checked_mul(height)?;
+
+        let (sequence, data) = Self::decode_sequence(data, flags, width, total, false)?;
+
+        // footer
+        if END_MARKER.ne(data) {
+            return None;
+        }
+
+        let pixels = if flags & flags::INTERLACED != 0 {
+            let mut pixels = alloc_pixels(total).ok()?;
+            pixels.resize(total, Pixel::new(0, 0, 0, 0));
+            for (&(i, _), pixel) in adam7_order(width, height).iter().zip(sequence) {
+                pixels[i] = pixel;
+            }
+            pixels
+        } else {
+            sequence
+        };
+
+        Some((
+            Image {
+                width,
+                height,
+                pixels,
+            },
+            flags,
+        ))
+    }
+
+    // Decodes as much of an interlaced stream as `data` holds (which may be a truncated
+    // prefix of the file) and stretches each pixel over the block it stands for. Returns
+    // `None` for non-interlaced streams or when not even the first pixel is available.
+    pub fn decode_preview(&self, data: &[u8]) -> Option<Image<Pixel>> {
+        let (width, height, flags, data) = self.decode_header(data)?;
+        if flags & flags::INTERLACED == 0 || flags & flags::DELTA != 0 {
+            return None;
+        }
+        let total = width.checked_mul(height)?;
+        // as for a standard file, input too short to hold the whole image is
+        // refused before anything is allocated for it
+        if total > data.len().saturating_mul(max_pixels_per_byte(flags)) {
+            return None;
+        }
+
+        let (sequence, _) = Self::decode_sequence(data, flags, width, total, true)?;
+        if sequence.is_empty() {
+            return None;
+        }
+
+        let mut pixels = alloc_pixels(total).ok()?;
+        pixels.resize(total, Pixel::new(0, 0, 0, 0));
+        for (&(i, pass), pixel) in adam7_order(width, height).iter().zip(sequence) {
+            let (x, y) = (i % width, i / width);
+            let (_, _, (block_w, block_h)) = ADAM7[pass];
+            for by in y..(y + block_h).min(height) {
+                pixels[by * width + x..by * width + (x + block_w).min(width)].fill(pixel);
+            }
+        }
+
+        Some(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn decode_header<'a>(&self, data: &'a [u8]) -> Option<(usize, usize, u8, &'a [u8])> {
+        let (Header { width, height, .. }, data) = Header::from_bytes(MAGIC, data)?;
+        let (&flags, data) = data.split_first()?;
+        if flags & !flags::ALL != 0 || !self.limits.allows(width, height) {
+            return None;
+        }
+        Some((width as usize, height as usize, flags, data))
+    }
+
+    // decodes up to `total` pixels, refusing any op that would go past them; with
+    // `partial` a truncated stream ends the sequence early
+    fn decode_sequence(
+        data: &[u8],
+        flags: u8,
+        width: usize,
+        total: usize,
+        partial: bool,
+    ) -> Option<(Vec<Pixel>, &[u8])> {
+        let mut cache = SpecCache::default();
+        let mut prev = Pixel::new(0, 0, 0, 255);
+
+        // body
+        let mut data = data;
+        let mut pixels = Vec::<Pixel>::with_capacity(total.min(data.len() * 62));
+        while pixels.len() < total {
+            if partial && !is_complete_op(data) {
+                break;
+            }
+
+            if let [ESCAPE, op, rest @ ..] = data {
+                data = match *op {
+                    OP_LONG_RUN if flags & flags::LONG_RUN != 0 => {
+                        let (len, rest) = rest.split_first_chunk::<2>()?;
+                        let len = u16::from_be_bytes(*len) as usize + LONG_RUN_BIAS;
+                        if pixels.len() + len > total {
+                            return None;
+                        }
+                        pixels.extend(std::iter::repeat_n(prev, len));
+                        rest
+                    }
+                    OP_PREV_ROW if flags & flags::PREV_ROW != 0 => {
+                        let (&len, rest) = rest.split_first()?;
+                        let start = pixels.len().checked_sub(width)?;
+                        if pixels.len() + len as usize + 1 > total {
+                            return None;
+                        }
+                        for i in start..start + len as usize + 1 {
+                            let p = pixels[i];
+                            cache.insert(p);
+                            pixels.push(p);
+                        }
+                        prev = *pixels.last()?;
+                        rest
+                    }
+                    _ => return None,
+                };
+                continue;
+            }
+
+            let (op, rest) = QoiOp::from_bytes(data)?;
+            let count = match op {
+                QoiOp::Run { len } => len,
+                _ => 1,
+            };
+            if pixels.len() + count as usize > total {
+                return None;
+            }
+            let pixel = op.apply(&cache, prev);
+            prev = pixel;
+            if !matches!(op, QoiOp::Run { .. }) {
+                cache.insert(pixel);
+            }
+            data = rest;
+
+            pixels.extend(std::iter::repeat_n(pixel, count as usize));
+        }
+
+        Some((pixels, data))
+    }
+}
+
+// A standard op byte yields at most 62 pixels; the extension ops allowed by
+// `flags` may stand for more.
+fn max_pixels_per_byte(flags: u8) -> usize {
+    if flags & flags::LONG_RUN != 0 {
+        LONG_RUN_MAX.div_ceil(4)
+    } else if flags & flags::PREV_ROW != 0 {
+        PREV_ROW_MAX.div_ceil(3)
+    } else {
+        62
+    }
+}
+
+fn is_complete_op(data: &[u8]) -> bool {
+    let needed = match data {
+        [] => return false,
+        [ESCAPE, OP_LONG_RUN, ..] => 4,
+        [ESCAPE, OP_PREV_ROW, ..] => 3,
+        [0b11111110, ..] => 4,
+        [0b11111111, ..] => 5,
+        [head, ..] if head >> 6 == 0b10 => 2,
+        _ => 1,
+    };
+    data.len() >= needed
+}
+
+fn sub(pixel: Pixel, base: Pixel) -> Pixel {
+    let [r, g, b, a] = pixel.to_bytes();
+    let [br, bg, bb, ba] = base.to_bytes();
+    Pixel::new(
+        r.wrapping_sub(br),
+        g.wrapping_sub(bg),
+        b.wrapping_sub(bb),
+        a.wrapping_sub(ba),
+    )
+}
+
+fn add(delta: Pixel, base: Pixel) -> Pixel {
+    let [r, g, b, a] = delta.to_bytes();
+    let [br, bg, bb, ba] = base.to_bytes();
+    Pixel::new(
+        r.wrapping_add(br),
+        g.wrapping_add(bg),
+        b.wrapping_add(bb),
+        a.wrapping_add(ba),
+    )
+}
+
+// image indices in Adam7 pass order, together with the pass each one belongs to
+fn adam7_order(width: usize, height: usize) -> Vec<(usize, usize)> {
+    let mut order = Vec::with_capacity(width * height);
+    for (pass, ((x0, y0), (dx, dy), _)) in ADAM7.into_iter().enumerate() {
+        for y in (y0..height).step_by(dy) {
+            for x in (x0..width).step_by(dx) {
+                order.push((y * width + x, pass));
+            }
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::Wrapping;
+
+    use super::*;
+    use crate::{Decoder, Encoder};
+
+    // flat areas for runs, repeated rows for PREV_ROW
+    fn striped(width: usize, height: usize) -> Vec<Pixel> {
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let Wrapping(v) = Wrapping(x as u8) * Wrapping(3);
+                if y % 8 < 4 {
+                    Pixel::new(v, v / 2, 255 - v, 255)
+                } else {
+                    Pixel::new(40, 40, 40, 255)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let (width, height) = (300, 64);
+        let img = striped(width, height);
+
+        for flags in [0, flags::LONG_RUN, flags::PREV_ROW, flags::ALL] {
+            let data = EncoderExt::new(width as u32, height as u32)
+                .with_flags(flags)
+                .encode(&img)
+                .unwrap();
+            let decoded = DecoderExt::new().decode(&data).unwrap();
+            assert!(decoded.pixels.eq(&img), "flags = {flags:#04b}");
+        }
+    }
+
+    #[test]
+    fn smaller_than_standard() {
+        let (width, height) = (300, 64);
+        let img = striped(width, height);
+
+        let standard = Encoder::new(width as u32, height as u32)
+            .encode(&img)
+            .unwrap();
+        let plus = EncoderExt::new(width as u32, height as u32)
+            .encode(&img)
+            .unwrap();
+        assert!(plus.len() < standard.len());
+
+        // the standard decoder must refuse the extended stream
+        assert!(Decoder::new().decode(&plus).is_err());
+    }
+
+    #[test]
+    fn limits() {
+        let img = striped(3, 2);
+        let data = EncoderExt::new(3, 2).encode(&img).unwrap();
+        let small = Limits {
+            max_pixels: 5,
+            ..Limits::default()
+        };
+        assert!(DecoderExt::new().with_limits(small).decode(&data).is_none());
+        assert_eq!(
+            EncoderExt::new(3, 2).with_limits(small).encode(&img),
+            Err(EncodeError::LimitsExceeded)
+        );
+
+        // a long run of 63 pixels into a 3x2 image is refused, not decoded
+        let mut overshoot = data[..15].to_vec();
+        overshoot.extend([ESCAPE, OP_LONG_RUN, 0, 0]);
+        overshoot.extend(END_MARKER);
+        assert!(DecoderExt::new().decode(&overshoot).is_none());
+    }
+
+    #[test]
+    fn preview_of_oversized_header() {
+        // a bare header declaring 65535x65535 is refused rather than allocated for
+        let data = EncoderExt::new(1, 1)
+            .interlaced(true)
+            .encode(&striped(1, 1))
+            .unwrap();
+        let mut huge = data.clone();
+        huge[4..12].copy_from_slice(&[0, 0, 0xff, 0xff, 0, 0, 0xff, 0xff]);
+        assert!(DecoderExt::new().decode_preview(&huge).is_none());
+        assert!(
+            DecoderExt::new()
+                .with_limits(Limits::unlimited())
+                .decode_preview(&huge)
+                .is_none()
+        );
+        assert!(DecoderExt::new().decode_preview(&data).is_some());
+    }
+
+    #[test]
+    fn interlaced() {
+        let (width, height) = (37, 21);
+        let img = striped(width, height);
+
+        let data = EncoderExt::new(width as u32, height as u32)
+            .interlaced(true)
+            .encode(&img)
+            .unwrap();
+        let decoded = DecoderExt::new().decode(&data).unwrap();
+        assert!(decoded.pixels.eq(&img));
+
+        // the full stream previews as the image itself
+        let preview = DecoderExt::new().decode_preview(&data).unwrap();
+        assert!(preview.pixels.eq(&img));
+
+        // the first pass alone spreads every 8th pixel over an 8x8 block
+        let first_pass = width.div_ceil(8) * height.div_ceil(8);
+        let decoded_len = |ops: &[u8]| {
+            DecoderExt::decode_sequence(ops, flags::INTERLACED, width, first_pass, true)
+                .map_or(0, |(sequence, _)| sequence.len())
+        };
+        let end = (15..data.len())
+            .find(|&end| decoded_len(&data[15..end]) >= first_pass)
+            .unwrap();
+        let preview = DecoderExt::new().decode_preview(&data[..end]).unwrap();
+        for y in 0..height {
+            for x in 0..width {
+                let block = (y / 8 * 8) * width + x / 8 * 8;
+                assert!(preview.pixels[y * width + x] == img[block]);
+            }
+        }
+    }
+}
@@ -0,0 +1,11 @@
+//! Glob-import `qoi_rs::prelude::*` to bring the commonly used types and traits
+//! into scope.
+
+pub use crate::{
+    CachePolicy, ChannelCount, Channels, DecodeError, EncodeError, Header, Image, Limits,
+    ParseHexError, Pixel, PixelOrder,
+};
+#[cfg(feature = "decode")]
+pub use crate::{Budget, Decoder, FrameDecoder, PartialDecode};
+#[cfg(feature = "encode")]
+pub use crate::{Encoder, QoiEncode, stream::StreamEncoder};
@@ -0,0 +1,76 @@
+//! Conversions between images and the `image` crate's buffers.
+
+use image::error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::{DynamicImage, ImageError, RgbaImage};
+
+use crate::{Image, Pixel};
+
+impl From<Image<Pixel>> for RgbaImage {
+    fn from(image: Image<Pixel>) -> Self {
+        let (width, height) = (image.width as u32, image.height as u32);
+        RgbaImage::from_raw(width, height, image.into_rgba_bytes())
+            .expect("pixel count matches the image dimensions")
+    }
+}
+
+impl From<RgbaImage> for Image<Pixel> {
+    fn from(image: RgbaImage) -> Self {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let pixels = image
+            .pixels()
+            .map(|&image::Rgba([r, g, b, a])| Pixel::new(r, g, b, a))
+            .collect();
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+impl TryFrom<DynamicImage> for Image<Pixel> {
+    type Error = ImageError;
+
+    // Any 8-bit color type is converted; deeper ones are refused rather than
+    // silently truncated, `to_rgba8` first accepts the loss explicitly.
+    fn try_from(image: DynamicImage) -> Result<Self, Self::Error> {
+        let color = image.color();
+        if color.bytes_per_pixel() / color.channel_count() != 1 {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormatHint::Unknown,
+                    UnsupportedErrorKind::Color(color.into()),
+                ),
+            ));
+        }
+        Ok(image.into_rgba8().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let png = image::ImageReader::open("assets/suz.png")
+            .unwrap()
+            .decode()
+            .unwrap();
+        let expected = png.to_rgba8();
+
+        let image = Image::<Pixel>::try_from(png).unwrap();
+        assert_eq!(
+            (image.width, image.height),
+            (expected.width() as usize, expected.height() as usize)
+        );
+        let [r, g, b, a] = expected.get_pixel(0, 0).0;
+        assert_eq!(image.pixels[0], Pixel::new(r, g, b, a));
+        assert_eq!(RgbaImage::from(image), expected);
+
+        let deep = DynamicImage::new_rgba16(2, 2);
+        assert!(Image::<Pixel>::try_from(deep).is_err());
+        let gray = DynamicImage::new_luma8(2, 2);
+        assert!(Image::<Pixel>::try_from(gray).unwrap().pixels[3] == Pixel::new(0, 0, 0, 255));
+    }
+}
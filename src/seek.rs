@@ -0,0 +1,258 @@
+//! Random access into standard QOI files through an index kept beside them.
+//!
+//! QOI ops depend on everything before them, so reaching row `y` normally
+//! means decoding all the rows above it. A `SeekIndex` records, every so many
+//! rows, where the op holding the row's first pixel starts and the decoder
+//! state just before it. `Decoder::decode_rows` then starts from the nearest
+//! checkpoint instead of the top of the file.
+//!
+//! Indexes are stored in `.qix` files next to the image: the image's 14-byte
+//! header with the magic `qoix`, the checkpoint spacing in rows and the number
+//! of checkpoints as big-endian u32s, then per checkpoint its offset and pixel
+//! index as big-endian u64s, the previous pixel, a u64 mask of the cache slots
+//! that are not zero, and the RGBA of those slots.
+
+use std::ops::{ControlFlow, Range};
+
+use crate::ops::{Observer, Ops};
+use crate::{DecodeError, Decoder, Header, Image, Pixel, SpecCache, alloc_pixels};
+
+const MAGIC: &[u8; 4] = b"qoix";
+
+// The decoder state before the op at `offset`, which produces pixel `index`
+// and possibly a few after it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Checkpoint {
+    pub offset: usize,
+    pub index: usize,
+    pub prev: Pixel,
+    pub cache: [Pixel; 64],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeekIndex {
+    pub header: Header,
+    // every `rows` rows from the first, in order
+    pub rows: u32,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl SeekIndex {
+    // Reads the whole file once, keeping a checkpoint every `rows` rows.
+    pub fn build(data: &[u8], rows: u32) -> Result<SeekIndex, DecodeError> {
+        let rows = rows.max(1);
+        let mut ops = Ops::new(data)?;
+        let header = ops.header();
+        let width = header.width as usize;
+        let mut checkpoints = vec![];
+        let mut row = 0usize;
+        let mut state = None;
+        loop {
+            // a run covers at most 62 pixels, so only ops starting that close
+            // to the next checkpoint's pixel can hold it
+            let target = row.saturating_mul(width);
+            let offset = ops.offset();
+            if ops.next_index() + 62 > target {
+                state = Some(ops.state());
+            }
+            let Some(info) = ops.next() else {
+                break;
+            };
+            let info = info?;
+            let end = info.index + info.op.pixels();
+            if target < end {
+                let (prev, cache) = state.take().expect("saved for the op");
+                checkpoints.push(Checkpoint {
+                    offset,
+                    index: info.index,
+                    prev,
+                    cache,
+                });
+                while row.saturating_mul(width) < end {
+                    row += rows as usize;
+                }
+            }
+        }
+        ops.end_marker()?;
+        Ok(SeekIndex {
+            header,
+            rows,
+            checkpoints,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.header.append_bytes(MAGIC, &mut buf);
+        buf.extend(self.rows.to_be_bytes());
+        buf.extend((self.checkpoints.len() as u32).to_be_bytes());
+        for checkpoint in &self.checkpoints {
+            buf.extend((checkpoint.offset as u64).to_be_bytes());
+            buf.extend((checkpoint.index as u64).to_be_bytes());
+            buf.extend(checkpoint.prev.to_bytes());
+            let empty = Pixel::new(0, 0, 0, 0);
+            let mask = (0..64)
+                .filter(|&slot| checkpoint.cache[slot] != empty)
+                .fold(0u64, |mask, slot| mask | 1 << slot);
+            buf.extend(mask.to_be_bytes());
+            for pixel in checkpoint.cache.iter().filter(|&&pixel| pixel != empty) {
+                buf.extend(pixel.to_bytes());
+            }
+        }
+        buf
+    }
+
+    // None unless `data` is a whole index with its checkpoints in order.
+    pub fn from_bytes(data: &[u8]) -> Option<SeekIndex> {
+        let (header, mut data) = Header::from_bytes(MAGIC, data)?;
+        let mut take = |len: usize| {
+            let (bytes, rest) = data.split_at_checked(len)?;
+            data = rest;
+            Some(bytes)
+        };
+        let u32_at = |bytes: &[u8]| u32::from_be_bytes(bytes.try_into().unwrap());
+        let u64_at = |bytes: &[u8]| u64::from_be_bytes(bytes.try_into().unwrap());
+        let pixel_at = |bytes: &[u8]| Pixel::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+
+        let rows = u32_at(take(4)?);
+        let count = u32_at(take(4)?);
+        let mut checkpoints = vec![];
+        for _ in 0..count {
+            let offset = usize::try_from(u64_at(take(8)?)).ok()?;
+            let index = usize::try_from(u64_at(take(8)?)).ok()?;
+            let prev = pixel_at(take(4)?);
+            let mask = u64_at(take(8)?);
+            let mut cache = [Pixel::new(0, 0, 0, 0); 64];
+            for (slot, pixel) in cache.iter_mut().enumerate() {
+                if mask & 1 << slot != 0 {
+                    *pixel = pixel_at(take(4)?);
+                }
+            }
+            checkpoints.push(Checkpoint {
+                offset,
+                index,
+                prev,
+                cache,
+            });
+        }
+        let ordered = checkpoints.windows(2).all(|w| w[0].index < w[1].index);
+        if !data.is_empty() || !ordered {
+            return None;
+        }
+        Some(SeekIndex {
+            header,
+            rows,
+            checkpoints,
+        })
+    }
+
+    // the last checkpoint at or before `pixel`
+    pub fn checkpoint_for(&self, pixel: usize) -> Option<&Checkpoint> {
+        let after = self.checkpoints.partition_point(|cp| cp.index <= pixel);
+        after.checked_sub(1).map(|i| &self.checkpoints[i])
+    }
+}
+
+impl<O: Observer> Decoder<SpecCache, O> {
+    // Decodes only `rows` of the image, starting from the checkpoint of `index`
+    // nearest above them. The index has to be the one built for `data`; a
+    // different header is an `InvalidArgument`. The end marker is not checked.
+    pub fn decode_rows(
+        &mut self,
+        data: &[u8],
+        index: &SeekIndex,
+        rows: Range<usize>,
+    ) -> Result<Image<Pixel>, DecodeError> {
+        let (header, _) = self.read_header(data)?;
+        let (width, height) = (header.width as usize, header.height as usize);
+        if header != index.header || rows.start > rows.end || rows.end > height {
+            return Err(DecodeError::InvalidArgument);
+        }
+        let (start, end) = (rows.start * width, rows.end * width);
+        let mut pixels = alloc_pixels(end - start)?;
+        if start == end {
+            return Ok(Image {
+                width,
+                height: 0,
+                pixels,
+            });
+        }
+
+        let checkpoint = index
+            .checkpoint_for(start)
+            .ok_or(DecodeError::InvalidArgument)?;
+        let ops = data
+            .get(checkpoint.offset..)
+            .ok_or(DecodeError::InvalidArgument)?;
+        self.cache = SpecCache::from_slots(checkpoint.cache);
+        self.prev = checkpoint.prev;
+        self.resume_ops(ops, checkpoint.index, width * height, |i, pixel, count| {
+            let from = i.max(start);
+            let to = (i + count).min(end);
+            if from < to {
+                pixels.extend(std::iter::repeat_n(pixel, to - from));
+            }
+            if i + count >= end {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })?;
+
+        if pixels.len() != end - start {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        Ok(Image {
+            width,
+            height: rows.len(),
+            pixels,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use super::*;
+    use crate::Encoder;
+
+    #[test]
+    fn decode_rows() {
+        // runs that cross row boundaries, and rows of distinct pixels
+        let image = Image::from_fn(50, 40, |x, y| match y % 3 {
+            0 => Pixel::new(9, 9, 9, 255),
+            _ => Pixel::new(x as u8, y as u8 * 5, (x * y) as u8, 255),
+        });
+        let data = Encoder::new(50, 40).encode(&image.pixels).unwrap();
+        let index = SeekIndex::build(&data, 4).unwrap();
+        assert_eq!(index.checkpoints.len(), 10);
+        assert!(
+            index
+                .checkpoints
+                .windows(2)
+                .all(|w| w[0].index < w[1].index)
+        );
+
+        let mut decoder = Decoder::new();
+        for rows in [0..40, 0..1, 7..12, 12..13, 39..40, 20..20] {
+            let decoded = decoder.decode_rows(&data, &index, rows.clone()).unwrap();
+            assert_eq!((decoded.width, decoded.height), (50, rows.len()));
+            assert!(decoded.pixels == image.pixels[rows.start * 50..rows.end * 50]);
+        }
+        assert_eq!(
+            decoder.decode_rows(&data, &index, 30..41).err(),
+            Some(DecodeError::InvalidArgument)
+        );
+        let other = Encoder::new(40, 50).encode(&image.pixels).unwrap();
+        assert_eq!(
+            decoder.decode_rows(&other, &index, 0..1).err(),
+            Some(DecodeError::InvalidArgument)
+        );
+
+        assert!(SeekIndex::build(&data[..data.len() - 1], 4).is_err());
+
+        let bytes = index.to_bytes();
+        assert_eq!(SeekIndex::from_bytes(&bytes), Some(index));
+        assert!(SeekIndex::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+        assert!(SeekIndex::from_bytes(&data).is_none());
+    }
+}
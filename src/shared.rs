@@ -0,0 +1,101 @@
+//! Images whose pixels several owners can hold at once, e.g. a decode thread,
+//! a renderer and a cache, without copying them.
+//!
+//! `Image::share` moves the pixels behind an `Arc` for free; cloning the
+//! result only bumps a count. `SharedImage::into_image` gets a plain `Image`
+//! back, copying only if someone else still holds the pixels.
+
+use std::ops::Index;
+use std::sync::Arc;
+
+use crate::Image;
+
+pub struct SharedImage<T> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Arc<Vec<T>>,
+}
+
+// not derived, which would require `T: Clone`
+impl<T> Clone for SharedImage<T> {
+    fn clone(&self) -> Self {
+        Self {
+            width: self.width,
+            height: self.height,
+            pixels: Arc::clone(&self.pixels),
+        }
+    }
+}
+
+impl<T> Image<T> {
+    pub fn share(self) -> SharedImage<T> {
+        SharedImage {
+            width: self.width,
+            height: self.height,
+            pixels: Arc::new(self.pixels),
+        }
+    }
+}
+
+impl<T> From<Image<T>> for SharedImage<T> {
+    fn from(image: Image<T>) -> Self {
+        image.share()
+    }
+}
+
+impl<T> SharedImage<T> {
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        (x < self.width && y < self.height)
+            .then(|| self.pixels.get(y * self.width + x))
+            .flatten()
+    }
+
+    // whether both hold the same pixels, not just equal ones
+    pub fn ptr_eq(&self, other: &SharedImage<T>) -> bool {
+        Arc::ptr_eq(&self.pixels, &other.pixels)
+    }
+}
+
+impl<T: Clone> SharedImage<T> {
+    pub fn into_image(self) -> Image<T> {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: Arc::unwrap_or_clone(self.pixels),
+        }
+    }
+}
+
+impl<T> Index<(usize, usize)> for SharedImage<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        let (width, height) = (self.width, self.height);
+        self.get(x, y)
+            .unwrap_or_else(|| panic!("pixel ({x}, {y}) is outside the {width}x{height} image"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pixel;
+
+    #[test]
+    fn shares_pixels() {
+        let image = Image::from_fn(3, 2, |x, y| Pixel::new(x as u8, y as u8, 0, 255));
+        let address = image.pixels.as_ptr();
+        let shared = image.share();
+        let other = shared.clone();
+        assert!(shared.ptr_eq(&other));
+        assert_eq!(other[(2, 1)], Pixel::new(2, 1, 0, 255));
+        assert_eq!(other.get(3, 0), None);
+
+        // a copy while the pixels are shared, the same buffer once they are not
+        let copy = other.into_image();
+        assert_ne!(copy.pixels.as_ptr(), address);
+        let back = shared.into_image();
+        assert_eq!(back.pixels.as_ptr(), address);
+        assert!(back.pixels == copy.pixels);
+    }
+}
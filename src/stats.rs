@@ -0,0 +1,106 @@
+//! Summary numbers for an image's pixels: per-channel ranges and entropy,
+//! color counts and coverage, computed in one pass.
+
+use std::collections::HashSet;
+
+use crate::{Image, Pixel};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChannelStats {
+    pub min: u8,
+    pub max: u8,
+    pub mean: f64,
+    // of the channel's values on their own, in bits, 0 to 8
+    pub entropy: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImageStats {
+    // red, green, blue and alpha
+    pub channels: [ChannelStats; 4],
+    pub unique_colors: usize,
+    // fractions of the pixels with an alpha of 0 and of 255
+    pub transparent: f64,
+    pub opaque: f64,
+}
+
+impl ImageStats {
+    // Bits per pixel if the channels were independent: the sum of their
+    // entropies, an upper bound on what a context-free coder could reach.
+    pub fn entropy(&self) -> f64 {
+        self.channels.iter().map(|channel| channel.entropy).sum()
+    }
+}
+
+impl Image<Pixel> {
+    pub fn stats(&self) -> ImageStats {
+        let mut histograms = [[0usize; 256]; 4];
+        let mut colors = HashSet::new();
+        for &Pixel { r, g, b, a } in &self.pixels {
+            for (histogram, value) in histograms.iter_mut().zip([r, g, b, a]) {
+                histogram[value as usize] += 1;
+            }
+            colors.insert(u32::from_be_bytes([r, g, b, a]));
+        }
+
+        let total = self.pixels.len();
+        if total == 0 {
+            return ImageStats::default();
+        }
+        let channels = histograms.map(|histogram| {
+            let used = || (0..256).filter(|&value| histogram[value] > 0);
+            let sum = (0..256)
+                .map(|value| value * histogram[value])
+                .sum::<usize>();
+            let entropy = used()
+                .map(|value| {
+                    let p = histogram[value] as f64 / total as f64;
+                    p * (1.0 / p).log2()
+                })
+                .sum::<f64>();
+            ChannelStats {
+                min: used().next().unwrap_or(0) as u8,
+                max: used().next_back().unwrap_or(0) as u8,
+                mean: sum as f64 / total as f64,
+                entropy,
+            }
+        });
+        ImageStats {
+            channels,
+            unique_colors: colors.len(),
+            transparent: histograms[3][0] as f64 / total as f64,
+            opaque: histograms[3][255] as f64 / total as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let image = Image::from_fn(4, 4, |x, y| {
+            let a = [0, 255, 255, 100][y];
+            Pixel::new(x as u8 * 10, 50 + y as u8, (x == y) as u8 * 255, a)
+        });
+        let stats = image.stats();
+        let [r, g, b, a] = stats.channels;
+        assert_eq!((r.min, r.max, r.mean), (0, 30, 15.0));
+        assert_eq!(r.entropy, 2.0);
+        assert_eq!((g.min, g.max, g.entropy), (50, 53, 2.0));
+        assert_eq!((b.min, b.max), (0, 255));
+        assert_eq!((a.min, a.max), (0, 255));
+        assert_eq!(stats.unique_colors, 16);
+        assert_eq!((stats.transparent, stats.opaque), (0.25, 0.5));
+        assert_eq!(stats.entropy(), 4.0 + b.entropy + a.entropy);
+
+        let flat = Image::from_fn(3, 2, |_, _| Pixel::new(1, 2, 3, 255));
+        assert_eq!(flat.stats().unique_colors, 1);
+        assert_eq!(flat.stats().entropy(), 0.0);
+        assert_eq!(
+            Image::from_fn(0, 0, |_, _| flat[(0, 0)]).stats(),
+            ImageStats::default()
+        );
+    }
+}
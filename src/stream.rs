@@ -0,0 +1,486 @@
+//! Streaming encode to a `Write` with bounded memory.
+//!
+//! `StreamEncoder` takes the pixels of an image a piece at a time, e.g. one
+//! scanline at a time as they are rendered or read, and hands the encoded bytes
+//! to the writer whenever its buffer fills up. Nothing but the buffer grows with
+//! the image, so images far larger than memory can be written out. A slow writer
+//! simply blocks in `write_all`, which holds the encoder back with it.
+//!
+//! A long encode can be stopped and carried on later, in another process if
+//! need be: `StreamEncoder::checkpoint` writes out everything so far and
+//! returns an `EncoderState`, which `StreamEncoder::resume` picks up from once
+//! the output is back to the length the state records.
+
+use std::io::{self, Write};
+
+use crate::{CachePolicy, END_MARKER, EncodeError, EncodeStats, Encoder, Pixel, QoiOp, SpecCache};
+
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+// room for the header, and for a run plus the 8 bytes `append_for_pixel` writes
+const MIN_BUFFER_SIZE: usize = 16;
+
+// a RUN op of the maximum length, 62
+const FULL_RUN: u8 = 0b11_111101;
+
+pub struct StreamEncoder<W: Write, C: CachePolicy = SpecCache> {
+    writer: W,
+    encoder: Encoder<C>,
+    buf: Vec<u8>,
+    buffer_size: usize,
+    started: bool,
+    remaining: u64,
+    run_length: u8,
+    // bytes handed to the writer
+    written: u64,
+}
+
+// Where a `StreamEncoder` was at a checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncoderState {
+    pub width: u32,
+    pub height: u32,
+    // pixels taken so far
+    pub pixels: u64,
+    // bytes written so far, the header included
+    pub bytes: u64,
+    pub prev: Pixel,
+    pub cache: [Pixel; 64],
+    // repeats of `prev` not yet written as a RUN op
+    pub run_length: u8,
+}
+
+const STATE_MAGIC: &[u8; 4] = b"qois";
+const STATE_LEN: usize = 4 + 4 + 4 + 8 + 8 + 4 + 256 + 1;
+
+impl EncoderState {
+    // big-endian fields in declaration order after the magic `qois`, the cache
+    // as 64 RGBA pixels
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(STATE_LEN);
+        buf.extend(STATE_MAGIC);
+        buf.extend(self.width.to_be_bytes());
+        buf.extend(self.height.to_be_bytes());
+        buf.extend(self.pixels.to_be_bytes());
+        buf.extend(self.bytes.to_be_bytes());
+        buf.extend(self.prev.to_bytes());
+        for pixel in &self.cache {
+            buf.extend(pixel.to_bytes());
+        }
+        buf.push(self.run_length);
+        buf
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Option<EncoderState> {
+        if data.len() != STATE_LEN {
+            return None;
+        }
+        let data = data.strip_prefix(STATE_MAGIC)?;
+        let (width, data) = data.split_first_chunk()?;
+        let (height, data) = data.split_first_chunk()?;
+        let (pixels, data) = data.split_first_chunk()?;
+        let (bytes, data) = data.split_first_chunk()?;
+        let (prev, data) = data.split_at(4);
+        let (cache, data) = data.split_at(256);
+        let pixel_at = |bytes: &[u8]| Pixel::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+        Some(EncoderState {
+            width: u32::from_be_bytes(*width),
+            height: u32::from_be_bytes(*height),
+            pixels: u64::from_be_bytes(*pixels),
+            bytes: u64::from_be_bytes(*bytes),
+            prev: pixel_at(prev),
+            cache: std::array::from_fn(|slot| pixel_at(&cache[slot * 4..])),
+            run_length: data[0],
+        })
+    }
+}
+
+impl<W: Write, C: CachePolicy> StreamEncoder<W, C> {
+    // The encoder's dimensions, limits and pixel options apply as they would to
+    // `Encoder::encode`.
+    pub fn new(writer: W, encoder: Encoder<C>) -> Self {
+        Self {
+            writer,
+            remaining: encoder.width as u64 * encoder.height as u64,
+            encoder,
+            buf: Vec::new(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            started: false,
+            run_length: 0,
+            written: 0,
+        }
+    }
+
+    // The most encoded bytes held before they are written out, at least 16.
+    // Only takes effect before the first pixels are written.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = bytes.max(MIN_BUFFER_SIZE);
+        self
+    }
+
+    pub fn stats(&self) -> &EncodeStats {
+        &self.encoder.stats
+    }
+
+    // Pixels left until the image is complete.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    pub fn write_pixels(&mut self, pixels: &[Pixel]) -> io::Result<()> {
+        self.start()?;
+        self.take(pixels.len() as u64)?;
+
+        for &pixel in pixels {
+            let (original, pixel) = self.encoder.stored(pixel);
+            self.encoder.stats.record_error(original, pixel, 1);
+            if pixel == self.encoder.prev {
+                self.run_length += 1;
+                if self.run_length == 62 {
+                    self.reserve(1)?;
+                    self.end_run();
+                }
+                continue;
+            }
+
+            self.reserve(1 + 8)?;
+            self.end_run();
+            let encoder = &mut self.encoder;
+            QoiOp::append_for_pixel(
+                &mut encoder.cache,
+                encoder.prev,
+                pixel,
+                &mut self.buf,
+                &mut encoder.stats,
+            );
+            encoder.prev = pixel;
+        }
+        Ok(())
+    }
+
+    // Writes `count` copies of `pixel` without going through them one by one,
+    // e.g. for solid backgrounds.
+    pub fn write_repeated(&mut self, pixel: Pixel, count: u64) -> io::Result<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        self.start()?;
+        let (original, stored) = self.encoder.stored(pixel);
+        if stored != self.encoder.prev {
+            self.write_pixels(&[pixel])?;
+            return self.write_repeated(pixel, count - 1);
+        }
+        self.take(count)?;
+        self.encoder
+            .stats
+            .record_error(original, stored, count as usize);
+
+        let total = self.run_length as u64 + count;
+        let mut full_runs = total / 62;
+        self.run_length = (total % 62) as u8;
+        self.encoder.stats.run += full_runs as usize;
+        self.encoder.stats.run_pixels += (full_runs * 62) as usize;
+        while full_runs > 0 {
+            self.reserve(1)?;
+            let n = (self.buffer_size - self.buf.len()).min(full_runs as usize);
+            self.buf.resize(self.buf.len() + n, FULL_RUN);
+            full_runs -= n as u64;
+        }
+        Ok(())
+    }
+
+    // Writes the end of the image and flushes the writer. Fails if fewer pixels
+    // than the dimensions call for were written.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.start()?;
+        if self.remaining != 0 {
+            return Err(invalid_input(EncodeError::PixelCountMismatch));
+        }
+        self.reserve(1 + END_MARKER.len())?;
+        self.end_run();
+        self.buf.extend_from_slice(&END_MARKER);
+        self.flush_buf()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    // checks the dimensions and buffers the header on first use
+    fn start(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        let encoder = &mut self.encoder;
+        let (width, height) = (encoder.width, encoder.height);
+        if width == 0 || height == 0 {
+            return Err(invalid_input(EncodeError::ZeroDimensions));
+        }
+        if !encoder.limits.allows(width, height) {
+            return Err(invalid_input(EncodeError::LimitsExceeded));
+        }
+
+        self.started = true;
+        self.buf = Vec::with_capacity(self.buffer_size);
+        encoder.append_header(&mut self.buf);
+        encoder.cache = C::default();
+        encoder.prev = Pixel::new(0, 0, 0, 255);
+        encoder.stats = EncodeStats::default();
+        Ok(())
+    }
+
+    fn take(&mut self, count: u64) -> io::Result<()> {
+        if count > self.remaining {
+            return Err(invalid_input(EncodeError::PixelCountMismatch));
+        }
+        self.remaining -= count;
+        self.encoder.stats.pixels += count as usize;
+        Ok(())
+    }
+
+    fn end_run(&mut self) {
+        if self.run_length > 0 {
+            let op = QoiOp::Run {
+                len: self.run_length,
+            };
+            self.encoder.stats.append(op, &mut self.buf);
+            self.run_length = 0;
+        }
+    }
+
+    // makes room for `len` more bytes without growing the buffer
+    fn reserve(&mut self, len: usize) -> io::Result<()> {
+        if self.buf.len() + len > self.buffer_size {
+            self.flush_buf()?;
+        }
+        Ok(())
+    }
+
+    fn flush_buf(&mut self) -> io::Result<()> {
+        self.writer.write_all(&self.buf)?;
+        self.written += self.buf.len() as u64;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> StreamEncoder<W> {
+    // Writes out and flushes everything encoded so far, and returns what it
+    // takes to carry on from here.
+    pub fn checkpoint(&mut self) -> io::Result<EncoderState> {
+        self.start()?;
+        self.flush_buf()?;
+        self.writer.flush()?;
+        let encoder = &self.encoder;
+        Ok(EncoderState {
+            width: encoder.width,
+            height: encoder.height,
+            pixels: encoder.width as u64 * encoder.height as u64 - self.remaining,
+            bytes: self.written,
+            prev: encoder.prev,
+            cache: encoder.cache.slots(),
+            run_length: self.run_length,
+        })
+    }
+
+    // Carries on from `state` with `writer` holding the first `state.bytes`
+    // bytes of the output, e.g. the file truncated to that length and opened to
+    // append. `encoder` needs the same dimensions and pixel options as the one
+    // the state came from. Stats only count what is encoded after resuming.
+    pub fn resume(writer: W, encoder: Encoder, state: &EncoderState) -> io::Result<Self> {
+        let total = encoder.width as u64 * encoder.height as u64;
+        if (encoder.width, encoder.height) != (state.width, state.height)
+            || state.pixels > total
+            // a full run is always written out, so 62 never stays pending
+            || state.run_length >= 62
+        {
+            return Err(invalid_input(EncodeError::PixelCountMismatch));
+        }
+        let mut stream = Self::new(writer, encoder);
+        stream.start()?;
+        stream.buf.clear();
+        stream.encoder.prev = state.prev;
+        stream.encoder.cache = SpecCache::from_slots(state.cache);
+        stream.remaining = total - state.pixels;
+        stream.run_length = state.run_length;
+        stream.written = state.bytes;
+        Ok(stream)
+    }
+}
+
+fn invalid_input(err: EncodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use super::*;
+    use crate::{Decoder, Image, Limits};
+
+    // Accepts at most 4 KiB per call and only keeps the length and the last bytes
+    // of what it was given, along with the largest write it saw.
+    #[derive(Default)]
+    struct Sink {
+        len: u64,
+        largest_write: usize,
+        tail: Vec<u8>,
+    }
+
+    impl Write for Sink {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.largest_write = self.largest_write.max(data.len());
+            let data = &data[..data.len().min(4096)];
+            self.len += data.len() as u64;
+            self.tail.extend_from_slice(data);
+            let excess = self.tail.len().saturating_sub(8);
+            self.tail.drain(..excess);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn noise(width: usize, height: usize) -> Image<Pixel> {
+        let mut state = 1u32;
+        Image::from_fn(width, height, |x, _| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            let v = (state >> 24) as u8;
+            match x % 7 {
+                0..3 => Pixel::new(v, v, v, 255),
+                3 => Pixel::new(v, v / 2, 0, v),
+                _ => Pixel::new(10, 20, 30, 255),
+            }
+        })
+    }
+
+    #[test]
+    fn matches_encoder() {
+        let image = noise(300, 200);
+        let expected = Encoder::new(300, 200).encode(&image.pixels).unwrap();
+
+        for buffer_size in [0, 100, DEFAULT_BUFFER_SIZE] {
+            let mut stream =
+                StreamEncoder::new(Vec::new(), Encoder::new(300, 200)).buffer_size(buffer_size);
+            for row in image.pixels.chunks(300) {
+                stream.write_pixels(&row[..123]).unwrap();
+                stream.write_pixels(&row[123..]).unwrap();
+            }
+            assert!(stream.buf.capacity() <= buffer_size.max(MIN_BUFFER_SIZE));
+            assert_eq!(stream.finish().unwrap(), expected);
+        }
+
+        // runs written in bulk give the same stream as pixel by pixel
+        let pixels: Vec<_> = [
+            (Pixel::new(0, 0, 0, 255), 70),
+            (Pixel::new(1, 2, 3, 4), 200),
+        ]
+        .into_iter()
+        .flat_map(|(pixel, n)| std::iter::repeat_n(pixel, n))
+        .collect();
+        let mut stream = StreamEncoder::new(Vec::new(), Encoder::new(27, 10)).buffer_size(20);
+        stream.write_pixels(&pixels[..5]).unwrap();
+        stream.write_repeated(Pixel::new(0, 0, 0, 255), 65).unwrap();
+        stream.write_repeated(Pixel::new(1, 2, 3, 4), 200).unwrap();
+        let data = stream.finish().unwrap();
+        assert_eq!(data, Encoder::new(27, 10).encode(&pixels).unwrap());
+        assert!(Decoder::new().decode(&data).unwrap().pixels == pixels);
+
+        // and so does a tolerance, including on runs of near pixels written in bulk
+        let mut pixels = image.pixels[..59_000].to_vec();
+        pixels.extend([Pixel::new(14, 16, 30, 255); 500]);
+        pixels.extend([Pixel::new(12, 20, 30, 255); 500]);
+        let mut encoder = Encoder::new(300, 200).near_lossless(8);
+        let expected = encoder.encode(&pixels).unwrap();
+        assert!(expected.len() < Encoder::new(300, 200).encode(&pixels).unwrap().len());
+        let lossy = Encoder::new(300, 200).near_lossless(8);
+        let mut stream = StreamEncoder::new(Vec::new(), lossy).buffer_size(100);
+        for chunk in pixels[..59_000].chunks(123) {
+            stream.write_pixels(chunk).unwrap();
+        }
+        stream
+            .write_repeated(Pixel::new(14, 16, 30, 255), 500)
+            .unwrap();
+        stream
+            .write_repeated(Pixel::new(12, 20, 30, 255), 500)
+            .unwrap();
+        let error =
+            |stats: &EncodeStats| (stats.changed_pixels, stats.max_error, stats.squared_error);
+        assert_eq!(error(stream.stats()), error(encoder.stats()));
+        assert!(stream.stats().changed_pixels > 500);
+        assert_eq!(stream.finish().unwrap(), expected);
+    }
+
+    #[test]
+    fn checkpoint_and_resume() {
+        let image = noise(300, 200);
+        let expected = Encoder::new(300, 200).encode(&image.pixels).unwrap();
+
+        let mut stream = StreamEncoder::new(Vec::new(), Encoder::new(300, 200)).buffer_size(100);
+        // stop part way through a run of the solid color
+        stream.write_pixels(&image.pixels[..30_006]).unwrap();
+        let state = stream.checkpoint().unwrap();
+        assert_eq!(state.pixels, 30_006);
+        assert!(state.run_length > 0);
+        let state = EncoderState::from_bytes(&state.to_bytes()).unwrap();
+
+        // the rest of the first run is lost, as if the process had died
+        let mut output = stream.writer;
+        assert!(output.len() as u64 >= state.bytes);
+        output.truncate(state.bytes as usize);
+        let mut stream = StreamEncoder::resume(output, Encoder::new(300, 200), &state).unwrap();
+        assert_eq!(stream.remaining(), 60_000 - 30_006);
+        stream.write_pixels(&image.pixels[30_006..]).unwrap();
+        assert_eq!(stream.finish().unwrap(), expected);
+
+        assert!(StreamEncoder::resume(vec![], Encoder::new(200, 300), &state).is_err());
+        assert!(EncoderState::from_bytes(&state.to_bytes()[1..]).is_none());
+        let full_run = EncoderState { run_length: 62, ..state };
+        assert!(StreamEncoder::resume(vec![], Encoder::new(300, 200), &full_run).is_err());
+    }
+
+    #[test]
+    fn pixel_count() {
+        let mut stream = StreamEncoder::new(Vec::new(), Encoder::new(4, 4));
+        stream
+            .write_pixels(&[Pixel::new(1, 2, 3, 255); 10])
+            .unwrap();
+        assert_eq!(stream.remaining(), 6);
+        let err = stream
+            .write_pixels(&[Pixel::new(1, 2, 3, 255); 7])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(stream.finish().is_err());
+
+        let stream = StreamEncoder::new(Vec::new(), Encoder::new(0, 4));
+        assert!(stream.finish().is_err());
+        let mut stream = StreamEncoder::new(Vec::new(), Encoder::new(100_000, 100_000));
+        assert!(stream.write_repeated(Pixel::new(0, 0, 0, 0), 1).is_err());
+    }
+
+    #[test]
+    fn gigapixels() {
+        // 2^31 pixels: a short gradient at the start of every row, then a solid color
+        let (width, height) = (1 << 16, 1 << 15);
+        let encoder = Encoder::new(width, height).with_limits(Limits::unlimited());
+        let mut stream = StreamEncoder::new(Sink::default(), encoder);
+        for y in 0..height {
+            let row: Vec<_> = (0..16)
+                .map(|x| Pixel::new(x * 16, y as u8, 0, 255))
+                .collect();
+            stream.write_pixels(&row).unwrap();
+            stream
+                .write_repeated(Pixel::new(0, 0, 200, 255), width as u64 - 16)
+                .unwrap();
+        }
+        assert_eq!(stream.stats().pixels, 1 << 31);
+        assert!(stream.buf.capacity() <= DEFAULT_BUFFER_SIZE);
+        let sink = stream.finish().unwrap();
+
+        assert!(sink.largest_write <= DEFAULT_BUFFER_SIZE);
+        assert_eq!(sink.tail, END_MARKER);
+        // every row has 1057 runs for its 65520 repeats and at most 16 other ops
+        let height = height as u64;
+        assert!(sink.len >= 14 + 8 + 1057 * height);
+        assert!(sink.len <= 14 + 8 + (1057 + 16 * 5) * height);
+    }
+}
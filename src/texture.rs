@@ -0,0 +1,79 @@
+//! Uploading decoded images as `wgpu` textures.
+
+use crate::{DecodeError, Decoder, Image, Pixel};
+
+// Colorspace 0 is sRGB color with linear alpha, which the Srgb format samples
+// correctly; colorspace 1 is linear in every channel.
+pub fn texture_format(colorspace: u8) -> wgpu::TextureFormat {
+    match colorspace {
+        0 => wgpu::TextureFormat::Rgba8UnormSrgb,
+        _ => wgpu::TextureFormat::Rgba8Unorm,
+    }
+}
+
+pub fn create_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &Image<Pixel>,
+    colorspace: u8,
+    label: Option<&str>,
+) -> wgpu::Texture {
+    let size = wgpu::Extent3d {
+        width: image.width as u32,
+        height: image.height as u32,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: texture_format(colorspace),
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    let data = image.as_rgba_bytes();
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        &data,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * size.width),
+            rows_per_image: Some(size.height),
+        },
+        size,
+    );
+
+    texture
+}
+
+// Decodes `data` and uploads it, picking the format from the file's colorspace byte.
+pub fn load_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    data: &[u8],
+    label: Option<&str>,
+) -> Result<wgpu::Texture, DecodeError> {
+    let mut decoder = Decoder::new();
+    let image = decoder.decode(data)?;
+    let colorspace = decoder.header().map_or(0, |header| header.colorspace);
+    Ok(create_texture(device, queue, &image, colorspace, label))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_from_colorspace() {
+        assert_eq!(texture_format(0), wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(texture_format(1), wgpu::TextureFormat::Rgba8Unorm);
+    }
+}
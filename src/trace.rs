@@ -0,0 +1,146 @@
+//! Spans and events for the `tracing` feature. Without it every function here
+//! is empty and the calls compile away.
+//!
+//! Encodes and decodes each get a `qoi_encode` or `qoi_decode` span at debug
+//! level with the dimensions, closed by an event with the byte count and how
+//! long it took. Rejected inputs are reported at warn level.
+
+// with only one half of the codec built, the other half's functions go unused
+#![cfg_attr(not(all(feature = "encode", feature = "decode")), allow(dead_code))]
+
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+// an encode or decode in progress
+pub(crate) struct Operation {
+    // entered until the operation is dropped
+    #[cfg(feature = "tracing")]
+    _span: tracing::span::EnteredSpan,
+    #[cfg(feature = "tracing")]
+    start: Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl Operation {
+    fn enter(span: tracing::Span) -> Self {
+        Self {
+            _span: span.entered(),
+            start: Instant::now(),
+        }
+    }
+
+    // `bytes` is the size of the encoded data, written or read
+    pub(crate) fn finish(self, pixels: usize, bytes: usize) {
+        let micros = self.start.elapsed().as_micros() as u64;
+        tracing::debug!(pixels, bytes, micros, "done");
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl Operation {
+    pub(crate) fn finish(self, _pixels: usize, _bytes: usize) {}
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn encode(width: u32, height: u32) -> Operation {
+    Operation::enter(tracing::debug_span!("qoi_encode", width, height))
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn encode(_width: u32, _height: u32) -> Operation {
+    Operation {}
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn decode(width: u32, height: u32, input_len: usize) -> Operation {
+    Operation::enter(tracing::debug_span!("qoi_decode", width, height, input_len))
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn decode(_width: u32, _height: u32, _input_len: usize) -> Operation {
+    Operation {}
+}
+
+// an image refused before any work was done on it, e.g. for exceeding the limits
+#[cfg(feature = "tracing")]
+pub(crate) fn rejected(width: u32, height: u32, reason: &dyn std::fmt::Display) {
+    tracing::warn!(width, height, %reason, "qoi image rejected");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn rejected(_width: u32, _height: u32, _reason: &dyn std::fmt::Display) {}
+
+// decoding stopped at `offset` of the input
+#[cfg(feature = "tracing")]
+pub(crate) fn failed(offset: usize, error: &dyn std::fmt::Display) {
+    tracing::warn!(offset, %error, "qoi decode failed");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn failed(_offset: usize, _error: &dyn std::fmt::Display) {}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::{DecodeError, Decoder, Encoder, Limits, Pixel};
+
+    // records the names of spans and the messages of events
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.0.lock().unwrap().push(span.metadata().name().into());
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let level = event.metadata().level();
+            self.0.lock().unwrap().push(format!("{level}"));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn spans_and_events() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let pixels = [Pixel::new(1, 2, 3, 255); 6];
+            let data = Encoder::new(3, 2).encode(&pixels).unwrap();
+            Decoder::new().decode(&data).unwrap();
+            let limits = Limits {
+                max_pixels: 5,
+                ..Limits::default()
+            };
+            let rejected = Decoder::new().with_limits(limits).decode(&data);
+            assert_eq!(rejected.err(), Some(DecodeError::LimitsExceeded));
+            assert!(Decoder::new().decode(&data[..15]).is_err());
+        });
+        let log = recorder.0.lock().unwrap();
+        let expected = [
+            "qoi_encode",
+            "DEBUG",
+            "qoi_decode",
+            "DEBUG",
+            "WARN",
+            "qoi_decode",
+            "WARN",
+        ];
+        assert_eq!(*log, expected);
+    }
+}
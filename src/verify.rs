@@ -0,0 +1,94 @@
+//! A one-call integrity check: decode a file, encode the pixels again the way
+//! qoi.h would, and compare.
+//!
+//! Files written by this crate or by qoi.h come back byte for byte. Other
+//! encoders may choose different ops for the same pixels, which is valid QOI;
+//! those still have to round-trip their pixels.
+
+use crate::{DecodeError, Decoder, Encoder, Header};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verification {
+    pub header: Header,
+    // the file is exactly what the encoder writes for its pixels, with no
+    // trailing data
+    pub bytes_match: bool,
+    // where the file and the fresh encode first differ
+    pub first_difference: Option<usize>,
+    // the fresh encode decodes to the same pixels as the file
+    pub pixels_match: bool,
+    // bytes after the end marker
+    pub trailing_len: usize,
+}
+
+// Fails only if `data` does not decode, even leniently; trailing data and
+// channels or colorspace values outside the spec are reported as mismatches.
+pub fn verify(data: &[u8]) -> Result<Verification, DecodeError> {
+    let mut decoder = Decoder::new().lenient(true);
+    let image = decoder.decode(data)?;
+    let header = decoder.header().expect("set by a successful decode");
+    let trailing_len = decoder.trailing_len();
+
+    // a header the encoder refuses, such as 0x0, differs from the start
+    let Ok(encoded) = Encoder::with_header(header).encode(&image.pixels) else {
+        return Ok(Verification {
+            header,
+            bytes_match: false,
+            first_difference: Some(0),
+            pixels_match: false,
+            trailing_len,
+        });
+    };
+    let first_difference = data
+        .iter()
+        .zip(&encoded)
+        .position(|(a, b)| a != b)
+        .or((data.len() != encoded.len()).then(|| data.len().min(encoded.len())));
+    let pixels_match = Decoder::new()
+        .decode(&encoded)
+        .is_ok_and(|decoded| decoded.pixels == image.pixels);
+    Ok(Verification {
+        header,
+        bytes_match: first_difference.is_none(),
+        first_difference,
+        pixels_match,
+        trailing_len,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pixel;
+
+    #[test]
+    fn round_trips() {
+        let pixels = (0..40u8)
+            .map(|i| Pixel::new(i * 6, i % 3, 200, 255 - i % 2))
+            .collect::<Vec<_>>();
+        let data = Encoder::new(8, 5).encode(&pixels).unwrap();
+        let report = verify(&data).unwrap();
+        assert!(report.bytes_match && report.pixels_match);
+        assert_eq!(report.first_difference, None);
+        assert_eq!(report.header.channels, 4);
+
+        // RGBA ops where the encoder would use RGB and a run are valid, but
+        // not what it writes
+        let mut data = b"qoif\0\0\0\x02\0\0\0\x01\x04\0".to_vec();
+        data.extend([0xff, 1, 2, 3, 255, 0xff, 1, 2, 3, 255]);
+        data.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+        let report = verify(&data).unwrap();
+        assert!(!report.bytes_match && report.pixels_match);
+        assert_eq!(report.first_difference, Some(14));
+
+        let mut trailing = Encoder::new(8, 5).encode(&pixels).unwrap();
+        trailing.push(0);
+        let report = verify(&trailing).unwrap();
+        assert!(!report.bytes_match && report.pixels_match);
+        assert_eq!(report.first_difference, Some(trailing.len() - 1));
+        assert_eq!(report.trailing_len, 1);
+
+        let truncated = &trailing[..trailing.len() - 9];
+        assert!(verify(truncated).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Full-range YCbCr with the BT.709 coefficients, to look at an image as
+//! brightness and color separately.
+//!
+//! Y spans 0..=255 and both chroma channels are centred on 128, as in JFIF.
+//! Converting to 8-bit YCbCr and back can be off by one or two per channel.
+
+use crate::{Image, Pixel};
+
+const KR: f32 = 0.2126;
+const KB: f32 = 0.0722;
+const KG: f32 = 1.0 - KR - KB;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct YCbCr {
+    pub y: u8,
+    pub cb: u8,
+    pub cr: u8,
+}
+
+fn to_u8(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+impl YCbCr {
+    // Alpha is dropped.
+    pub fn from_pixel(pixel: Pixel) -> YCbCr {
+        let (r, g, b) = (pixel.r as f32, pixel.g as f32, pixel.b as f32);
+        let y = KR * r + KG * g + KB * b;
+        YCbCr {
+            y: to_u8(y),
+            cb: to_u8((b - y) / (2.0 * (1.0 - KB)) + 128.0),
+            cr: to_u8((r - y) / (2.0 * (1.0 - KR)) + 128.0),
+        }
+    }
+
+    pub fn to_pixel(self, a: u8) -> Pixel {
+        let y = self.y as f32;
+        let cb = self.cb as f32 - 128.0;
+        let cr = self.cr as f32 - 128.0;
+        let r = y + 2.0 * (1.0 - KR) * cr;
+        let b = y + 2.0 * (1.0 - KB) * cb;
+        let g = (y - KR * r - KB * b) / KG;
+        Pixel::new(to_u8(r), to_u8(g), to_u8(b), a)
+    }
+}
+
+impl Image<Pixel> {
+    pub fn to_ycbcr(&self) -> Image<YCbCr> {
+        self.map_pixels(YCbCr::from_pixel)
+    }
+}
+
+impl Image<YCbCr> {
+    // Back to RGB, opaque.
+    pub fn to_rgba(&self) -> Image<Pixel> {
+        self.map_pixels(|pixel| pixel.to_pixel(255))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let convert = |r, g, b| {
+            let YCbCr { y, cb, cr } = YCbCr::from_pixel(Pixel::new(r, g, b, 9));
+            (y, cb, cr)
+        };
+        assert_eq!(convert(0, 0, 0), (0, 128, 128));
+        assert_eq!(convert(255, 255, 255), (255, 128, 128));
+        assert_eq!(convert(255, 0, 0), (54, 99, 255));
+        assert_eq!(convert(0, 0, 255), (18, 255, 116));
+        // green weighs most in BT.709 luma
+        assert_eq!(convert(0, 255, 0).0, 182);
+
+        let image = Image::from_fn(64, 64, |x, y| {
+            Pixel::new(x as u8 * 4, y as u8 * 4, (x + y) as u8 * 2, 77)
+        });
+        let ycbcr = image.to_ycbcr();
+        let back = ycbcr.to_rgba();
+        for (a, b) in image.pixels.iter().zip(&back.pixels) {
+            assert!(a.r.abs_diff(b.r) <= 2 && a.g.abs_diff(b.g) <= 2 && a.b.abs_diff(b.b) <= 2);
+            assert_eq!(b.a, 255);
+        }
+        assert_eq!(ycbcr.map_pixels(|p| p.y)[(0, 0)], 0);
+        assert_eq!(YCbCr::from_pixel(image[(5, 7)]).to_pixel(77).a, 77);
+    }
+}